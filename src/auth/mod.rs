@@ -0,0 +1,200 @@
+#[cfg(feature = "service-account")]
+mod service_account;
+
+use crate::error::GoogleApiError;
+use async_trait::async_trait;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+#[cfg(feature = "service-account")]
+pub use service_account::*;
+
+/// OAuth 2.0 access token returned by a [`TokenProvider`].
+#[derive(Clone, Debug)]
+pub struct AccessToken {
+    token: String,
+    expires_at: Option<Instant>,
+}
+
+impl AccessToken {
+    /// A token without a known expiry.
+    pub fn new(token: &str) -> Self {
+        AccessToken {
+            token: token.to_string(),
+            expires_at: None,
+        }
+    }
+    /// A token that expires `expires_in` from now.
+    pub fn with_expires_in(token: &str, expires_in: Duration) -> Self {
+        AccessToken {
+            token: token.to_string(),
+            expires_at: Some(Instant::now() + expires_in),
+        }
+    }
+    pub fn token(&self) -> &str {
+        self.token.as_str()
+    }
+    pub fn expires_at(&self) -> Option<Instant> {
+        self.expires_at
+    }
+    /// Whether the token expires within `margin` from now. Tokens without expiry never do.
+    pub fn expires_within(&self, margin: Duration) -> bool {
+        match self.expires_at {
+            Some(expires_at) => Instant::now() + margin >= expires_at,
+            None => false,
+        }
+    }
+}
+
+/// Source of access tokens, consulted by [`IndexingClient`](crate::IndexingClient) before each request.
+#[async_trait]
+pub trait TokenProvider: Send + Sync {
+    /// Returns a token to send as `Authorization: Bearer <token>`.
+    async fn token(&self) -> Result<AccessToken, GoogleApiError>;
+
+    /// Called when the API rejected the last token with `401 Unauthorized`.
+    /// Providers that cache tokens should drop the cached one here.
+    async fn invalidate(&self) {}
+}
+
+#[async_trait]
+impl<P> TokenProvider for Arc<P>
+where
+    P: TokenProvider + ?Sized,
+{
+    async fn token(&self) -> Result<AccessToken, GoogleApiError> {
+        (**self).token().await
+    }
+    async fn invalidate(&self) {
+        (**self).invalidate().await
+    }
+}
+
+/// Always returns the same token.
+#[derive(Clone, Debug)]
+pub struct StaticTokenProvider {
+    token: String,
+}
+
+impl StaticTokenProvider {
+    pub fn new(token: &str) -> Self {
+        StaticTokenProvider {
+            token: token.to_string(),
+        }
+    }
+}
+
+#[async_trait]
+impl TokenProvider for StaticTokenProvider {
+    async fn token(&self) -> Result<AccessToken, GoogleApiError> {
+        Ok(AccessToken::new(self.token.as_str()))
+    }
+}
+
+/// Caches the token of an inner provider and fetches a new one shortly before it expires.
+///
+/// Tokens without an expiry are kept until [`TokenProvider::invalidate`] is called.
+pub struct CachedTokenProvider<P> {
+    inner: P,
+    refresh_margin: Duration,
+    cached: Mutex<Option<AccessToken>>,
+}
+
+impl<P: TokenProvider> CachedTokenProvider<P> {
+    /// Refreshes 60 seconds before expiry.
+    pub fn new(inner: P) -> Self {
+        CachedTokenProvider::with_refresh_margin(inner, Duration::from_secs(60))
+    }
+    pub fn with_refresh_margin(inner: P, refresh_margin: Duration) -> Self {
+        CachedTokenProvider {
+            inner,
+            refresh_margin,
+            cached: Mutex::new(None),
+        }
+    }
+}
+
+#[async_trait]
+impl<P: TokenProvider> TokenProvider for CachedTokenProvider<P> {
+    async fn token(&self) -> Result<AccessToken, GoogleApiError> {
+        // ロックを保持したまま取得するので、同時に期限切れを検知しても取得は1回で済む
+        let mut cached = self.cached.lock().await;
+        if let Some(token) = cached.as_ref() {
+            if !token.expires_within(self.refresh_margin) {
+                return Ok(token.clone());
+            }
+        }
+        let token = self.inner.token().await?;
+        *cached = Some(token.clone());
+        Ok(token)
+    }
+
+    async fn invalidate(&self) {
+        *self.cached.lock().await = None;
+        self.inner.invalidate().await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    struct CountingProvider {
+        calls: AtomicUsize,
+        expires_in: Option<Duration>,
+    }
+
+    #[async_trait]
+    impl TokenProvider for CountingProvider {
+        async fn token(&self) -> Result<AccessToken, GoogleApiError> {
+            let n = self.calls.fetch_add(1, Ordering::SeqCst) + 1;
+            let token = format!("token-{}", n);
+            Ok(match self.expires_in {
+                Some(expires_in) => AccessToken::with_expires_in(token.as_str(), expires_in),
+                None => AccessToken::new(token.as_str()),
+            })
+        }
+    }
+
+    fn counting(expires_in: Option<Duration>) -> CountingProvider {
+        CountingProvider {
+            calls: AtomicUsize::new(0),
+            expires_in,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_cached_provider_reuses_valid_token() {
+        let provider = CachedTokenProvider::new(counting(Some(Duration::from_secs(3600))));
+        assert_eq!(provider.token().await.unwrap().token(), "token-1");
+        assert_eq!(provider.token().await.unwrap().token(), "token-1");
+        assert_eq!(provider.inner.calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_cached_provider_refreshes_before_expiry() {
+        // 有効期限 30 秒は既定のマージン 60 秒より短いので毎回取り直す
+        let provider = CachedTokenProvider::new(counting(Some(Duration::from_secs(30))));
+        assert_eq!(provider.token().await.unwrap().token(), "token-1");
+        assert_eq!(provider.token().await.unwrap().token(), "token-2");
+    }
+
+    #[tokio::test]
+    async fn test_cached_provider_invalidate() {
+        let provider = CachedTokenProvider::new(counting(None));
+        assert_eq!(provider.token().await.unwrap().token(), "token-1");
+        assert_eq!(provider.token().await.unwrap().token(), "token-1");
+        provider.invalidate().await;
+        assert_eq!(provider.token().await.unwrap().token(), "token-2");
+    }
+
+    #[test]
+    fn test_access_token_expires_within() {
+        let token = AccessToken::with_expires_in("t", Duration::from_secs(120));
+        assert!(!token.expires_within(Duration::from_secs(60)));
+        assert!(token.expires_within(Duration::from_secs(180)));
+        assert!(!AccessToken::new("t").expires_within(Duration::from_secs(3600)));
+    }
+}
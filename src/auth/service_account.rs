@@ -0,0 +1,364 @@
+use crate::auth::{AccessToken, TokenProvider};
+use crate::error::GoogleApiError;
+use async_trait::async_trait;
+use jsonwebtoken::{Algorithm, EncodingKey, Header};
+use serde::{Deserialize, Serialize};
+use std::fmt::{Debug, Formatter};
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use urlencoding::encode;
+
+/// Default OAuth 2.0 token endpoint, used when the key has no `token_uri`.
+pub const DEFAULT_TOKEN_ENDPOINT: &str = "https://oauth2.googleapis.com/token";
+
+/// OAuth 2.0 scope of the Indexing API.
+pub const INDEXING_SCOPE: &str = "https://www.googleapis.com/auth/indexing";
+
+// Google が受け付ける JWT の有効期限の上限は 1 時間
+const ASSERTION_LIFETIME: Duration = Duration::from_secs(3600);
+
+/// Google service account key (the JSON file downloaded from the Cloud Console).
+#[derive(Clone, Deserialize)]
+pub struct ServiceAccountKey {
+    pub client_email: String,
+    pub private_key: String,
+    #[serde(default)]
+    pub private_key_id: Option<String>,
+    #[serde(default)]
+    pub project_id: Option<String>,
+    #[serde(default)]
+    pub token_uri: Option<String>,
+}
+
+impl ServiceAccountKey {
+    pub fn from_json(json: &str) -> Result<Self, GoogleApiError> {
+        serde_json::from_str(json).map_err(|e| {
+            GoogleApiError::InvalidArgument(format!("invalid service account key: {}", e))
+        })
+    }
+
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, GoogleApiError> {
+        let path = path.as_ref();
+        let json = std::fs::read_to_string(path).map_err(|e| {
+            GoogleApiError::InvalidArgument(format!(
+                "cannot read service account key {}: {}",
+                path.display(),
+                e
+            ))
+        })?;
+        ServiceAccountKey::from_json(json.as_str())
+    }
+}
+
+// 秘密鍵をログに出さない
+impl Debug for ServiceAccountKey {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ServiceAccountKey")
+            .field("client_email", &self.client_email)
+            .field("private_key_id", &self.private_key_id)
+            .field("project_id", &self.project_id)
+            .field("token_uri", &self.token_uri)
+            .finish_non_exhaustive()
+    }
+}
+
+#[derive(Serialize)]
+struct Claims<'a> {
+    iss: &'a str,
+    scope: String,
+    aud: &'a str,
+    iat: u64,
+    exp: u64,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    #[serde(default)]
+    expires_in: Option<u64>,
+}
+
+/// Gets access tokens with a service account key (OAuth 2.0 JWT bearer flow).
+///
+/// Every call to [`TokenProvider::token`] signs a new RS256 assertion and exchanges it
+/// at the token endpoint, so wrap it in a [`CachedTokenProvider`](crate::CachedTokenProvider).
+/// ```rust,no_run
+/// use google_indexing_api::{CachedTokenProvider, IndexingClient, ServiceAccountTokenProvider};
+/// let provider = ServiceAccountTokenProvider::from_file("service-account-key.json").unwrap();
+/// let client = IndexingClient::builder()
+///     .token_provider(CachedTokenProvider::new(provider))
+///     .build()
+///     .unwrap();
+/// ```
+pub struct ServiceAccountTokenProvider {
+    key: ServiceAccountKey,
+    encoding_key: EncodingKey,
+    token_endpoint: String,
+    scopes: Vec<String>,
+    http: reqwest::Client,
+}
+
+impl ServiceAccountTokenProvider {
+    /// Uses the `indexing` scope and the key's `token_uri`.
+    pub fn new(key: ServiceAccountKey) -> Result<Self, GoogleApiError> {
+        let encoding_key = EncodingKey::from_rsa_pem(key.private_key.as_bytes()).map_err(|e| {
+            GoogleApiError::InvalidArgument(format!(
+                "invalid private key in service account key: {}",
+                e
+            ))
+        })?;
+        let token_endpoint = key
+            .token_uri
+            .clone()
+            .unwrap_or_else(|| DEFAULT_TOKEN_ENDPOINT.to_string());
+        Ok(ServiceAccountTokenProvider {
+            key,
+            encoding_key,
+            token_endpoint,
+            scopes: vec![INDEXING_SCOPE.to_string()],
+            http: reqwest::Client::new(),
+        })
+    }
+
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, GoogleApiError> {
+        ServiceAccountTokenProvider::new(ServiceAccountKey::from_file(path)?)
+    }
+
+    /// Token endpoint to exchange the assertion at. It is also the JWT audience.
+    pub fn token_endpoint(mut self, token_endpoint: &str) -> Self {
+        self.token_endpoint = token_endpoint.to_string();
+        self
+    }
+    /// Scopes to request (default: the `indexing` scope).
+    pub fn scopes(mut self, scopes: &[&str]) -> Self {
+        self.scopes = scopes.iter().map(|q| q.to_string()).collect();
+        self
+    }
+    /// HTTP client used to call the token endpoint.
+    pub fn http_client(mut self, client: reqwest::Client) -> Self {
+        self.http = client;
+        self
+    }
+
+    pub fn key(&self) -> &ServiceAccountKey {
+        &self.key
+    }
+
+    fn assertion(&self) -> Result<String, GoogleApiError> {
+        let iat = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let claims = Claims {
+            iss: self.key.client_email.as_str(),
+            scope: self.scopes.join(" "),
+            aud: self.token_endpoint.as_str(),
+            iat,
+            exp: iat + ASSERTION_LIFETIME.as_secs(),
+        };
+        let mut header = Header::new(Algorithm::RS256);
+        header.kid = self.key.private_key_id.clone();
+        jsonwebtoken::encode(&header, &claims, &self.encoding_key).map_err(|e| {
+            GoogleApiError::InvalidArgument(format!("cannot sign the JWT assertion: {}", e))
+        })
+    }
+}
+
+impl Debug for ServiceAccountTokenProvider {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ServiceAccountTokenProvider")
+            .field("key", &self.key)
+            .field("token_endpoint", &self.token_endpoint)
+            .field("scopes", &self.scopes)
+            .finish_non_exhaustive()
+    }
+}
+
+#[async_trait]
+impl TokenProvider for ServiceAccountTokenProvider {
+    async fn token(&self) -> Result<AccessToken, GoogleApiError> {
+        let body = format!(
+            "grant_type={}&assertion={}",
+            encode("urn:ietf:params:oauth:grant-type:jwt-bearer"),
+            encode(self.assertion()?.as_str())
+        );
+        let response = self
+            .http
+            .post(self.token_endpoint.as_str())
+            .header("Content-Type", "application/x-www-form-urlencoded")
+            .header("Accept", "application/json")
+            .body(body)
+            .send()
+            .await;
+        if response.is_err() {
+            return Err(GoogleApiError::from_reqwest(response.err().unwrap()));
+        }
+        let response = response.unwrap();
+        let status = response.status();
+        let value = response.text().await;
+        if !status.is_success() {
+            return Err(GoogleApiError::http_status(
+                status.as_u16(),
+                value.unwrap_or_default(),
+            ));
+        }
+        if value.is_err() {
+            return Err(GoogleApiError::BodyRead(value.err().unwrap()));
+        }
+        let value = value.unwrap();
+        let parse = serde_json::from_str::<TokenResponse>(value.as_str());
+        if parse.is_err() {
+            return Err(GoogleApiError::json_parse(value, parse.err().unwrap()));
+        }
+        let parse = parse.unwrap();
+        Ok(match parse.expires_in {
+            Some(expires_in) => AccessToken::with_expires_in(
+                parse.access_token.as_str(),
+                Duration::from_secs(expires_in),
+            ),
+            None => AccessToken::new(parse.access_token.as_str()),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aws_lc_rs::encoding::{AsDer, Pkcs8V1Der, PublicKeyX509Der};
+    use aws_lc_rs::rsa::{KeyPair, KeySize};
+    use aws_lc_rs::signature::KeyPair as _;
+    use httpmock::prelude::*;
+    use jsonwebtoken::{DecodingKey, Validation};
+    use pem::Pem;
+    use std::sync::LazyLock;
+
+    // テストの実行時に生成する使い捨ての鍵。(秘密鍵を含む鍵ファイル, 公開鍵の PEM)
+    static TEST_KEY: LazyLock<(String, String)> = LazyLock::new(|| {
+        let pair = KeyPair::generate(KeySize::Rsa2048).unwrap();
+        let private_key = AsDer::<Pkcs8V1Der>::as_der(&pair).unwrap();
+        let public_key = AsDer::<PublicKeyX509Der>::as_der(pair.public_key()).unwrap();
+        let key_json = serde_json::json!({
+            "type": "service_account",
+            "project_id": "test-project",
+            "private_key_id": "test-only",
+            "private_key": pem::encode(&Pem::new("PRIVATE KEY", private_key.as_ref())),
+            "client_email": "indexer@test-project.iam.gserviceaccount.com",
+            "client_id": "000000000000000000000",
+            "token_uri": DEFAULT_TOKEN_ENDPOINT,
+        });
+        (
+            key_json.to_string(),
+            pem::encode(&Pem::new("PUBLIC KEY", public_key.as_ref())),
+        )
+    });
+
+    fn key_json() -> &'static str {
+        TEST_KEY.0.as_str()
+    }
+
+    #[derive(Deserialize)]
+    struct DecodedClaims {
+        iss: String,
+        scope: String,
+        aud: String,
+        iat: u64,
+        exp: u64,
+    }
+
+    fn provider_for(server: &MockServer) -> ServiceAccountTokenProvider {
+        ServiceAccountTokenProvider::new(ServiceAccountKey::from_json(key_json()).unwrap())
+            .unwrap()
+            .token_endpoint(server.url("/token").as_str())
+    }
+
+    #[test]
+    fn test_assertion_is_signed_rs256_jwt() {
+        let provider =
+            ServiceAccountTokenProvider::new(ServiceAccountKey::from_json(key_json()).unwrap())
+                .unwrap();
+        let assertion = provider.assertion().unwrap();
+
+        let header = jsonwebtoken::decode_header(assertion.as_str()).unwrap();
+        assert_eq!(header.alg, Algorithm::RS256);
+        assert_eq!(header.kid.as_deref(), Some("test-only"));
+
+        let mut validation = Validation::new(Algorithm::RS256);
+        validation.set_audience(&[DEFAULT_TOKEN_ENDPOINT]);
+        let claims = jsonwebtoken::decode::<DecodedClaims>(
+            assertion.as_str(),
+            &DecodingKey::from_rsa_pem(TEST_KEY.1.as_bytes()).unwrap(),
+            &validation,
+        )
+        .unwrap()
+        .claims;
+        assert_eq!(claims.iss, "indexer@test-project.iam.gserviceaccount.com");
+        assert_eq!(claims.scope, INDEXING_SCOPE);
+        assert_eq!(claims.aud, DEFAULT_TOKEN_ENDPOINT);
+        assert_eq!(claims.exp - claims.iat, 3600);
+    }
+
+    #[tokio::test]
+    async fn test_token_exchanges_assertion() {
+        let server = MockServer::start_async().await;
+        let mock = server
+            .mock_async(|when, then| {
+                when.method(POST)
+                    .path("/token")
+                    .header("Content-Type", "application/x-www-form-urlencoded")
+                    .body_contains(
+                        "grant_type=urn%3Aietf%3Aparams%3Aoauth%3Agrant-type%3Ajwt-bearer",
+                    )
+                    .body_contains("&assertion=ey");
+                then.status(200).body(
+                    r#"{"access_token":"ya29.test","expires_in":3599,"token_type":"Bearer"}"#,
+                );
+            })
+            .await;
+
+        let token = provider_for(&server).token().await.unwrap();
+        mock.assert_async().await;
+        assert_eq!(token.token(), "ya29.test");
+        assert!(!token.expires_within(Duration::from_secs(3000)));
+        assert!(token.expires_within(Duration::from_secs(3600)));
+    }
+
+    #[tokio::test]
+    async fn test_token_endpoint_error() {
+        let server = MockServer::start_async().await;
+        server
+            .mock_async(|when, then| {
+                when.method(POST).path("/token");
+                then.status(400)
+                    .body(r#"{"error":"invalid_grant","error_description":"Invalid JWT"}"#);
+            })
+            .await;
+
+        match provider_for(&server).token().await {
+            Err(GoogleApiError::HttpStatus { status, body, .. }) => {
+                assert_eq!(status, 400);
+                assert!(body.contains("invalid_grant"));
+            }
+            other => panic!("HttpStatus であるべき: {:?}", other.err()),
+        }
+    }
+
+    #[test]
+    fn test_invalid_key_is_rejected() {
+        let mut key = ServiceAccountKey::from_json(key_json()).unwrap();
+        key.private_key = "not a pem".to_string();
+        assert!(matches!(
+            ServiceAccountTokenProvider::new(key),
+            Err(GoogleApiError::InvalidArgument(_))
+        ));
+        assert!(matches!(
+            ServiceAccountKey::from_json("{}"),
+            Err(GoogleApiError::InvalidArgument(_))
+        ));
+    }
+
+    #[test]
+    fn test_key_debug_hides_private_key() {
+        let key = ServiceAccountKey::from_json(key_json()).unwrap();
+        assert!(!format!("{:?}", key).contains("PRIVATE KEY"));
+    }
+}
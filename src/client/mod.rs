@@ -0,0 +1,1000 @@
+use crate::auth::TokenProvider;
+use crate::error::GoogleApiError;
+use crate::http::{HttpClient, MAX_BATCH_URLS};
+use crate::property::{SearchConsoleProperties, SiteEntry};
+use crate::quota::QuotaGovernor;
+use crate::retry::RetryPolicy;
+use crate::validate::UrlValidator;
+use crate::{
+    PublishUrlNotificationResponse, ResponseGoogleIndexingBatch, ResponseUrlNotificationMetadata,
+    UrlNotification, UrlNotificationsType,
+};
+use futures_util::stream::{self, StreamExt};
+use serde_json::json;
+use std::fmt::{Debug, Formatter};
+use std::sync::Arc;
+use std::time::Duration;
+use urlencoding::encode;
+
+/// Default base URL of the Indexing API.
+pub const DEFAULT_BASE_URL: &str = "https://indexing.googleapis.com";
+
+/// Default base URL of the Search Console API, used by [`IndexingClient::list_sites`].
+pub const DEFAULT_SEARCH_CONSOLE_BASE_URL: &str = "https://www.googleapis.com/webmasters/v3";
+
+const DEFAULT_BATCH_CONCURRENCY: usize = 2;
+
+const DEFAULT_USER_AGENT: &str = concat!("google-indexing-api/", env!("CARGO_PKG_VERSION"));
+
+/// A batch request of [`IndexingClient::batch_all`] that failed as a whole.
+#[derive(Debug)]
+pub struct BatchFailure {
+    /// The notifications of the batch, after validation and normalization.
+    pub notifications: Vec<UrlNotification>,
+    pub error: GoogleApiError,
+}
+
+/// Result of [`IndexingClient::batch_all`].
+#[derive(Debug, Default)]
+pub struct BatchReport {
+    /// Results of the batches that were sent, in input order.
+    pub results: Vec<ResponseGoogleIndexingBatch>,
+    /// Batches that failed as a whole, in input order. Google may not have received them.
+    pub failed: Vec<BatchFailure>,
+}
+
+impl BatchReport {
+    /// Whether every batch was sent.
+    pub fn is_complete(&self) -> bool {
+        self.failed.is_empty()
+    }
+
+    /// The results, or the error of the first failed batch.
+    pub fn into_result(self) -> Result<Vec<ResponseGoogleIndexingBatch>, GoogleApiError> {
+        match self.failed.into_iter().next() {
+            Some(failure) => Err(failure.error),
+            None => Ok(self.results),
+        }
+    }
+}
+
+/// Reusable Indexing API client.
+///
+/// It owns a `reqwest::Client`, so connections are pooled across calls.
+/// Cloning is cheap and clones share the same connection pool.
+/// Access tokens are taken from the configured [`TokenProvider`] before each request.
+/// ```rust
+/// use google_indexing_api::{IndexingClient, StaticTokenProvider, UrlNotificationsType};
+/// use std::time::Duration;
+/// async fn example_client(token: &str) {
+///     let client = IndexingClient::builder()
+///         .timeout(Duration::from_secs(30))
+///         .token_provider(StaticTokenProvider::new(token))
+///         .build()
+///         .unwrap();
+///     client
+///         .publish("https://example.com/widgets/1", UrlNotificationsType::UPDATED)
+///         .await;
+/// }
+/// ```
+#[derive(Clone)]
+pub struct IndexingClient {
+    http: HttpClient,
+    base_url: String,
+    token_provider: Option<Arc<dyn TokenProvider>>,
+    batch_concurrency: usize,
+    quota: Option<Arc<QuotaGovernor>>,
+    url_validator: Option<UrlValidator>,
+    search_console_base_url: String,
+    properties: Option<SearchConsoleProperties>,
+}
+
+impl Debug for IndexingClient {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("IndexingClient")
+            .field("base_url", &self.base_url)
+            .field("token_provider", &self.token_provider.is_some())
+            .field("quota", &self.quota)
+            .field("url_validator", &self.url_validator)
+            .field("properties", &self.properties)
+            .finish()
+    }
+}
+
+impl IndexingClient {
+    /// Creates a client with the default settings.
+    ///
+    /// # Panics
+    /// Panics if the TLS backend cannot be initialized, like `reqwest::Client::new()`.
+    /// Use [`IndexingClient::builder`] to handle that case as an error.
+    pub fn new() -> Self {
+        IndexingClient::builder()
+            .build()
+            .expect("failed to build the default IndexingClient")
+    }
+
+    pub fn builder() -> IndexingClientBuilder {
+        IndexingClientBuilder::default()
+    }
+
+    /// Base URL the client sends requests to.
+    pub fn base_url(&self) -> &str {
+        self.base_url.as_str()
+    }
+
+    /// The client-side quota governor, to check the remaining budget.
+    pub fn quota_governor(&self) -> Option<&QuotaGovernor> {
+        self.quota.as_deref()
+    }
+
+    /// The Search Console properties URLs are checked against, if configured.
+    pub fn search_console_properties(&self) -> Option<&SearchConsoleProperties> {
+        self.properties.as_ref()
+    }
+
+    /// Lists the Search Console properties the account can access, with its permission
+    /// level on each (Search Console `sites.list`).
+    ///
+    /// The token needs the `https://www.googleapis.com/auth/webmasters.readonly` scope
+    /// in addition to `indexing`. It does not count toward the Indexing API quota.
+    pub async fn list_sites(&self) -> Result<Vec<SiteEntry>, GoogleApiError> {
+        #[derive(serde::Deserialize)]
+        struct SitesList {
+            #[serde(rename = "siteEntry", default)]
+            site_entry: Vec<SiteEntry>,
+        }
+        let list: SitesList = self
+            .http
+            .get_unmetered(
+                self.token_provider()?,
+                format!("{}/sites", self.search_console_base_url).as_str(),
+            )
+            .await?;
+        Ok(list.site_entry)
+    }
+
+    /// Notify Google that a URL has been updated or deleted.
+    pub async fn publish(
+        &self,
+        url: &str,
+        url_type: UrlNotificationsType,
+    ) -> Result<PublishUrlNotificationResponse, GoogleApiError> {
+        self.publish_with(self.token_provider()?, url, url_type)
+            .await
+    }
+
+    /// Fetch metadata about notifications previously sent for a URL.
+    pub async fn get_metadata(
+        &self,
+        url: &str,
+    ) -> Result<ResponseUrlNotificationMetadata, GoogleApiError> {
+        self.get_metadata_with(self.token_provider()?, url).await
+    }
+
+    /// Notify Google about 1 to 100 URLs in a single batch request.
+    pub async fn batch(
+        &self,
+        urls: Vec<String>,
+        url_type: UrlNotificationsType,
+    ) -> Result<Vec<ResponseGoogleIndexingBatch>, GoogleApiError> {
+        self.batch_with(self.token_provider()?, urls, url_type)
+            .await
+    }
+
+    /// Notify Google about any number of URLs.
+    ///
+    /// The URLs are split into batches of 100 that are sent concurrently
+    /// (see [`IndexingClientBuilder::batch_concurrency`]). The results are returned in the
+    /// order of `urls`. An empty list returns an empty result without sending anything.
+    /// If a whole batch fails, the remaining batches still complete; the failed batch's
+    /// notifications and error are in [`BatchReport::failed`]. `Err` means nothing was
+    /// sent, e.g. because a URL is invalid.
+    pub async fn batch_all(
+        &self,
+        urls: Vec<String>,
+        url_type: UrlNotificationsType,
+    ) -> Result<BatchReport, GoogleApiError> {
+        self.batch_all_with(self.token_provider()?, urls, url_type)
+            .await
+    }
+
+    /// Like [`IndexingClient::batch`], but each URL has its own notification type,
+    /// so `UPDATED` and `DELETED` notifications can share one batch request.
+    /// ```rust
+    /// use google_indexing_api::{IndexingClient, UrlNotification};
+    /// async fn example_mixed(client: &IndexingClient) {
+    ///     client
+    ///         .batch_notifications(vec![
+    ///             UrlNotification::updated("https://example.com/jobs/new"),
+    ///             UrlNotification::deleted("https://example.com/jobs/expired"),
+    ///         ])
+    ///         .await;
+    /// }
+    /// ```
+    pub async fn batch_notifications(
+        &self,
+        notifications: Vec<UrlNotification>,
+    ) -> Result<Vec<ResponseGoogleIndexingBatch>, GoogleApiError> {
+        self.batch_notifications_with(self.token_provider()?, notifications)
+            .await
+    }
+
+    /// Like [`IndexingClient::batch_all`], but each URL has its own notification type.
+    pub async fn batch_all_notifications(
+        &self,
+        notifications: Vec<UrlNotification>,
+    ) -> Result<BatchReport, GoogleApiError> {
+        self.batch_all_notifications_with(self.token_provider()?, notifications)
+            .await
+    }
+
+    /// Fetch notification metadata for 1 to 100 URLs in a single batch request.
+    ///
+    /// Returns one entry per URL, in the order of `urls`. An entry is an error when
+    /// its part failed, e.g. `HttpStatus { status: 404, .. }` for a URL that was never notified.
+    pub async fn batch_get_metadata(
+        &self,
+        urls: Vec<String>,
+    ) -> Result<Vec<Result<ResponseUrlNotificationMetadata, GoogleApiError>>, GoogleApiError> {
+        self.batch_get_metadata_with(self.token_provider()?, urls)
+            .await
+    }
+
+    fn token_provider(&self) -> Result<&dyn TokenProvider, GoogleApiError> {
+        match self.token_provider.as_deref() {
+            Some(provider) => Ok(provider),
+            None => Err(GoogleApiError::InvalidArgument(
+                "no token provider is configured on this IndexingClient".to_string(),
+            )),
+        }
+    }
+
+    // token provider とクォータだけを差し替えた複製。接続プールとほかの設定は共有する
+    pub(crate) fn with_account(
+        &self,
+        tokens: Arc<dyn TokenProvider>,
+        quota: Arc<QuotaGovernor>,
+    ) -> IndexingClient {
+        IndexingClient {
+            http: self.http.clone().with_quota_governor(Some(quota.clone())),
+            token_provider: Some(tokens),
+            quota: Some(quota),
+            ..self.clone()
+        }
+    }
+
+    fn normalize(&self, url: &str) -> Result<String, GoogleApiError> {
+        let url = match &self.url_validator {
+            Some(validator) => validator.normalize(url)?,
+            None => url.to_string(),
+        };
+        if let Some(properties) = &self.properties {
+            properties.check_url(url.as_str())?;
+        }
+        Ok(url)
+    }
+
+    // prepare と同じだが、重複を除かずに 1 件ずつ正規化する
+    pub(crate) fn normalize_each(&self, urls: Vec<String>) -> Result<Vec<String>, GoogleApiError> {
+        let urls = match &self.url_validator {
+            Some(validator) => validator.normalize_each(urls)?,
+            None => urls,
+        };
+        if let Some(properties) = &self.properties {
+            properties.check(urls.as_slice())?;
+        }
+        Ok(urls)
+    }
+
+    // 検証と正規化、重複の除去をしてから、所有していない property の URL を弾く
+    pub(crate) fn prepare(
+        &self,
+        notifications: Vec<UrlNotification>,
+    ) -> Result<Vec<UrlNotification>, GoogleApiError> {
+        let notifications = match &self.url_validator {
+            Some(validator) => validator.normalize_notifications(notifications)?,
+            None => notifications,
+        };
+        if let Some(properties) = &self.properties {
+            let urls = notifications
+                .iter()
+                .map(|n| n.url.as_str())
+                .collect::<Vec<_>>();
+            properties.check(urls.as_slice())?;
+        }
+        Ok(notifications)
+    }
+
+    pub(crate) async fn publish_with(
+        &self,
+        tokens: &dyn TokenProvider,
+        url: &str,
+        url_type: UrlNotificationsType,
+    ) -> Result<PublishUrlNotificationResponse, GoogleApiError> {
+        let url = self.normalize(url)?;
+        self.http
+            .post(
+                tokens,
+                format!("{}/v3/urlNotifications:publish", self.base_url).as_str(),
+                json!({
+                    "url": url,
+                    "type": url_type.to_string(),
+                }),
+            )
+            .await
+    }
+
+    pub(crate) async fn get_metadata_with(
+        &self,
+        tokens: &dyn TokenProvider,
+        url: &str,
+    ) -> Result<ResponseUrlNotificationMetadata, GoogleApiError> {
+        let url = self.normalize(url)?;
+        self.http
+            .get(
+                tokens,
+                format!(
+                    "{}/v3/urlNotifications/metadata?url={}",
+                    self.base_url,
+                    encode(url.as_str())
+                )
+                .as_str(),
+            )
+            .await
+    }
+
+    pub(crate) async fn batch_with(
+        &self,
+        tokens: &dyn TokenProvider,
+        urls: Vec<String>,
+        url_type: UrlNotificationsType,
+    ) -> Result<Vec<ResponseGoogleIndexingBatch>, GoogleApiError> {
+        self.batch_notifications_with(tokens, to_notifications(urls, url_type))
+            .await
+    }
+
+    pub(crate) async fn batch_notifications_with(
+        &self,
+        tokens: &dyn TokenProvider,
+        notifications: Vec<UrlNotification>,
+    ) -> Result<Vec<ResponseGoogleIndexingBatch>, GoogleApiError> {
+        let notifications = self.prepare(notifications)?;
+        self.send_prepared(tokens, notifications).await
+    }
+
+    // prepare 済みの通知を 1 つのバッチで送る
+    async fn send_prepared(
+        &self,
+        tokens: &dyn TokenProvider,
+        notifications: Vec<UrlNotification>,
+    ) -> Result<Vec<ResponseGoogleIndexingBatch>, GoogleApiError> {
+        self.http
+            .execute_url(
+                format!("{}/batch", self.base_url).as_str(),
+                tokens,
+                notifications,
+            )
+            .await
+    }
+
+    pub(crate) async fn batch_get_metadata_with(
+        &self,
+        tokens: &dyn TokenProvider,
+        urls: Vec<String>,
+    ) -> Result<Vec<Result<ResponseUrlNotificationMetadata, GoogleApiError>>, GoogleApiError> {
+        // 結果は入力の URL と 1 対 1 なので、重複は除かない
+        let urls = self.normalize_each(urls)?;
+        self.http
+            .execute_metadata_url(format!("{}/batch", self.base_url).as_str(), tokens, urls)
+            .await
+    }
+
+    pub(crate) async fn batch_all_with(
+        &self,
+        tokens: &dyn TokenProvider,
+        urls: Vec<String>,
+        url_type: UrlNotificationsType,
+    ) -> Result<BatchReport, GoogleApiError> {
+        self.batch_all_notifications_with(tokens, to_notifications(urls, url_type))
+            .await
+    }
+
+    pub(crate) async fn batch_all_notifications_with(
+        &self,
+        tokens: &dyn TokenProvider,
+        notifications: Vec<UrlNotification>,
+    ) -> Result<BatchReport, GoogleApiError> {
+        // 分割する前に重複を除く。別のバッチに入った重複は除けないため
+        let notifications = self.prepare(notifications)?;
+        // buffered は入力の順に結果を返すので、チャンクを結合すれば元の順になる
+        let chunks = stream::iter(notifications.chunks(MAX_BATCH_URLS).map(|q| q.to_vec()))
+            .map(|chunk| async move {
+                let result = self.send_prepared(tokens, chunk.clone()).await;
+                (chunk, result)
+            })
+            .buffered(self.batch_concurrency)
+            .collect::<Vec<_>>()
+            .await;
+        let mut report = BatchReport {
+            results: Vec::with_capacity(notifications.len()),
+            failed: Vec::new(),
+        };
+        for (notifications, result) in chunks {
+            match result {
+                Ok(results) => report.results.extend(results),
+                Err(error) => report.failed.push(BatchFailure {
+                    notifications,
+                    error,
+                }),
+            }
+        }
+        Ok(report)
+    }
+}
+
+fn to_notifications(urls: Vec<String>, url_type: UrlNotificationsType) -> Vec<UrlNotification> {
+    urls.into_iter()
+        .map(|url| UrlNotification {
+            url,
+            url_type: url_type.clone(),
+        })
+        .collect()
+}
+
+impl Default for IndexingClient {
+    fn default() -> Self {
+        IndexingClient::new()
+    }
+}
+
+/// Builder for [`IndexingClient`].
+#[derive(Default)]
+pub struct IndexingClientBuilder {
+    token_provider: Option<Arc<dyn TokenProvider>>,
+    client: Option<reqwest::Client>,
+    base_url: Option<String>,
+    timeout: Option<Duration>,
+    connect_timeout: Option<Duration>,
+    user_agent: Option<String>,
+    proxy: Option<reqwest::Proxy>,
+    retry: Option<RetryPolicy>,
+    batch_concurrency: Option<usize>,
+    quota: Option<Arc<QuotaGovernor>>,
+    url_validator: Option<UrlValidator>,
+    search_console_base_url: Option<String>,
+    properties: Option<SearchConsoleProperties>,
+}
+
+impl IndexingClientBuilder {
+    /// Source of the access tokens sent with each request.
+    pub fn token_provider<P>(mut self, provider: P) -> Self
+    where
+        P: TokenProvider + 'static,
+    {
+        self.token_provider = Some(Arc::new(provider));
+        self
+    }
+    /// Use an existing `reqwest::Client`.
+    /// It cannot be combined with `timeout`, `connect_timeout`, `user_agent` or `proxy`.
+    pub fn http_client(mut self, client: reqwest::Client) -> Self {
+        self.client = Some(client);
+        self
+    }
+    /// Base URL of the API (default: `https://indexing.googleapis.com`).
+    pub fn base_url(mut self, base_url: &str) -> Self {
+        self.base_url = Some(base_url.to_string());
+        self
+    }
+    /// Total timeout of each request.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+    /// Timeout of the connect phase of each request.
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+    /// `User-Agent` header (default: `google-indexing-api/<version>`).
+    pub fn user_agent(mut self, user_agent: &str) -> Self {
+        self.user_agent = Some(user_agent.to_string());
+        self
+    }
+    pub fn proxy(mut self, proxy: reqwest::Proxy) -> Self {
+        self.proxy = Some(proxy);
+        self
+    }
+    /// Maximum number of batch requests `batch_all` sends at the same time (default: 2).
+    /// `0` is treated as `1`.
+    pub fn batch_concurrency(mut self, batch_concurrency: usize) -> Self {
+        self.batch_concurrency = Some(batch_concurrency.max(1));
+        self
+    }
+    /// Retry transient failures (default: no retries).
+    pub fn retry_policy(mut self, retry: RetryPolicy) -> Self {
+        self.retry = Some(retry);
+        self
+    }
+    /// Enforce a client-side quota before sending requests (default: none).
+    /// Pass an `Arc<QuotaGovernor>` to share one budget between several clients.
+    pub fn quota_governor<Q>(mut self, governor: Q) -> Self
+    where
+        Q: Into<Arc<QuotaGovernor>>,
+    {
+        self.quota = Some(governor.into());
+        self
+    }
+
+    /// Check and normalize every URL before sending it (default: URLs are sent as given).
+    /// Batches also drop duplicate URLs, so they may return fewer results than URLs; see
+    /// [`UrlValidator::normalize_notifications`] for which type is kept.
+    pub fn url_validator(mut self, validator: UrlValidator) -> Self {
+        self.url_validator = Some(validator);
+        self
+    }
+
+    /// Reject URLs no owned property covers before sending them, instead of getting
+    /// `403 Forbidden` from the API (default: no check).
+    pub fn search_console_properties(mut self, properties: SearchConsoleProperties) -> Self {
+        self.properties = Some(properties);
+        self
+    }
+    /// Base URL of the Search Console API (default: `https://www.googleapis.com/webmasters/v3`).
+    pub fn search_console_base_url(mut self, base_url: &str) -> Self {
+        self.search_console_base_url = Some(base_url.to_string());
+        self
+    }
+
+    pub fn build(self) -> Result<IndexingClient, GoogleApiError> {
+        let base_url = self
+            .base_url
+            .unwrap_or_else(|| DEFAULT_BASE_URL.to_string())
+            .trim_end_matches('/')
+            .to_string();
+        if base_url.is_empty() {
+            return Err(GoogleApiError::InvalidArgument(
+                "base_url must not be empty".to_string(),
+            ));
+        }
+
+        let client = match self.client {
+            Some(client) => {
+                // 注入された Client の設定を黙って上書きできないので、併用はエラーにする
+                if self.timeout.is_some()
+                    || self.connect_timeout.is_some()
+                    || self.user_agent.is_some()
+                    || self.proxy.is_some()
+                {
+                    return Err(GoogleApiError::InvalidArgument(
+                        "http_client cannot be combined with timeout, connect_timeout, user_agent or proxy"
+                            .to_string(),
+                    ));
+                }
+                client
+            }
+            None => {
+                let mut builder = reqwest::Client::builder().user_agent(
+                    self.user_agent
+                        .unwrap_or_else(|| DEFAULT_USER_AGENT.to_string()),
+                );
+                if let Some(timeout) = self.timeout {
+                    builder = builder.timeout(timeout);
+                }
+                if let Some(timeout) = self.connect_timeout {
+                    builder = builder.connect_timeout(timeout);
+                }
+                if let Some(proxy) = self.proxy {
+                    builder = builder.proxy(proxy);
+                }
+                let client = builder.build();
+                if client.is_err() {
+                    return Err(GoogleApiError::Connection(client.err().unwrap()));
+                }
+                client.unwrap()
+            }
+        };
+
+        Ok(IndexingClient {
+            http: HttpClient::new(client, self.retry.unwrap_or_else(RetryPolicy::none))
+                .with_quota_governor(self.quota.clone()),
+            base_url,
+            token_provider: self.token_provider,
+            batch_concurrency: self.batch_concurrency.unwrap_or(DEFAULT_BATCH_CONCURRENCY),
+            quota: self.quota,
+            url_validator: self.url_validator,
+            search_console_base_url: self
+                .search_console_base_url
+                .unwrap_or_else(|| DEFAULT_SEARCH_CONSOLE_BASE_URL.to_string())
+                .trim_end_matches('/')
+                .to_string(),
+            properties: self.properties,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::StaticTokenProvider;
+    use crate::quota::{QuotaBucket, QuotaLimit};
+    use crate::test_support::{batch_body, client_for, part};
+    use httpmock::prelude::*;
+
+    #[tokio::test]
+    async fn test_publish_uses_base_url_and_user_agent() {
+        let server = MockServer::start_async().await;
+        let mock = server
+            .mock_async(|when, then| {
+                when.method(POST)
+                    .path("/v3/urlNotifications:publish")
+                    .header("Authorization", "Bearer test-token")
+                    .header("User-Agent", DEFAULT_USER_AGENT)
+                    .body_contains("URL_UPDATED");
+                then.status(200).body(
+                    r#"{"urlNotificationMetadata":{"url":"https://example.com/widgets/1","latestUpdate":{"url":"https://example.com/widgets/1","type":"URL_UPDATED","notifyTime":"2026-10-01T10:00:00.123456789Z"}}}"#,
+                );
+            })
+            .await;
+
+        let response = client_for(&server)
+            .publish(
+                "https://example.com/widgets/1",
+                UrlNotificationsType::UPDATED,
+            )
+            .await
+            .unwrap();
+        mock.assert_async().await;
+        let latest_update = response
+            .url_notification_metadata
+            .latest_update
+            .as_ref()
+            .unwrap();
+        assert_eq!(latest_update.notify_time, "2026-10-01T10:00:00.123456789Z");
+        assert_eq!(
+            response.raw()["urlNotificationMetadata"]["url"],
+            "https://example.com/widgets/1"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_get_metadata_encodes_url() {
+        let server = MockServer::start_async().await;
+        let mock = server
+            .mock_async(|when, then| {
+                when.method(GET)
+                    .path("/v3/urlNotifications/metadata")
+                    .query_param("url", "https://example.com/widgets/1?a=b");
+                then.status(200)
+                    .body(r#"{"url":"https://example.com/widgets/1?a=b"}"#);
+            })
+            .await;
+
+        let metadata = client_for(&server)
+            .get_metadata("https://example.com/widgets/1?a=b")
+            .await
+            .unwrap();
+        mock.assert_async().await;
+        assert_eq!(metadata.url, "https://example.com/widgets/1?a=b");
+    }
+
+    #[tokio::test]
+    async fn test_custom_user_agent_and_trailing_slash() {
+        let server = MockServer::start_async().await;
+        let mock = server
+            .mock_async(|when, then| {
+                when.method(POST)
+                    .path("/v3/urlNotifications:publish")
+                    .header("User-Agent", "my-crawler/2.0");
+                then.status(200)
+                    .body(r#"{"urlNotificationMetadata":{"url":"https://example.com/widgets/1"}}"#);
+            })
+            .await;
+
+        let client = IndexingClient::builder()
+            .base_url(format!("{}/", server.base_url()).as_str())
+            .user_agent("my-crawler/2.0")
+            .token_provider(StaticTokenProvider::new("test-token"))
+            .timeout(Duration::from_secs(5))
+            .build()
+            .unwrap();
+        assert_eq!(client.base_url(), server.base_url());
+        client
+            .publish(
+                "https://example.com/widgets/1",
+                UrlNotificationsType::DELETED,
+            )
+            .await
+            .unwrap();
+        mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_call_without_token_provider_is_rejected() {
+        // 送信前に弾かれるので、到達できない base_url でも検証できる
+        let client = IndexingClient::builder()
+            .base_url("http://127.0.0.1:1")
+            .build()
+            .unwrap();
+        let result = client.get_metadata("https://example.com/widgets/1").await;
+        assert!(matches!(result, Err(GoogleApiError::InvalidArgument(_))));
+    }
+
+    // offset 番目から count 件分の part を持つバッチレスポンス。各 part は通し番号 n を返す
+    fn batch_response(offset: usize, count: usize) -> String {
+        batch_body((0..count).map(|i| {
+            part(
+                i + 1,
+                "200 OK",
+                format!("{{\"n\":{}}}", offset + i).as_str(),
+            )
+        }))
+    }
+
+    #[tokio::test]
+    async fn test_batch_all_splits_into_batches_of_100_in_order() {
+        let server = MockServer::start_async().await;
+        let mut mocks = vec![];
+        for (offset, count) in [(0, 100), (100, 100), (200, 50)] {
+            let mock = server
+                .mock_async(|when, then| {
+                    when.method(POST)
+                        .path("/batch")
+                        .body_contains(format!("\"https://example.com/jobs/{}\"", offset));
+                    then.status(200)
+                        .header("Content-Type", "multipart/mixed; boundary=batch_x")
+                        .body(batch_response(offset, count));
+                })
+                .await;
+            mocks.push(mock);
+        }
+
+        let urls = (0..250)
+            .map(|i| format!("https://example.com/jobs/{}", i))
+            .collect::<Vec<String>>();
+        let report = client_for(&server)
+            .batch_all(urls, UrlNotificationsType::UPDATED)
+            .await
+            .unwrap();
+        for mock in mocks {
+            mock.assert_async().await;
+        }
+        assert!(report.is_complete());
+        assert_eq!(report.results.len(), 250);
+        for (i, result) in report.results.iter().enumerate() {
+            assert_eq!(result.url(), format!("https://example.com/jobs/{}", i));
+            assert_eq!(result.json()["n"], i);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_batch_all_keeps_results_of_batches_that_succeeded() {
+        let server = MockServer::start_async().await;
+        server
+            .mock_async(|when, then| {
+                when.method(POST)
+                    .path("/batch")
+                    .body_contains("\"https://example.com/jobs/0\"");
+                then.status(200)
+                    .header("Content-Type", "multipart/mixed; boundary=batch_x")
+                    .body(batch_response(0, 100));
+            })
+            .await;
+        server
+            .mock_async(|when, then| {
+                when.method(POST)
+                    .path("/batch")
+                    .body_contains("\"https://example.com/jobs/100\"");
+                then.status(503).body("unavailable");
+            })
+            .await;
+
+        let urls = (0..150)
+            .map(|i| format!("https://example.com/jobs/{}", i))
+            .collect::<Vec<String>>();
+        let report = client_for(&server)
+            .batch_all(urls, UrlNotificationsType::UPDATED)
+            .await
+            .unwrap();
+        assert_eq!(report.results.len(), 100);
+        assert_eq!(report.failed.len(), 1);
+        let failure = &report.failed[0];
+        assert_eq!(failure.notifications.len(), 50);
+        assert_eq!(failure.notifications[0].url, "https://example.com/jobs/100");
+        assert!(matches!(
+            failure.error,
+            GoogleApiError::HttpStatus { status: 503, .. }
+        ));
+        assert!(report.into_result().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_batch_notifications_sends_each_type() {
+        let server = MockServer::start_async().await;
+        let mock = server
+            .mock_async(|when, then| {
+                when.method(POST)
+                    .path("/batch")
+                    .body_contains(r#"{"type":"URL_UPDATED","url":"https://example.com/jobs/0"}"#)
+                    .body_contains(r#"{"type":"URL_DELETED","url":"https://example.com/jobs/1"}"#);
+                then.status(200)
+                    .header("Content-Type", "multipart/mixed; boundary=batch_x")
+                    .body(batch_response(0, 2));
+            })
+            .await;
+
+        let results = client_for(&server)
+            .batch_notifications(vec![
+                UrlNotification::updated("https://example.com/jobs/0"),
+                ("https://example.com/jobs/1", UrlNotificationsType::DELETED).into(),
+            ])
+            .await
+            .unwrap();
+        mock.assert_async().await;
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[1].url(), "https://example.com/jobs/1");
+    }
+
+    #[tokio::test]
+    async fn test_quota_governor_stops_batch_before_sending() {
+        let server = MockServer::start_async().await;
+        let mock = server
+            .mock_async(|when, then| {
+                when.method(POST).path("/batch");
+                then.status(200)
+                    .header("Content-Type", "multipart/mixed; boundary=batch_x")
+                    .body(batch_response(0, 2));
+            })
+            .await;
+        let client = IndexingClient::builder()
+            .base_url(server.base_url().as_str())
+            .token_provider(StaticTokenProvider::new("test-token"))
+            .quota_governor(QuotaGovernor::new().publish(QuotaLimit::unlimited().per_day(3)))
+            .build()
+            .unwrap();
+        let urls = vec![
+            "https://example.com/jobs/0".to_string(),
+            "https://example.com/jobs/1".to_string(),
+        ];
+
+        client
+            .batch(urls.clone(), UrlNotificationsType::UPDATED)
+            .await
+            .unwrap();
+        let remaining = client
+            .quota_governor()
+            .unwrap()
+            .remaining(QuotaBucket::Publish)
+            .unwrap();
+        assert_eq!(remaining.today, Some(1));
+
+        // 2 件目のバッチは予算 (残り 1 件) を超えるので送信しない
+        let result = client.batch(urls, UrlNotificationsType::UPDATED).await;
+        assert!(matches!(
+            result,
+            Err(GoogleApiError::QuotaBudgetExhausted {
+                bucket: QuotaBucket::Publish,
+                ..
+            })
+        ));
+        mock.assert_hits_async(1).await;
+    }
+
+    #[tokio::test]
+    async fn test_batch_all_with_no_urls_sends_nothing() {
+        let client = IndexingClient::builder()
+            .base_url("http://127.0.0.1:1")
+            .token_provider(StaticTokenProvider::new("test-token"))
+            .build()
+            .unwrap();
+        let report = client
+            .batch_all(vec![], UrlNotificationsType::UPDATED)
+            .await
+            .unwrap();
+        assert!(report.results.is_empty());
+        assert!(report.failed.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_url_validator_normalizes_and_dedupes_batch() {
+        let server = MockServer::start_async().await;
+        let mock = server
+            .mock_async(|when, then| {
+                when.method(POST)
+                    .path("/batch")
+                    .body_contains(r#"{"type":"URL_DELETED","url":"https://example.com/jobs/0"}"#)
+                    .body_contains(r#"{"type":"URL_UPDATED","url":"https://example.com/jobs/1"}"#);
+                then.status(200)
+                    .header("Content-Type", "multipart/mixed; boundary=batch_x")
+                    .body(batch_response(0, 2));
+            })
+            .await;
+        let client = IndexingClient::builder()
+            .base_url(server.base_url().as_str())
+            .token_provider(StaticTokenProvider::new("test-token"))
+            .url_validator(UrlValidator::new())
+            .build()
+            .unwrap();
+
+        let report = client
+            .batch_all_notifications(vec![
+                UrlNotification::updated("HTTPS://Example.com/jobs/0#apply"),
+                UrlNotification::updated("https://example.com/jobs/1"),
+                UrlNotification::deleted("https://example.com/jobs/0"),
+            ])
+            .await
+            .unwrap();
+        mock.assert_async().await;
+        assert_eq!(report.results.len(), 2);
+
+        // 不正な URL があれば何も送らない
+        let result = client
+            .batch(
+                vec![
+                    "https://example.com/jobs/0".to_string(),
+                    "example.com/jobs/1".to_string(),
+                ],
+                UrlNotificationsType::UPDATED,
+            )
+            .await;
+        assert!(
+            matches!(&result, Err(GoogleApiError::InvalidArgument(e)) if e.starts_with("url 1: ")),
+            "{:?}",
+            result
+        );
+        mock.assert_hits_async(1).await;
+    }
+
+    #[tokio::test]
+    async fn test_search_console_properties_reject_uncovered_urls() {
+        let server = MockServer::start_async().await;
+        let mock = server
+            .mock_async(|when, then| {
+                when.any_request();
+                then.status(403);
+            })
+            .await;
+        let client = IndexingClient::builder()
+            .base_url(server.base_url().as_str())
+            .token_provider(StaticTokenProvider::new("test-token"))
+            .search_console_properties(
+                SearchConsoleProperties::parse(["sc-domain:example.com"]).unwrap(),
+            )
+            .build()
+            .unwrap();
+
+        let result = client
+            .publish("https://example.org/jobs/1", UrlNotificationsType::UPDATED)
+            .await;
+        assert!(
+            matches!(&result, Err(GoogleApiError::InvalidArgument(e)) if e.contains("https://example.org/jobs/1")),
+            "{:?}",
+            result
+        );
+        let result = client
+            .batch_notifications(vec![
+                UrlNotification::updated("https://jobs.example.com/1"),
+                UrlNotification::updated("https://example.org/jobs/1"),
+            ])
+            .await;
+        assert!(
+            matches!(&result, Err(GoogleApiError::InvalidArgument(e)) if e.starts_with("url 1: ")),
+            "{:?}",
+            result
+        );
+        mock.assert_hits_async(0).await;
+    }
+
+    #[test]
+    fn test_builder_rejects_http_client_with_settings() {
+        let result = IndexingClient::builder()
+            .http_client(reqwest::Client::new())
+            .timeout(Duration::from_secs(5))
+            .build();
+        assert!(matches!(result, Err(GoogleApiError::InvalidArgument(_))));
+
+        assert!(IndexingClient::builder()
+            .http_client(reqwest::Client::new())
+            .build()
+            .is_ok());
+    }
+}
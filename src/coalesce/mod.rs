@@ -0,0 +1,327 @@
+use crate::client::{BatchReport, IndexingClient};
+use crate::error::GoogleApiError;
+use crate::{UrlNotification, UrlNotificationsType};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// Collapses repeated notifications for the same URL before they are sent.
+///
+/// A URL pushed several times within `window` is sent once, with the latest type:
+/// `UPDATED` followed by `DELETED` becomes `DELETED`. The window starts with the first
+/// notification of a URL, so a URL that keeps changing is still sent after `window`.
+/// [`flush`](NotificationCoalescer::flush) sends the URLs whose window has passed as
+/// one batch call.
+/// ```rust
+/// use google_indexing_api::{IndexingClient, NotificationCoalescer, UrlNotification};
+/// use std::time::Duration;
+/// async fn example_coalesce(client: &IndexingClient) {
+///     let mut coalescer = NotificationCoalescer::new(Duration::from_secs(300));
+///     coalescer.push(UrlNotification::updated("https://example.com/jobs/1"));
+///     coalescer.push(UrlNotification::updated("https://example.com/jobs/1"));
+///     coalescer.push(UrlNotification::deleted("https://example.com/jobs/1"));
+///     // Later, e.g. once a minute: sends one DELETED notification
+///     coalescer.flush(client).await.unwrap();
+/// }
+/// ```
+#[derive(Clone, Debug)]
+pub struct NotificationCoalescer {
+    window: Duration,
+    pending: HashMap<String, Pending>,
+    next_seq: u64,
+    coalesced: u64,
+}
+
+#[derive(Clone, Debug)]
+struct Pending {
+    url_type: UrlNotificationsType,
+    since: Instant,
+    // flush するときは最初に入った順に並べる
+    seq: u64,
+}
+
+impl NotificationCoalescer {
+    pub fn new(window: Duration) -> Self {
+        NotificationCoalescer {
+            window,
+            pending: HashMap::new(),
+            next_seq: 0,
+            coalesced: 0,
+        }
+    }
+
+    pub fn window(&self) -> Duration {
+        self.window
+    }
+
+    /// Adds a notification. If the URL is already pending, only its type is replaced.
+    pub fn push(&mut self, notification: UrlNotification) {
+        self.push_at(notification, Instant::now());
+    }
+
+    /// Number of distinct URLs waiting to be sent.
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    /// Number of notifications merged into an already pending one so far, i.e. the
+    /// requests saved.
+    pub fn coalesced(&self) -> u64 {
+        self.coalesced
+    }
+
+    /// The pending notifications, in the order their URLs were first pushed.
+    pub fn pending(&self) -> Vec<UrlNotification> {
+        self.sorted(self.pending.iter().map(|(url, p)| (url.clone(), p.clone())))
+    }
+
+    /// Time until the next URL is ready to be sent, `Duration::ZERO` if one is ready
+    /// now, or `None` when nothing is pending.
+    pub fn next_ready_in(&self) -> Option<Duration> {
+        let now = Instant::now();
+        self.pending
+            .values()
+            .map(|p| {
+                p.since
+                    .checked_add(self.window)
+                    .map_or(Duration::MAX, |ready| ready.saturating_duration_since(now))
+            })
+            .min()
+    }
+
+    /// Removes and returns the notifications whose window has passed.
+    pub fn take_ready(&mut self) -> Vec<UrlNotification> {
+        self.take_ready_at(Instant::now())
+    }
+
+    /// Removes and returns all pending notifications, ready or not.
+    pub fn take_all(&mut self) -> Vec<UrlNotification> {
+        let pending = std::mem::take(&mut self.pending);
+        self.sorted(pending)
+    }
+
+    /// Sends the notifications whose window has passed with
+    /// [`IndexingClient::batch_all_notifications`].
+    ///
+    /// The notifications of batches that fail as a whole (see [`BatchReport::failed`])
+    /// are pending again, unless the URL was pushed again in the meantime. If nothing
+    /// could be sent, all of them are pending again and the error is returned.
+    pub async fn flush(&mut self, client: &IndexingClient) -> Result<BatchReport, GoogleApiError> {
+        let ready = self.take_ready();
+        self.send(client, ready).await
+    }
+
+    /// Like [`flush`](NotificationCoalescer::flush), but sends everything pending, e.g.
+    /// on shutdown.
+    pub async fn flush_all(
+        &mut self,
+        client: &IndexingClient,
+    ) -> Result<BatchReport, GoogleApiError> {
+        let all = self.take_all();
+        self.send(client, all).await
+    }
+
+    async fn send(
+        &mut self,
+        client: &IndexingClient,
+        notifications: Vec<UrlNotification>,
+    ) -> Result<BatchReport, GoogleApiError> {
+        if notifications.is_empty() {
+            return Ok(BatchReport::default());
+        }
+        match client.batch_all_notifications(notifications.clone()).await {
+            Ok(report) => {
+                let failed = report
+                    .failed
+                    .iter()
+                    .flat_map(|failure| failure.notifications.iter().cloned())
+                    .collect();
+                self.restore(failed);
+                Ok(report)
+            }
+            Err(e) => {
+                self.restore(notifications);
+                Err(e)
+            }
+        }
+    }
+
+    // 送れなかった分は戻す。すぐ次の flush で送れるよう ready のまま入れる
+    fn restore(&mut self, notifications: Vec<UrlNotification>) {
+        let now = Instant::now();
+        let since = now.checked_sub(self.window).unwrap_or(now);
+        for notification in notifications {
+            if !self.pending.contains_key(&notification.url) {
+                self.insert(notification, since);
+            }
+        }
+    }
+
+    fn push_at(&mut self, notification: UrlNotification, now: Instant) {
+        match self.pending.get_mut(&notification.url) {
+            Some(pending) => {
+                pending.url_type = notification.url_type;
+                self.coalesced += 1;
+            }
+            None => self.insert(notification, now),
+        }
+    }
+
+    fn take_ready_at(&mut self, now: Instant) -> Vec<UrlNotification> {
+        let window = self.window;
+        let ready = self
+            .pending
+            .extract_if(|_, p| {
+                p.since
+                    .checked_add(window)
+                    .is_some_and(|ready| ready <= now)
+            })
+            .collect::<Vec<_>>();
+        self.sorted(ready)
+    }
+
+    fn insert(&mut self, notification: UrlNotification, since: Instant) {
+        self.pending.insert(
+            notification.url,
+            Pending {
+                url_type: notification.url_type,
+                since,
+                seq: self.next_seq,
+            },
+        );
+        self.next_seq += 1;
+    }
+
+    fn sorted<I>(&self, pending: I) -> Vec<UrlNotification>
+    where
+        I: IntoIterator<Item = (String, Pending)>,
+    {
+        let mut pending = pending.into_iter().collect::<Vec<_>>();
+        pending.sort_by_key(|(_, p)| p.seq);
+        pending
+            .into_iter()
+            .map(|(url, p)| UrlNotification {
+                url,
+                url_type: p.url_type,
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{batch_ok, client_for};
+    use httpmock::prelude::*;
+
+    #[test]
+    fn test_latest_type_wins_within_window() {
+        let start = Instant::now();
+        let window = Duration::from_secs(300);
+        let mut coalescer = NotificationCoalescer::new(window);
+        coalescer.push_at(UrlNotification::updated("https://example.com/1"), start);
+        coalescer.push_at(
+            UrlNotification::updated("https://example.com/2"),
+            start + Duration::from_secs(10),
+        );
+        coalescer.push_at(
+            UrlNotification::updated("https://example.com/1"),
+            start + Duration::from_secs(20),
+        );
+        coalescer.push_at(
+            UrlNotification::deleted("https://example.com/1"),
+            start + Duration::from_secs(30),
+        );
+        assert_eq!(coalescer.len(), 2);
+        assert_eq!(coalescer.coalesced(), 2);
+
+        // まだ窓の中なので何も出ない
+        assert!(coalescer
+            .take_ready_at(start + Duration::from_secs(299))
+            .is_empty());
+
+        // 窓は最初の通知から数えるので、更新が続いても 1 は送られる
+        assert_eq!(
+            coalescer.take_ready_at(start + window),
+            vec![UrlNotification::deleted("https://example.com/1")]
+        );
+        assert_eq!(
+            coalescer.take_ready_at(start + window + Duration::from_secs(10)),
+            vec![UrlNotification::updated("https://example.com/2")]
+        );
+        assert!(coalescer.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_flush_sends_one_batch() {
+        let server = MockServer::start_async().await;
+        let mock = server
+            .mock_async(|when, then| {
+                when.method(POST)
+                    .path("/batch")
+                    .body_contains(r#"{"type":"URL_DELETED","url":"https://example.com/1"}"#)
+                    .body_contains(r#"{"type":"URL_UPDATED","url":"https://example.com/2"}"#);
+                then.status(200)
+                    .header("Content-Type", "multipart/mixed; boundary=batch_x")
+                    .body(batch_ok(2));
+            })
+            .await;
+
+        let mut coalescer = NotificationCoalescer::new(Duration::ZERO);
+        coalescer.push(UrlNotification::updated("https://example.com/1"));
+        coalescer.push(UrlNotification::updated("https://example.com/2"));
+        coalescer.push(UrlNotification::deleted("https://example.com/1"));
+        let report = coalescer.flush(&client_for(&server)).await.unwrap();
+        mock.assert_async().await;
+        assert_eq!(
+            report.results.iter().map(|r| r.url()).collect::<Vec<_>>(),
+            vec!["https://example.com/1", "https://example.com/2"]
+        );
+        assert!(coalescer.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_flush_keeps_failed_batches_pending() {
+        let server = MockServer::start_async().await;
+        server
+            .mock_async(|when, then| {
+                when.method(POST)
+                    .path("/batch")
+                    .body_contains("\"https://example.com/0\"");
+                then.status(200)
+                    .header("Content-Type", "multipart/mixed; boundary=batch_x")
+                    .body(batch_ok(100));
+            })
+            .await;
+        server
+            .mock_async(|when, then| {
+                when.method(POST)
+                    .path("/batch")
+                    .body_contains("\"https://example.com/100\"");
+                then.status(500).body("internal");
+            })
+            .await;
+
+        let mut coalescer = NotificationCoalescer::new(Duration::from_secs(3600));
+        let urls = (0..101)
+            .map(|i| format!("https://example.com/{}", i))
+            .collect::<Vec<_>>();
+        for url in &urls {
+            coalescer.push(UrlNotification::updated(url.as_str()));
+        }
+        let report = coalescer.flush_all(&client_for(&server)).await.unwrap();
+        assert_eq!(report.results.len(), 100);
+        assert!(matches!(
+            report.failed[0].error,
+            GoogleApiError::HttpStatus { status: 500, .. }
+        ));
+        // 失敗したバッチの分だけを、窓を待たずに次の flush で送る
+        assert_eq!(
+            coalescer.take_ready(),
+            vec![UrlNotification::updated("https://example.com/100")]
+        );
+    }
+}
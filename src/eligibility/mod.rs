@@ -0,0 +1,433 @@
+use crate::timestamp::{civil_from_days, days_from_civil, Timestamp};
+use crate::UrlNotificationsType;
+use scraper::{ElementRef, Html, Selector};
+use serde_json::{Map, Value};
+use std::time::SystemTime;
+
+/// What to send for a URL, decided from the structured data of its page.
+/// See [`StructuredData::eligibility`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Eligibility {
+    /// The page has a current `JobPosting` or a `BroadcastEvent`: send `URL_UPDATED`.
+    Updated,
+    /// Every `JobPosting` of the page has expired: send `URL_DELETED`.
+    Deleted,
+    /// The page has neither: the Indexing API does not support it.
+    Skip,
+}
+
+impl Eligibility {
+    /// Extracts the structured data of `html` and decides as of now.
+    /// ```rust
+    /// use google_indexing_api::{Eligibility, UrlNotificationsType};
+    /// let html = r#"<script type="application/ld+json">
+    ///     {"@context": "https://schema.org", "@type": "JobPosting", "title": "Engineer"}
+    /// </script>"#;
+    /// let eligibility = Eligibility::check(html);
+    /// assert_eq!(eligibility, Eligibility::Updated);
+    /// assert_eq!(eligibility.url_type(), Some(UrlNotificationsType::UPDATED));
+    /// assert_eq!(Eligibility::check("<p>About us</p>"), Eligibility::Skip);
+    /// ```
+    pub fn check(html: &str) -> Eligibility {
+        StructuredData::extract(html).eligibility()
+    }
+
+    /// The notification type to send, or `None` for [`Eligibility::Skip`].
+    pub fn url_type(self) -> Option<UrlNotificationsType> {
+        match self {
+            Eligibility::Updated => Some(UrlNotificationsType::UPDATED),
+            Eligibility::Deleted => Some(UrlNotificationsType::DELETED),
+            Eligibility::Skip => None,
+        }
+    }
+}
+
+/// A schema.org `JobPosting` found on a page.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct JobPosting {
+    pub title: Option<String>,
+    /// When the posting expires. A date without a time is valid until the end of that
+    /// day, month or year (UTC). `None` if it is missing or not an ISO 8601 date.
+    pub valid_through: Option<Timestamp>,
+}
+
+impl JobPosting {
+    pub fn is_expired_at(&self, now: Timestamp) -> bool {
+        self.valid_through
+            .is_some_and(|valid_through| valid_through <= now)
+    }
+}
+
+/// A schema.org `BroadcastEvent` (a livestream) found on a page, usually as the
+/// `publication` of a `VideoObject`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct BroadcastEvent {
+    pub name: Option<String>,
+    pub start_date: Option<Timestamp>,
+    pub end_date: Option<Timestamp>,
+}
+
+/// The `JobPosting` and `BroadcastEvent` items of a page, from JSON-LD and microdata.
+///
+/// The Indexing API only supports pages with one of them. Checking the page before
+/// sending avoids using quota on URLs Google ignores, and finds job postings whose
+/// `validThrough` has passed, which should be sent as `URL_DELETED`.
+/// ```rust
+/// use google_indexing_api::{Eligibility, StructuredData, Timestamp};
+/// let html = r#"<div itemscope itemtype="https://schema.org/JobPosting">
+///     <h1 itemprop="title">Engineer</h1>
+///     <meta itemprop="validThrough" content="2026-07-31">
+/// </div>"#;
+/// let data = StructuredData::extract(html);
+/// assert_eq!(data.job_postings[0].title.as_deref(), Some("Engineer"));
+/// let august = Timestamp::parse("2026-08-01T00:00:00Z").unwrap();
+/// assert_eq!(data.eligibility_at(august), Eligibility::Deleted);
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct StructuredData {
+    pub job_postings: Vec<JobPosting>,
+    pub broadcast_events: Vec<BroadcastEvent>,
+}
+
+impl StructuredData {
+    /// Reads the `application/ld+json` scripts and the microdata items of `html`.
+    /// Scripts that are not valid JSON are ignored, like Google does.
+    pub fn extract(html: &str) -> StructuredData {
+        let document = Html::parse_document(html);
+        let mut data = StructuredData::default();
+
+        let scripts = Selector::parse("script[type]").unwrap();
+        for script in document.select(&scripts) {
+            let is_json_ld = script
+                .value()
+                .attr("type")
+                .is_some_and(|t| t.trim().eq_ignore_ascii_case("application/ld+json"));
+            if !is_json_ld {
+                continue;
+            }
+            let json = script.text().collect::<String>();
+            if let Ok(value) = serde_json::from_str::<Value>(json.as_str()) {
+                data.collect_json_ld(&value);
+            }
+        }
+
+        let items = Selector::parse("[itemscope][itemtype]").unwrap();
+        for item in document.select(&items) {
+            let itemtype = item.value().attr("itemtype").unwrap_or_default();
+            let types = itemtype.split_whitespace().collect::<Vec<_>>();
+            if types.iter().any(|t| is_schema_type(t, "JobPosting")) {
+                data.job_postings.push(JobPosting {
+                    title: microdata_property(item, "title"),
+                    valid_through: microdata_property(item, "validThrough")
+                        .and_then(|v| parse_valid_through(v.as_str())),
+                });
+            }
+            if types.iter().any(|t| is_schema_type(t, "BroadcastEvent")) {
+                data.broadcast_events.push(BroadcastEvent {
+                    name: microdata_property(item, "name"),
+                    start_date: microdata_property(item, "startDate")
+                        .and_then(|v| Timestamp::parse_w3c(v.as_str())),
+                    end_date: microdata_property(item, "endDate")
+                        .and_then(|v| Timestamp::parse_w3c(v.as_str())),
+                });
+            }
+        }
+        data
+    }
+
+    /// Whether the page has no `JobPosting` and no `BroadcastEvent`.
+    pub fn is_empty(&self) -> bool {
+        self.job_postings.is_empty() && self.broadcast_events.is_empty()
+    }
+
+    /// What to send for the page as of now.
+    pub fn eligibility(&self) -> Eligibility {
+        self.eligibility_at(Timestamp::from(SystemTime::now()))
+    }
+
+    /// What to send for the page as of `now`: `Updated` if it has a `BroadcastEvent`
+    /// or a `JobPosting` that has not expired, `Deleted` if all its job postings have
+    /// expired, and `Skip` if it has neither.
+    pub fn eligibility_at(&self, now: Timestamp) -> Eligibility {
+        if !self.broadcast_events.is_empty()
+            || self.job_postings.iter().any(|job| !job.is_expired_at(now))
+        {
+            return Eligibility::Updated;
+        }
+        if self.job_postings.is_empty() {
+            Eligibility::Skip
+        } else {
+            Eligibility::Deleted
+        }
+    }
+
+    // 入れ子のオブジェクトと @graph もたどる。BroadcastEvent は VideoObject の publication に入る
+    fn collect_json_ld(&mut self, value: &Value) {
+        match value {
+            Value::Array(values) => {
+                for value in values {
+                    self.collect_json_ld(value);
+                }
+            }
+            Value::Object(object) => {
+                if has_json_ld_type(object, "JobPosting") {
+                    self.job_postings.push(JobPosting {
+                        title: json_ld_text(object, "title"),
+                        valid_through: json_ld_text(object, "validThrough")
+                            .and_then(|v| parse_valid_through(v.as_str())),
+                    });
+                }
+                if has_json_ld_type(object, "BroadcastEvent") {
+                    self.broadcast_events.push(BroadcastEvent {
+                        name: json_ld_text(object, "name"),
+                        start_date: json_ld_text(object, "startDate")
+                            .and_then(|v| Timestamp::parse_w3c(v.as_str())),
+                        end_date: json_ld_text(object, "endDate")
+                            .and_then(|v| Timestamp::parse_w3c(v.as_str())),
+                    });
+                }
+                for (key, value) in object {
+                    if key != "@context" {
+                        self.collect_json_ld(value);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+// "JobPosting"、"https://schema.org/JobPosting"、"schema:JobPosting" のどれでもよい
+fn is_schema_type(value: &str, name: &str) -> bool {
+    let value = value.trim();
+    let local = value.rsplit(['/', ':', '#']).next().unwrap_or(value);
+    if local != name {
+        return false;
+    }
+    let namespace = value[..value.len() - local.len()].to_ascii_lowercase();
+    namespace.is_empty() || namespace == "schema:" || namespace.contains("schema.org")
+}
+
+fn has_json_ld_type(object: &Map<String, Value>, name: &str) -> bool {
+    match object.get("@type") {
+        Some(Value::String(t)) => is_schema_type(t, name),
+        Some(Value::Array(types)) => types
+            .iter()
+            .any(|t| t.as_str().is_some_and(|t| is_schema_type(t, name))),
+        _ => false,
+    }
+}
+
+fn json_ld_text(object: &Map<String, Value>, key: &str) -> Option<String> {
+    match object.get(key)? {
+        Value::String(text) => Some(text.trim().to_string()),
+        // {"@value": "..."} の形
+        Value::Object(value) => value
+            .get("@value")
+            .and_then(Value::as_str)
+            .map(|text| text.trim().to_string()),
+        _ => None,
+    }
+}
+
+// item 自身のプロパティのうち最初のものの値。入れ子の item のプロパティは除く
+fn microdata_property(item: ElementRef, name: &str) -> Option<String> {
+    item.descendants()
+        .skip(1)
+        .filter_map(ElementRef::wrap)
+        .filter(|element| {
+            element
+                .value()
+                .attr("itemprop")
+                .is_some_and(|props| props.split_whitespace().any(|prop| prop == name))
+        })
+        .find(|element| {
+            element
+                .ancestors()
+                .filter_map(ElementRef::wrap)
+                .find(|ancestor| ancestor.value().attr("itemscope").is_some())
+                .is_some_and(|scope| scope.id() == item.id())
+        })
+        .map(|element| {
+            let value = element.value();
+            let attr = match value.name() {
+                "meta" => value.attr("content"),
+                "time" => value.attr("datetime"),
+                "data" | "meter" => value.attr("value"),
+                "a" | "link" | "area" => value.attr("href"),
+                _ => None,
+            };
+            match attr {
+                Some(attr) => attr.trim().to_string(),
+                None => element.text().collect::<String>().trim().to_string(),
+            }
+        })
+}
+
+// 時刻のない validThrough (YYYY-MM-DD, YYYY-MM, YYYY) はその日・月・年の終わりまで有効とする
+fn parse_valid_through(value: &str) -> Option<Timestamp> {
+    let value = value.trim();
+    let timestamp = Timestamp::parse_w3c(value)?;
+    let (year, month, day) = civil_from_days(timestamp.unix_seconds().div_euclid(86_400));
+    let end = match value.len() {
+        10 => days_from_civil(year, month, day) + 1,
+        7 if month < 12 => days_from_civil(year, month + 1, 1),
+        7 | 4 => days_from_civil(year + 1, 1, 1),
+        _ => return Some(timestamp),
+    };
+    Some(Timestamp::from_unix(end * 86_400, 0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(value: &str) -> Timestamp {
+        Timestamp::parse(value).unwrap()
+    }
+
+    #[test]
+    fn test_json_ld_job_posting_expiry() {
+        let html = r#"<html><head>
+<script type="application/ld+json">
+{
+  "@context": "https://schema.org/",
+  "@type": "JobPosting",
+  "title": "Software Engineer",
+  "validThrough": "2026-07-31T18:00:00+09:00",
+  "hiringOrganization": {"@type": "Organization", "name": "Example"}
+}
+</script>
+</head><body></body></html>"#;
+        let data = StructuredData::extract(html);
+        assert_eq!(
+            data.job_postings,
+            vec![JobPosting {
+                title: Some("Software Engineer".to_string()),
+                valid_through: Some(at("2026-07-31T09:00:00Z")),
+            }]
+        );
+        assert_eq!(
+            data.eligibility_at(at("2026-07-31T08:59:59Z")),
+            Eligibility::Updated
+        );
+        assert_eq!(
+            data.eligibility_at(at("2026-07-31T09:00:00Z")),
+            Eligibility::Deleted
+        );
+    }
+
+    #[test]
+    fn test_json_ld_graph_and_nested_broadcast_event() {
+        let html = r#"
+<script type="application/ld+json">{ broken json</script>
+<script type="application/ld+json">
+{
+  "@context": "https://schema.org",
+  "@graph": [
+    {"@type": "WebPage", "name": "Jobs"},
+    {"@type": ["JobPosting"], "title": "Old", "validThrough": "2026-06-30"},
+    {"@type": "schema:JobPosting", "title": "Current", "validThrough": "2026-07-31"}
+  ]
+}
+</script>
+<script type="application/ld+json">
+{
+  "@context": "https://schema.org",
+  "@type": "VideoObject",
+  "publication": {
+    "@type": "http://schema.org/BroadcastEvent",
+    "isLiveBroadcast": true,
+    "startDate": "2026-07-01T10:00:00Z",
+    "endDate": "2026-07-01T12:00:00Z"
+  }
+}
+</script>"#;
+        let data = StructuredData::extract(html);
+        assert_eq!(
+            data.job_postings
+                .iter()
+                .map(|job| (job.title.as_deref(), job.valid_through))
+                .collect::<Vec<_>>(),
+            vec![
+                (Some("Old"), Some(at("2026-07-01T00:00:00Z"))),
+                (Some("Current"), Some(at("2026-08-01T00:00:00Z"))),
+            ]
+        );
+        assert_eq!(
+            data.broadcast_events,
+            vec![BroadcastEvent {
+                name: None,
+                start_date: Some(at("2026-07-01T10:00:00Z")),
+                end_date: Some(at("2026-07-01T12:00:00Z")),
+            }]
+        );
+        // 終わった BroadcastEvent があるので、求人が全部期限切れでも UPDATED
+        assert_eq!(
+            data.eligibility_at(at("2026-09-01T00:00:00Z")),
+            Eligibility::Updated
+        );
+    }
+
+    #[test]
+    fn test_microdata() {
+        let html = r#"
+<div itemscope itemtype="https://schema.org/JobPosting">
+  <h1 itemprop="title"> Nurse </h1>
+  <div itemprop="hiringOrganization" itemscope itemtype="https://schema.org/Organization">
+    <span itemprop="name">Hospital</span>
+    <time itemprop="validThrough" datetime="2099-01-01">not this one</time>
+  </div>
+  <p>Apply by <time itemprop="validThrough" datetime="2026-07-31T17:00Z">July 31</time></p>
+</div>"#;
+        let data = StructuredData::extract(html);
+        assert_eq!(
+            data.job_postings,
+            vec![JobPosting {
+                title: Some("Nurse".to_string()),
+                valid_through: Some(at("2026-07-31T17:00:00Z")),
+            }]
+        );
+        assert_eq!(
+            data.eligibility_at(at("2026-08-01T00:00:00Z")),
+            Eligibility::Deleted
+        );
+    }
+
+    #[test]
+    fn test_pages_without_supported_types_are_skipped() {
+        for html in [
+            "<html><body><h1>About us</h1></body></html>",
+            r#"<script type="application/ld+json">{"@type": "Article", "headline": "JobPosting tips"}</script>"#,
+            r#"<div itemscope itemtype="https://example.com/JobPosting"></div>"#,
+            r#"<script type="text/plain">{"@type": "JobPosting"}</script>"#,
+        ] {
+            let data = StructuredData::extract(html);
+            assert!(data.is_empty(), "{}", html);
+            assert_eq!(data.eligibility(), Eligibility::Skip);
+            assert_eq!(data.eligibility().url_type(), None);
+        }
+        // 期限のない求人は有効
+        assert_eq!(
+            Eligibility::check(
+                r#"<script type="application/ld+json">{"@type": "JobPosting"}</script>"#
+            ),
+            Eligibility::Updated
+        );
+    }
+
+    #[test]
+    fn test_valid_through_without_time_lasts_to_the_end_of_the_period() {
+        for (value, end) in [
+            ("2026-07-31", "2026-08-01T00:00:00Z"),
+            ("2026-07", "2026-08-01T00:00:00Z"),
+            ("2026-12", "2027-01-01T00:00:00Z"),
+            ("2024-02", "2024-03-01T00:00:00Z"),
+            ("2026", "2027-01-01T00:00:00Z"),
+            ("2026-07-31T18:00:00+09:00", "2026-07-31T09:00:00Z"),
+        ] {
+            assert_eq!(parse_valid_through(value), Some(at(end)), "{}", value);
+        }
+        assert_eq!(parse_valid_through("2026-13"), None);
+    }
+}
@@ -1,13 +1,74 @@
+use crate::quota::QuotaBucket;
+use crate::retry::RetryPolicy;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
 use std::fmt::{Debug, Display, Formatter};
+use std::time::Duration;
+
+const ERROR_INFO_TYPE: &str = "type.googleapis.com/google.rpc.ErrorInfo";
+const QUOTA_FAILURE_TYPE: &str = "type.googleapis.com/google.rpc.QuotaFailure";
 
 /// Error type returned by all API calls.
+///
+/// Errors caused by another error (`reqwest`, `serde_json`, or a parsed Google error)
+/// return it from [`source`](std::error::Error::source). New variants may be added, so
+/// matches need a wildcard arm.
+#[non_exhaustive]
 pub enum GoogleApiError {
-    /// Failed to connect or send the request.
-    Connection(String),
-    /// The response could not be parsed (invalid JSON or a malformed batch response).
-    JsonParse(String),
-    /// The API returned an error status. Holds the HTTP status code and the response body.
-    HttpStatus(u16, String),
+    /// Failed to connect (DNS resolution, refused connection, proxy) or send the request.
+    Connection(reqwest::Error),
+    /// The request timed out (see `IndexingClientBuilder::timeout` and `connect_timeout`).
+    Timeout(reqwest::Error),
+    /// The TLS handshake failed, e.g. the server certificate could not be verified.
+    /// Only detected with reqwest's default rustls backend; with another backend these
+    /// failures are `Connection` errors.
+    Tls(reqwest::Error),
+    /// The connection failed while reading the response body.
+    BodyRead(reqwest::Error),
+    /// The response body is not the expected JSON. Holds the body and the parse error.
+    JsonParse {
+        body: String,
+        source: serde_json::Error,
+    },
+    /// The batch response is not a well-formed multipart response. `part` is the
+    /// 0-based index of the offending part in the response, if one part is to blame.
+    MalformedBatch {
+        part: Option<usize>,
+        message: String,
+    },
+    /// The API returned `401 Unauthorized`, even after getting a new token.
+    Unauthorized {
+        body: String,
+        error: Option<GoogleErrorBody>,
+    },
+    /// The API rejected the call because a quota or rate limit was exceeded
+    /// (HTTP 429 or `RESOURCE_EXHAUSTED`).
+    QuotaExceeded {
+        status: u16,
+        body: String,
+        error: Option<GoogleErrorBody>,
+    },
+    /// The request was not sent because the client-side [`QuotaGovernor`](crate::QuotaGovernor)
+    /// budget of `bucket` is used up. The budget frees up in `retry_in`.
+    QuotaBudgetExhausted {
+        bucket: QuotaBucket,
+        retry_in: Duration,
+    },
+    /// The [`QuotaStore`](crate::QuotaStore) could not read or write its counts.
+    QuotaStore(std::io::Error),
+    /// The [`SubmissionJournal`](crate::SubmissionJournal) could not read or write its file.
+    Journal(std::io::Error),
+    /// A sitemap is not valid XML, not a `urlset` or `sitemapindex`, or its gzip data is
+    /// corrupt (`sitemap` feature).
+    MalformedSitemap(String),
+    /// The API returned another error status. Holds the HTTP status code, the response
+    /// body, and the parsed Google error if the body is a Google error envelope.
+    HttpStatus {
+        status: u16,
+        body: String,
+        error: Option<GoogleErrorBody>,
+    },
     /// The request input was rejected before sending (e.g. batch size out of range).
     InvalidArgument(String),
 }
@@ -16,8 +77,30 @@ impl Display for GoogleApiError {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
             GoogleApiError::Connection(e) => write!(f, "connection error: {}", e),
-            GoogleApiError::JsonParse(e) => write!(f, "json parse error: {}", e),
-            GoogleApiError::HttpStatus(status, body) => {
+            GoogleApiError::Timeout(e) => write!(f, "timeout: {}", e),
+            GoogleApiError::Tls(e) => write!(f, "tls error: {}", e),
+            GoogleApiError::BodyRead(e) => write!(f, "failed to read the response body: {}", e),
+            GoogleApiError::JsonParse { body, source } => {
+                write!(f, "json parse error: {}: {}", source, body)
+            }
+            GoogleApiError::MalformedBatch { part, message } => match part {
+                Some(part) => write!(f, "malformed batch response (part {}): {}", part, message),
+                None => write!(f, "malformed batch response: {}", message),
+            },
+            GoogleApiError::Unauthorized { body, .. } => write!(f, "unauthorized: {}", body),
+            GoogleApiError::QuotaExceeded { status, body, .. } => {
+                write!(f, "quota exceeded (http status {}): {}", status, body)
+            }
+            GoogleApiError::QuotaBudgetExhausted { bucket, retry_in } => write!(
+                f,
+                "client-side {} quota budget exhausted, retry in {}s",
+                bucket,
+                retry_in.as_secs()
+            ),
+            GoogleApiError::QuotaStore(e) => write!(f, "quota store error: {}", e),
+            GoogleApiError::Journal(e) => write!(f, "journal error: {}", e),
+            GoogleApiError::MalformedSitemap(e) => write!(f, "malformed sitemap: {}", e),
+            GoogleApiError::HttpStatus { status, body, .. } => {
                 write!(f, "http status {}: {}", status, body)
             }
             GoogleApiError::InvalidArgument(e) => write!(f, "invalid argument: {}", e),
@@ -25,10 +108,381 @@ impl Display for GoogleApiError {
     }
 }
 
+impl GoogleApiError {
+    // エラーステータスのレスポンスから作る。Google のエラー形式なら解析して持たせる
+    pub(crate) fn http_status(status: u16, body: String) -> GoogleApiError {
+        let error = GoogleErrorBody::parse(body.as_str());
+        if status == 401 {
+            return GoogleApiError::Unauthorized { body, error };
+        }
+        let quota_exceeded = match &error {
+            Some(error) => error.is_quota_exceeded(),
+            None => status == 429,
+        };
+        if quota_exceeded {
+            return GoogleApiError::QuotaExceeded {
+                status,
+                body,
+                error,
+            };
+        }
+        GoogleApiError::HttpStatus {
+            status,
+            body,
+            error,
+        }
+    }
+
+    // reqwest のエラーを分類する。reqwest は TLS エラーを区別しないので、
+    // source をたどって rustls のエラーを探す
+    pub(crate) fn from_reqwest(e: reqwest::Error) -> GoogleApiError {
+        if e.is_timeout() {
+            return GoogleApiError::Timeout(e);
+        }
+        if e.is_body() || e.is_decode() {
+            return GoogleApiError::BodyRead(e);
+        }
+        if e.is_connect() && is_tls_failure(&e) {
+            return GoogleApiError::Tls(e);
+        }
+        GoogleApiError::Connection(e)
+    }
+
+    pub(crate) fn json_parse(body: String, source: serde_json::Error) -> GoogleApiError {
+        GoogleApiError::JsonParse { body, source }
+    }
+
+    pub(crate) fn malformed_batch(part: Option<usize>, message: String) -> GoogleApiError {
+        GoogleApiError::MalformedBatch { part, message }
+    }
+
+    /// The parsed Google error of an `HttpStatus`, `Unauthorized` or `QuotaExceeded` error.
+    pub fn google_error(&self) -> Option<&GoogleErrorBody> {
+        match self {
+            GoogleApiError::HttpStatus { error, .. }
+            | GoogleApiError::Unauthorized { error, .. }
+            | GoogleApiError::QuotaExceeded { error, .. } => error.as_ref(),
+            _ => None,
+        }
+    }
+
+    /// Whether the call was rejected because a quota or rate limit was exceeded, by the
+    /// API or by the client-side [`QuotaGovernor`](crate::QuotaGovernor).
+    pub fn is_quota_exceeded(&self) -> bool {
+        matches!(
+            self,
+            GoogleApiError::QuotaExceeded { .. } | GoogleApiError::QuotaBudgetExhausted { .. }
+        )
+    }
+
+    /// Whether the API denied access, typically because the account is not an owner of
+    /// the URL's Search Console property.
+    pub fn is_permission_denied(&self) -> bool {
+        match self {
+            GoogleApiError::HttpStatus { status, error, .. } => match error {
+                Some(error) => error.is_permission_denied(),
+                None => *status == 403,
+            },
+            _ => false,
+        }
+    }
+
+    /// Whether the call failed with a transient error that is worth retrying
+    /// (see [`RetryPolicy`] for the list).
+    pub fn is_retriable(&self) -> bool {
+        match self {
+            GoogleApiError::Connection(_)
+            | GoogleApiError::Timeout(_)
+            | GoogleApiError::BodyRead(_)
+            | GoogleApiError::QuotaExceeded { .. } => true,
+            GoogleApiError::HttpStatus { status, body, .. } => {
+                RetryPolicy::is_retriable_response(*status, body.as_str())
+            }
+            _ => false,
+        }
+    }
+}
+
 impl Debug for GoogleApiError {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         Display::fmt(self, f)
     }
 }
 
-impl std::error::Error for GoogleApiError {}
+impl std::error::Error for GoogleApiError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            GoogleApiError::Connection(e)
+            | GoogleApiError::Timeout(e)
+            | GoogleApiError::Tls(e)
+            | GoogleApiError::BodyRead(e) => Some(e),
+            GoogleApiError::JsonParse { source, .. } => Some(source),
+            GoogleApiError::QuotaStore(e) | GoogleApiError::Journal(e) => Some(e),
+            GoogleApiError::HttpStatus { error, .. }
+            | GoogleApiError::Unauthorized { error, .. }
+            | GoogleApiError::QuotaExceeded { error, .. } => error
+                .as_ref()
+                .map(|error| error as &(dyn std::error::Error + 'static)),
+            GoogleApiError::MalformedBatch { .. }
+            | GoogleApiError::MalformedSitemap(_)
+            | GoogleApiError::QuotaBudgetExhausted { .. }
+            | GoogleApiError::InvalidArgument(_) => None,
+        }
+    }
+}
+
+fn is_tls_failure(e: &reqwest::Error) -> bool {
+    let mut source = std::error::Error::source(e);
+    while let Some(error) = source {
+        if error.is::<rustls::Error>() {
+            return true;
+        }
+        // io::Error は source() で中のエラーを返さないので get_ref() でたどる
+        let mut inner = error
+            .downcast_ref::<std::io::Error>()
+            .and_then(|e| e.get_ref());
+        while let Some(e) = inner {
+            if e.is::<rustls::Error>() {
+                return true;
+            }
+            inner = e.downcast_ref::<std::io::Error>().and_then(|e| e.get_ref());
+        }
+        source = error.source();
+    }
+    false
+}
+
+/// The standard Google JSON error payload: `{"error": {"code", "message", "status", "details"}}`.
+#[derive(Default, Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct GoogleErrorBody {
+    /// HTTP status code.
+    #[serde(default)]
+    pub code: u16,
+    #[serde(default)]
+    pub message: String,
+    /// `google.rpc.Code` name such as `PERMISSION_DENIED` or `RESOURCE_EXHAUSTED`.
+    #[serde(default)]
+    pub status: String,
+    /// Extra error details (`google.rpc.ErrorInfo`, `google.rpc.QuotaFailure`, ...) as JSON.
+    /// See [`error_info`](Self::error_info) and [`quota_violations`](Self::quota_violations)
+    /// for typed access.
+    #[serde(default)]
+    pub details: Vec<Value>,
+}
+
+/// `google.rpc.ErrorInfo`: the machine-readable reason of an error.
+#[derive(Default, Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ErrorInfo {
+    /// e.g. `RATE_LIMIT_EXCEEDED` or `ACCESS_TOKEN_SCOPE_INSUFFICIENT`.
+    #[serde(default)]
+    pub reason: String,
+    /// e.g. `googleapis.com`.
+    #[serde(default)]
+    pub domain: String,
+    #[serde(default)]
+    pub metadata: HashMap<String, String>,
+}
+
+/// One violation of a `google.rpc.QuotaFailure`.
+#[derive(Default, Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct QuotaViolation {
+    /// The subject the quota applies to, e.g. `project:123456`.
+    #[serde(default)]
+    pub subject: String,
+    #[serde(default)]
+    pub description: String,
+}
+
+impl GoogleErrorBody {
+    /// Parses the `{"error": {...}}` envelope. Returns `None` if the body is not one.
+    pub fn parse(body: &str) -> Option<GoogleErrorBody> {
+        #[derive(Deserialize)]
+        struct Envelope {
+            error: GoogleErrorBody,
+        }
+        serde_json::from_str::<Envelope>(body)
+            .ok()
+            .map(|envelope| envelope.error)
+    }
+
+    /// The `google.rpc.ErrorInfo` entries of `details`.
+    pub fn error_info(&self) -> Vec<ErrorInfo> {
+        self.details_of(ERROR_INFO_TYPE)
+            .filter_map(|detail| serde_json::from_value(detail.clone()).ok())
+            .collect()
+    }
+
+    /// The violations of the `google.rpc.QuotaFailure` entries of `details`.
+    pub fn quota_violations(&self) -> Vec<QuotaViolation> {
+        #[derive(Deserialize)]
+        struct QuotaFailure {
+            #[serde(default)]
+            violations: Vec<QuotaViolation>,
+        }
+        self.details_of(QUOTA_FAILURE_TYPE)
+            .filter_map(|detail| serde_json::from_value::<QuotaFailure>(detail.clone()).ok())
+            .flat_map(|failure| failure.violations)
+            .collect()
+    }
+
+    /// Whether any `ErrorInfo` detail has this `reason`.
+    pub fn has_reason(&self, reason: &str) -> bool {
+        self.error_info().iter().any(|info| info.reason == reason)
+    }
+
+    /// `RESOURCE_EXHAUSTED`, HTTP 429, a `QuotaFailure` detail, or a rate limit reason.
+    pub fn is_quota_exceeded(&self) -> bool {
+        self.status == "RESOURCE_EXHAUSTED"
+            || self.code == 429
+            || !self.quota_violations().is_empty()
+            || self.has_reason("RATE_LIMIT_EXCEEDED")
+    }
+
+    /// `PERMISSION_DENIED`, or HTTP 403 without a `status` that is not a quota error.
+    pub fn is_permission_denied(&self) -> bool {
+        if self.status.is_empty() {
+            return self.code == 403 && !self.is_quota_exceeded();
+        }
+        self.status == "PERMISSION_DENIED"
+    }
+
+    /// Whether the error is transient and worth retrying (see [`RetryPolicy`]).
+    pub fn is_retriable(&self) -> bool {
+        RetryPolicy::is_retriable_status(self.code, self.status.as_str())
+    }
+
+    fn details_of<'a>(&'a self, type_url: &'a str) -> impl Iterator<Item = &'a Value> + 'a {
+        self.details
+            .iter()
+            .filter(move |detail| detail["@type"] == type_url)
+    }
+
+    // エンベロープでないボディでも status_code と本文から組み立てる
+    pub(crate) fn from_response(status_code: u16, body: &str) -> GoogleErrorBody {
+        let mut error = GoogleErrorBody::parse(body).unwrap_or_else(|| GoogleErrorBody {
+            message: body.to_string(),
+            ..GoogleErrorBody::default()
+        });
+        if error.code == 0 {
+            error.code = status_code;
+        }
+        error
+    }
+}
+
+impl std::error::Error for GoogleErrorBody {}
+
+impl Display for GoogleErrorBody {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if self.status.is_empty() {
+            write!(f, "{}: {}", self.code, self.message)
+        } else {
+            write!(f, "{} {}: {}", self.code, self.status, self.message)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_google_error_body() {
+        let error = GoogleErrorBody::parse(
+            r#"{"error":{"code":403,"message":"Permission denied. Failed to verify the URL ownership.","status":"PERMISSION_DENIED","details":[{"@type":"type.googleapis.com/google.rpc.ErrorInfo","reason":"ACCESS_DENIED"}]}}"#,
+        )
+        .unwrap();
+        assert_eq!(error.code, 403);
+        assert_eq!(error.status, "PERMISSION_DENIED");
+        assert_eq!(error.details.len(), 1);
+        assert_eq!(error.details[0]["reason"], "ACCESS_DENIED");
+        assert_eq!(
+            error.to_string(),
+            "403 PERMISSION_DENIED: Permission denied. Failed to verify the URL ownership."
+        );
+        assert_eq!(GoogleErrorBody::parse("rate limit"), None);
+    }
+
+    #[test]
+    fn test_quota_exceeded_error() {
+        let error = GoogleApiError::http_status(
+            429,
+            r#"{"error":{"code":429,"message":"Quota exceeded for quota metric 'Publish requests' and limit 'Publish requests per day'","status":"RESOURCE_EXHAUSTED","details":[{"@type":"type.googleapis.com/google.rpc.ErrorInfo","reason":"RATE_LIMIT_EXCEEDED","domain":"googleapis.com","metadata":{"quota_metric":"indexing.googleapis.com/v3_publish_requests","service":"indexing.googleapis.com"}},{"@type":"type.googleapis.com/google.rpc.QuotaFailure","violations":[{"subject":"project:123456","description":"Publish requests per day"}]}]}}"#
+                .to_string(),
+        );
+        assert!(matches!(
+            error,
+            GoogleApiError::QuotaExceeded { status: 429, .. }
+        ));
+        assert!(error.is_quota_exceeded());
+        assert!(!error.is_permission_denied());
+        assert!(error.is_retriable());
+        // source() から解析済みの Google エラーをたどれる
+        let source = std::error::Error::source(&error).unwrap();
+        assert_eq!(
+            source.downcast_ref::<GoogleErrorBody>().unwrap().status,
+            "RESOURCE_EXHAUSTED"
+        );
+
+        let body = error.google_error().unwrap();
+        let info = body.error_info();
+        assert_eq!(info.len(), 1);
+        assert_eq!(info[0].reason, "RATE_LIMIT_EXCEEDED");
+        assert_eq!(
+            info[0].metadata["quota_metric"],
+            "indexing.googleapis.com/v3_publish_requests"
+        );
+        assert_eq!(
+            body.quota_violations(),
+            vec![QuotaViolation {
+                subject: "project:123456".to_string(),
+                description: "Publish requests per day".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_permission_denied_error() {
+        let error = GoogleApiError::http_status(
+            403,
+            r#"{"error":{"code":403,"message":"Permission denied. Failed to verify the URL ownership.","status":"PERMISSION_DENIED"}}"#
+                .to_string(),
+        );
+        assert!(error.is_permission_denied());
+        assert!(!error.is_quota_exceeded());
+        assert!(!error.is_retriable());
+
+        // Google のエラー形式でないボディはステータスコードで判定する
+        let error = GoogleApiError::http_status(403, "forbidden".to_string());
+        assert!(error.google_error().is_none());
+        assert!(error.is_permission_denied());
+        assert!(GoogleApiError::http_status(503, String::new()).is_retriable());
+        assert!(!GoogleApiError::InvalidArgument(String::new()).is_retriable());
+    }
+
+    #[test]
+    fn test_unauthorized_error() {
+        let error = GoogleApiError::http_status(
+            401,
+            r#"{"error":{"code":401,"message":"Request had invalid authentication credentials.","status":"UNAUTHENTICATED"}}"#
+                .to_string(),
+        );
+        match &error {
+            GoogleApiError::Unauthorized {
+                error: Some(body), ..
+            } => {
+                assert_eq!(body.status, "UNAUTHENTICATED")
+            }
+            other => panic!("Unauthorized であるべき: {:?}", other),
+        }
+        assert!(!error.is_retriable());
+    }
+
+    #[test]
+    fn test_error_body_from_plain_text_response() {
+        let error = GoogleErrorBody::from_response(503, "unavailable");
+        assert_eq!(error.code, 503);
+        assert_eq!(error.message, "unavailable");
+        assert_eq!(error.status, "");
+    }
+}
@@ -1,270 +1,547 @@
+use crate::auth::TokenProvider;
 use crate::error::GoogleApiError;
-use crate::{ResponseGoogleIndexingBatch, UrlNotificationsType};
-use reqwest::header::{AUTHORIZATION, CONTENT_TYPE};
+use crate::quota::{QuotaBucket, QuotaGovernor};
+use crate::retry::{parse_retry_after, retry_delay, retry_hint, RetryPolicy};
+use crate::{ResponseGoogleIndexingBatch, ResponseUrlNotificationMetadata, UrlNotification};
+use reqwest::header::{HeaderMap, AUTHORIZATION, CONTENT_TYPE};
+use reqwest::{RequestBuilder, StatusCode};
 use serde_json::json;
-use std::collections::{HashMap, HashSet};
+use std::collections::HashMap;
 use std::fmt::Debug;
+use std::sync::Arc;
+use std::time::Duration;
+use urlencoding::encode;
+
+// バッチAPIの上限は100件
+pub(crate) const MAX_BATCH_URLS: usize = 100;
+
+// reqwest::Client は内部で Arc を持つので clone しても接続プールは共有される
+#[derive(Clone, Debug)]
+pub(crate) struct HttpClient {
+    client: reqwest::Client,
+    retry: RetryPolicy,
+    quota: Option<Arc<QuotaGovernor>>,
+}
 
-#[derive(Default, Debug)]
-pub(crate) struct HttpClient {}
-
-impl HttpClient {
-    pub async fn get<T>(token: &str, url: &str) -> Result<T, GoogleApiError>
-    where
-        T: for<'de> serde::Deserialize<'de>,
-    {
-        let mut response = reqwest::Client::new()
-            .get(url.to_string())
-            .header("Authorization", format!("Bearer {}", token));
+impl Default for HttpClient {
+    fn default() -> Self {
+        HttpClient::new(reqwest::Client::new(), RetryPolicy::none())
+    }
+}
 
-        response = response.header("Accept", "application/json");
+// クォータに数えるバケットと件数
+type Meter = (QuotaBucket, usize);
 
-        let response = response.send().await;
+// 送信してボディまで読み終えたレスポンス
+struct HttpReply {
+    status: StatusCode,
+    headers: HeaderMap,
+    body: String,
+}
 
-        if response.is_err() {
-            return Err(GoogleApiError::Connection(
-                response.err().unwrap().to_string(),
-            ));
-        }
-        let response = response.unwrap();
-        let status = response.status();
-        let value = response.text().await;
-        if !status.is_success() {
-            return Err(GoogleApiError::HttpStatus(
-                status.as_u16(),
-                value.unwrap_or_default(),
-            ));
-        }
-        if value.is_err() {
-            return Err(GoogleApiError::JsonParse(value.err().unwrap().to_string()));
+impl HttpClient {
+    pub fn new(client: reqwest::Client, retry: RetryPolicy) -> Self {
+        HttpClient {
+            client,
+            retry,
+            quota: None,
         }
-        let value = value.unwrap();
-        let parse = serde_json::from_str(value.as_str());
-        if parse.is_err() {
-            return Err(GoogleApiError::JsonParse(value));
+    }
+
+    pub fn with_quota_governor(mut self, quota: Option<Arc<QuotaGovernor>>) -> Self {
+        self.quota = quota;
+        self
+    }
+
+    // 送信前にクォータの予算を取る。get は metadata、post は publish にだけ使われる
+    async fn acquire(&self, meter: Option<Meter>) -> Result<(), GoogleApiError> {
+        match (&self.quota, meter) {
+            (Some(quota), Some((bucket, cost))) => quota.acquire(bucket, cost as u32).await,
+            _ => Ok(()),
         }
+    }
 
-        Ok(parse.unwrap())
+    pub async fn get<T>(&self, tokens: &dyn TokenProvider, url: &str) -> Result<T, GoogleApiError>
+    where
+        T: for<'de> serde::Deserialize<'de>,
+    {
+        self.get_json(tokens, url, Some((QuotaBucket::Metadata, 1)))
+            .await
+    }
+    // Indexing API 以外 (Search Console など) への GET。クォータを数えない
+    pub async fn get_unmetered<T>(
+        &self,
+        tokens: &dyn TokenProvider,
+        url: &str,
+    ) -> Result<T, GoogleApiError>
+    where
+        T: for<'de> serde::Deserialize<'de>,
+    {
+        self.get_json(tokens, url, None).await
+    }
+    async fn get_json<T>(
+        &self,
+        tokens: &dyn TokenProvider,
+        url: &str,
+        meter: Option<Meter>,
+    ) -> Result<T, GoogleApiError>
+    where
+        T: for<'de> serde::Deserialize<'de>,
+    {
+        let reply = self
+            .send(tokens, meter, |client, token| {
+                client
+                    .get(url.to_string())
+                    .header("Authorization", format!("Bearer {}", token))
+                    .header("Accept", "application/json")
+            })
+            .await?;
+        parse_json(reply)
     }
-    pub async fn post<T, U>(token: &str, url: &str, params: U) -> Result<T, GoogleApiError>
+    pub async fn post<T, U>(
+        &self,
+        tokens: &dyn TokenProvider,
+        url: &str,
+        params: U,
+    ) -> Result<T, GoogleApiError>
     where
         T: for<'de> serde::Deserialize<'de>,
         U: serde::Serialize + std::fmt::Debug,
     {
-        let mut response = reqwest::Client::new().post(url.to_string());
-        if !token.is_empty() {
-            response = response.header("Authorization", format!("Bearer {}", token))
-        }
-        let response = response.json(&json!(params)).send().await;
+        let params = json!(params);
+        let reply = self
+            .send(tokens, Some((QuotaBucket::Publish, 1)), |client, token| {
+                let mut request = client.post(url.to_string());
+                if !token.is_empty() {
+                    request = request.header("Authorization", format!("Bearer {}", token))
+                }
+                request.json(&params)
+            })
+            .await?;
+        parse_json(reply)
+    }
 
-        if response.is_err() {
-            return Err(GoogleApiError::Connection(
-                response.err().unwrap().to_string(),
-            ));
-        }
-        let response = response.unwrap();
-        let status = response.status();
-        let value = response.text().await;
-        if !status.is_success() {
-            return Err(GoogleApiError::HttpStatus(
-                status.as_u16(),
-                value.unwrap_or_default(),
-            ));
-        }
-        if value.is_err() {
-            return Err(GoogleApiError::JsonParse(value.err().unwrap().to_string()));
-        }
-        let value = value.unwrap();
-        let parse = serde_json::from_str(value.as_str());
-        if parse.is_err() {
-            return Err(GoogleApiError::JsonParse(value));
+    // トークンを取得してリクエストを送り、ボディまで読む。
+    // 401 はトークンの失効が考えられるので、プロバイダに破棄させて一度だけ取り直して再送する。
+    // 接続エラーと一時的なエラー応答は RetryPolicy に従って待ってから再送する。
+    // 再送も Google のクォータに数えられるので、送るたびに meter の分の予算を取る
+    async fn send<F>(
+        &self,
+        tokens: &dyn TokenProvider,
+        meter: Option<Meter>,
+        build: F,
+    ) -> Result<HttpReply, GoogleApiError>
+    where
+        F: Fn(&reqwest::Client, &str) -> RequestBuilder,
+    {
+        let mut refreshed = false;
+        let mut attempt = 1;
+        loop {
+            self.acquire(meter).await?;
+            let token = tokens.token().await?;
+            let response = match build(&self.client, token.token()).send().await {
+                Ok(response) => response,
+                Err(e) => {
+                    if e.is_timeout() || e.is_connect() {
+                        if let Some(delay) = self.retry.delay(attempt, None) {
+                            tokio::time::sleep(delay).await;
+                            attempt += 1;
+                            continue;
+                        }
+                    }
+                    return Err(GoogleApiError::from_reqwest(e));
+                }
+            };
+            let status = response.status();
+            if status == StatusCode::UNAUTHORIZED && !refreshed {
+                refreshed = true;
+                tokens.invalidate().await;
+                continue;
+            }
+            let headers = response.headers().clone();
+            let body = response.text().await;
+            if body.is_err() {
+                return Err(GoogleApiError::BodyRead(body.err().unwrap()));
+            }
+            let body = body.unwrap();
+            if !status.is_success()
+                && RetryPolicy::is_retriable_response(status.as_u16(), body.as_str())
+            {
+                let hint = retry_hint(&headers, body.as_str());
+                if let Some(delay) = self.retry.delay(attempt, hint) {
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                    continue;
+                }
+            }
+            return Ok(HttpReply {
+                status,
+                headers,
+                body,
+            });
         }
-
-        Ok(parse.unwrap())
     }
 
-    pub async fn execute(
-        token: &str,
-        urls: Vec<String>,
-        url_type: UrlNotificationsType,
+    pub async fn execute_url(
+        &self,
+        endpoint: &str,
+        tokens: &dyn TokenProvider,
+        notifications: Vec<UrlNotification>,
     ) -> Result<Vec<ResponseGoogleIndexingBatch>, GoogleApiError> {
-        Self::execute_url(
-            "https://indexing.googleapis.com/batch",
-            token,
-            urls,
-            url_type,
-        )
-        .await
+        let parts = notifications
+            .iter()
+            .map(publish_part)
+            .collect::<Vec<BatchRequestPart>>();
+        let responses = self
+            .execute_parts(endpoint, tokens, QuotaBucket::Publish, parts)
+            .await?;
+        Ok(notifications
+            .into_iter()
+            .zip(responses)
+            .map(|(notification, part)| ResponseGoogleIndexingBatch {
+                url: notification.url,
+                status_code: part.status_code,
+                value: part.body,
+                attempts: part.attempts,
+                retry_error: part.retry_error,
+            })
+            .collect())
     }
 
-    // endpoint を差し替え可能にしているのはモックサーバでテストするため
-    async fn execute_url(
+    // urlNotifications/metadata の GET をまとめて送る。結果は urls の順
+    pub async fn execute_metadata_url(
+        &self,
         endpoint: &str,
-        token: &str,
+        tokens: &dyn TokenProvider,
         urls: Vec<String>,
-        url_type: UrlNotificationsType,
-    ) -> Result<Vec<ResponseGoogleIndexingBatch>, GoogleApiError> {
-        // バッチAPIの上限は100件
-        if urls.is_empty() || urls.len() > 100 {
+    ) -> Result<Vec<Result<ResponseUrlNotificationMetadata, GoogleApiError>>, GoogleApiError> {
+        let parts = urls
+            .iter()
+            .map(|url| metadata_part(url))
+            .collect::<Vec<BatchRequestPart>>();
+        let responses = self
+            .execute_parts(endpoint, tokens, QuotaBucket::Metadata, parts)
+            .await?;
+        // 再送できなかった part は、最後に受け取った part のエラーを返す
+        Ok(responses
+            .into_iter()
+            .map(|part| {
+                if !(200..300).contains(&part.status_code) {
+                    return Err(GoogleApiError::http_status(part.status_code, part.body));
+                }
+                let parse = serde_json::from_str(part.body.as_str());
+                if parse.is_err() {
+                    return Err(GoogleApiError::json_parse(part.body, parse.err().unwrap()));
+                }
+                Ok(parse.unwrap())
+            })
+            .collect())
+    }
+
+    async fn execute_parts(
+        &self,
+        endpoint: &str,
+        tokens: &dyn TokenProvider,
+        bucket: QuotaBucket,
+        parts: Vec<BatchRequestPart>,
+    ) -> Result<Vec<BatchResponsePart>, GoogleApiError> {
+        if parts.is_empty() || parts.len() > MAX_BATCH_URLS {
             return Err(GoogleApiError::InvalidArgument(format!(
-                "batch accepts 1 to 100 urls, got {}",
-                urls.len()
+                "batch accepts 1 to {} urls, got {}",
+                MAX_BATCH_URLS,
+                parts.len()
             )));
         }
-        let (key_values, send_body) = build_batch_request_body(&urls, &url_type);
-        // リクエストの送信とレスポンスの取得
-        let response = reqwest::Client::new()
-            .post(endpoint)
-            .header(
-                CONTENT_TYPE,
-                format!("multipart/mixed; boundary={}", BATCH_BOUNDARY),
-            )
-            .header(AUTHORIZATION, format!("Bearer {}", token))
-            .body(send_body)
-            .send()
-            .await;
+        let mut results = self.execute_once(endpoint, tokens, bucket, &parts).await?;
+        if !self.retry.retries_batch_parts() {
+            return Ok(results);
+        }
 
-        if response.is_err() {
-            return Err(GoogleApiError::Connection(
-                response.err().unwrap().to_string(),
-            ));
+        // 一時的なエラーになった part だけを集めて再送し、結果を元の位置に戻す
+        let mut attempt = 1;
+        loop {
+            let failed = results
+                .iter()
+                .enumerate()
+                .filter(|(_, q)| q.is_retriable())
+                .map(|(i, _)| i)
+                .collect::<Vec<usize>>();
+            if failed.is_empty() {
+                break;
+            }
+            // 429 などの part が待ち時間を指定していれば、そのうち最も長いものに従う
+            let hint = failed.iter().filter_map(|i| results[*i].retry_after).max();
+            let Some(delay) = self.retry.delay(attempt, hint) else {
+                break;
+            };
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+            let retry_parts = failed
+                .iter()
+                .map(|i| parts[*i].clone())
+                .collect::<Vec<BatchRequestPart>>();
+            // 予算が無いか再送そのものが失敗した場合は、それまでの結果にその理由を付けて返す
+            let retried = match self
+                .execute_once(endpoint, tokens, bucket, &retry_parts)
+                .await
+            {
+                Ok(retried) => retried,
+                Err(e) => {
+                    let error = Arc::new(e);
+                    for index in failed {
+                        results[index].retry_error = Some(error.clone());
+                    }
+                    break;
+                }
+            };
+            for (index, mut result) in failed.into_iter().zip(retried) {
+                result.attempts = attempt;
+                results[index] = result;
+            }
         }
-        let response = response.unwrap();
+        Ok(results)
+    }
 
-        let status = response.status();
-        if !status.is_success() {
-            let body = response.text().await.unwrap_or_default();
-            return Err(GoogleApiError::HttpStatus(status.as_u16(), body));
+    // 1回分のバッチリクエストを送る。結果はリクエストの順に並べて返す
+    async fn execute_once(
+        &self,
+        endpoint: &str,
+        tokens: &dyn TokenProvider,
+        bucket: QuotaBucket,
+        parts: &[BatchRequestPart],
+    ) -> Result<Vec<BatchResponsePart>, GoogleApiError> {
+        let (key_values, send_body) = build_batch_request_body(parts);
+        // リクエストの送信とレスポンスの取得。バッチの URL はそれぞれ 1 件としてクォータに数えられる
+        let reply = self
+            .send(tokens, Some((bucket, parts.len())), |client, token| {
+                client
+                    .post(endpoint)
+                    .header(
+                        CONTENT_TYPE,
+                        format!("multipart/mixed; boundary={}", BATCH_BOUNDARY),
+                    )
+                    .header(AUTHORIZATION, format!("Bearer {}", token))
+                    .body(send_body.clone())
+            })
+            .await?;
+
+        if !reply.status.is_success() {
+            return Err(GoogleApiError::http_status(
+                reply.status.as_u16(),
+                reply.body,
+            ));
         }
-        let headers = response.headers();
 
         let mut content_type = "".to_string();
-        if let Some(value) = headers.get("Content-Type") {
+        if let Some(value) = reply.headers.get("Content-Type") {
             content_type = match value.to_str() {
                 Ok(v) => v.to_string(),
                 Err(e) => {
-                    return Err(GoogleApiError::JsonParse(format!(
-                        "batch response Content-Type header is not valid text: {}",
-                        e
-                    )))
+                    return Err(GoogleApiError::malformed_batch(
+                        None,
+                        format!(
+                            "batch response Content-Type header is not valid text: {}",
+                            e
+                        ),
+                    ))
                 }
             };
         }
 
-        // レスポンスのボディの読み取り
-        let body = response.text().await;
-        if body.is_err() {
-            return Err(GoogleApiError::Connection(body.err().unwrap().to_string()));
-        }
-        let body = body.unwrap();
+        let body = reply.body;
         let boundary = get_boundary(content_type.as_str());
         if boundary.is_empty() {
-            return Err(GoogleApiError::JsonParse(format!(
-                "batch response is not multipart/mixed with a boundary: Content-Type=\"{}\"",
-                content_type
-            )));
+            return Err(GoogleApiError::malformed_batch(
+                None,
+                format!(
+                    "batch response is not multipart/mixed with a boundary: Content-Type=\"{}\"",
+                    content_type
+                ),
+            ));
         }
 
-        let mut batch_response = vec![];
+        let mut batch_response: Vec<Option<BatchResponsePart>> =
+            parts.iter().map(|_| None).collect();
 
         let boundary_bodies = body_boundary_split(body.as_str(), boundary.as_str());
         if boundary_bodies.is_empty() {
-            return Err(GoogleApiError::JsonParse(format!(
-                "batch response body has no closing boundary: {}",
-                body
-            )));
+            return Err(GoogleApiError::malformed_batch(
+                None,
+                format!("batch response body has no closing boundary: {}", body),
+            ));
         }
-        let mut seen_ids = HashSet::new();
-        for boundary_body in boundary_bodies {
+        for (part, boundary_body) in boundary_bodies.into_iter().enumerate() {
             let http = plane_http_to_response(boundary_body.as_str());
-            let mut http_url = None;
-            for (id, url) in &key_values {
-                if id == &http.content_id {
-                    http_url = Some(url.to_string());
-                    break;
-                }
-            }
+            let index = key_values.iter().position(|(id, _)| id == &http.content_id);
             // リクエストのどの Content-ID とも一致しなければ、結果と URL の対応が取れない
-            let Some(http_url) = http_url else {
-                return Err(GoogleApiError::JsonParse(format!(
-                    "batch response has an unknown Content-ID \"{}\": {}",
-                    http.content_id, body
-                )));
+            let Some(index) = index else {
+                return Err(GoogleApiError::malformed_batch(
+                    Some(part),
+                    format!(
+                        "batch response has an unknown Content-ID \"{}\": {}",
+                        http.content_id, body
+                    ),
+                ));
             };
-            if !seen_ids.insert(http.content_id.to_string()) {
-                return Err(GoogleApiError::JsonParse(format!(
-                    "batch response has a duplicated Content-ID \"{}\": {}",
-                    http.content_id, body
-                )));
+            if batch_response[index].is_some() {
+                return Err(GoogleApiError::malformed_batch(
+                    Some(part),
+                    format!(
+                        "batch response has a duplicated Content-ID \"{}\": {}",
+                        http.content_id, body
+                    ),
+                ));
             }
             if http.status_code == 0 {
-                return Err(GoogleApiError::JsonParse(format!(
-                    "batch response part has no parsable HTTP status line: {}",
-                    boundary_body
-                )));
+                return Err(GoogleApiError::malformed_batch(
+                    Some(part),
+                    format!(
+                        "batch response part has no parsable HTTP status line: {}",
+                        boundary_body
+                    ),
+                ));
             }
-            batch_response.push(ResponseGoogleIndexingBatch {
-                url: http_url,
+            let retry_after = http
+                .header
+                .iter()
+                .find(|(key, _)| key.trim().eq_ignore_ascii_case("Retry-After"))
+                .and_then(|(_, value)| parse_retry_after(value))
+                .or_else(|| retry_delay(http.content.as_str()));
+            batch_response[index] = Some(BatchResponsePart {
                 status_code: http.status_code,
-                value: http.content,
+                body: http.content,
+                attempts: 1,
+                retry_after,
+                retry_error: None,
             });
         }
-        if batch_response.len() != urls.len() {
-            return Err(GoogleApiError::JsonParse(format!(
-                "batch response has {} parts but {} urls were requested: {}",
-                batch_response.len(),
-                urls.len(),
-                body
-            )));
+        let received = batch_response.iter().filter(|q| q.is_some()).count();
+        if received != parts.len() {
+            return Err(GoogleApiError::malformed_batch(
+                None,
+                format!(
+                    "batch response has {} parts but {} urls were requested: {}",
+                    received,
+                    parts.len(),
+                    body
+                ),
+            ));
         }
 
-        Ok(batch_response)
+        Ok(batch_response.into_iter().flatten().collect())
     }
 }
+fn parse_json<T>(reply: HttpReply) -> Result<T, GoogleApiError>
+where
+    T: for<'de> serde::Deserialize<'de>,
+{
+    if !reply.status.is_success() {
+        return Err(GoogleApiError::http_status(
+            reply.status.as_u16(),
+            reply.body,
+        ));
+    }
+    let parse = serde_json::from_str(reply.body.as_str());
+    if parse.is_err() {
+        return Err(GoogleApiError::json_parse(reply.body, parse.err().unwrap()));
+    }
+
+    Ok(parse.unwrap())
+}
+
+// バッチに含める 1 件分の HTTP リクエスト
+#[derive(Debug, Clone)]
+struct BatchRequestPart {
+    // 対象の URL (Content-ID との対応表に使う)
+    url: String,
+    method: &'static str,
+    path: String,
+    body: Option<String>,
+}
+
+// バッチレスポンスの 1 件分
+#[derive(Debug)]
+struct BatchResponsePart {
+    status_code: u16,
+    body: String,
+    attempts: u32,
+    // part のヘッダーかボディで指定された、再送までの待ち時間
+    retry_after: Option<Duration>,
+    // 再送できなかった理由。複数の part で共有する
+    retry_error: Option<Arc<GoogleApiError>>,
+}
+
+impl BatchResponsePart {
+    fn is_retriable(&self) -> bool {
+        !(200..300).contains(&self.status_code)
+            && RetryPolicy::is_retriable_response(self.status_code, self.body.as_str())
+    }
+}
+
+fn publish_part(notification: &UrlNotification) -> BatchRequestPart {
+    BatchRequestPart {
+        url: notification.url.to_string(),
+        method: "POST",
+        path: "/v3/urlNotifications:publish".to_string(),
+        body: Some(
+            json!({
+                "url": notification.url,
+                "type": notification.url_type.to_string(),
+            })
+            .to_string(),
+        ),
+    }
+}
+
+fn metadata_part(url: &str) -> BatchRequestPart {
+    BatchRequestPart {
+        url: url.to_string(),
+        method: "GET",
+        path: format!("/v3/urlNotifications/metadata?url={}", encode(url)),
+        body: None,
+    }
+}
+
 // マルチパートフォームデータのバウンダリー
 const BATCH_BOUNDARY: &str = "===============7330845974216740156==";
 
 // バッチ用の multipart/mixed リクエストボディを組み立てる。
 // 戻り値は (Content-ID と URL の対応表, ボディ文字列)
-fn build_batch_request_body(
-    urls: &[String],
-    url_type: &UrlNotificationsType,
-) -> (Vec<(String, String)>, String) {
+fn build_batch_request_body(parts: &[BatchRequestPart]) -> (Vec<(String, String)>, String) {
     let boundary2 = format!("--{}", BATCH_BOUNDARY);
-    fn make_row(index: isize, url: &str, url_type: &str) -> (String, String, String) {
-        let body = json!({
-            "url":url,
-            "type":url_type,
-        })
-        .to_string();
+    fn make_row(index: usize, part: &BatchRequestPart) -> (String, String, String) {
         let id = format!("b29c5de2-0db4-490b-b421-6a51b598bd23+{}", index + 1);
-        (
-            id.to_string(),
-            url.to_string(),
-            [
-                "Content-Type: application/http",
-                "Content-Transfer-Encoding: binary",
-                format!("Content-ID: <{}>", id).as_str(),
-                "",
-                "POST /v3/urlNotifications:publish HTTP/1.1",
-                "Content-Type: application/json",
-                "accept: application/json",
-                format!("content-length: {}", body.len()).as_str(),
-                "",
-                body.as_str(),
-            ]
-            .join("\r\n"),
-        )
+        let mut rows = vec![
+            "Content-Type: application/http".to_string(),
+            "Content-Transfer-Encoding: binary".to_string(),
+            format!("Content-ID: <{}>", id),
+            "".to_string(),
+            format!("{} {} HTTP/1.1", part.method, part.path),
+        ];
+        match &part.body {
+            Some(body) => {
+                rows.push("Content-Type: application/json".to_string());
+                rows.push("accept: application/json".to_string());
+                rows.push(format!("content-length: {}", body.len()));
+                rows.push("".to_string());
+                rows.push(body.to_string());
+            }
+            // ボディの無いリクエストはヘッダーの後の空行で終わる
+            None => {
+                rows.push("accept: application/json".to_string());
+                rows.push("".to_string());
+            }
+        }
+        (id, part.url.to_string(), rows.join("\r\n"))
     }
 
-    let send_data1 = urls
+    let send_data1 = parts
         .iter()
         .enumerate()
-        .map(|(i, url)| make_row(i as isize, url, url_type.to_string().as_str()))
+        .map(|(i, q)| make_row(i, q))
         .collect::<Vec<(String, String, String)>>();
 
     let key_values = send_data1
-        .clone()
         .iter()
         .map(|q| (q.0.to_string(), q.1.to_string()))
         .collect::<Vec<(String, String)>>();
@@ -399,9 +676,25 @@ impl HttpResponse {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::auth::{AccessToken, StaticTokenProvider};
+    use crate::quota::QuotaLimit;
+    use crate::UrlNotificationsType;
     use httpmock::prelude::*;
+    use httpmock::Mock;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
 
     const BOUNDARY: &str = "batch_abc123";
+
+    fn notifications(urls: &[&str], url_type: UrlNotificationsType) -> Vec<UrlNotification> {
+        urls.iter()
+            .map(|url| UrlNotification::new(url, url_type.clone()))
+            .collect()
+    }
+
+    fn test_token() -> StaticTokenProvider {
+        StaticTokenProvider::new("test-token")
+    }
     // make_row が生成する Content-ID の接頭辞と同じ値
     const REQUEST_ID_PREFIX: &str = "b29c5de2-0db4-490b-b421-6a51b598bd23";
 
@@ -454,10 +747,9 @@ mod tests {
 
     #[test]
     fn test_batch_request_body_ends_with_closing_boundary() {
-        let (key_values, body) = build_batch_request_body(
-            &["http://example.com/widgets/1".to_string()],
-            &UrlNotificationsType::UPDATED,
-        );
+        let (key_values, body) = build_batch_request_body(&[publish_part(
+            &UrlNotification::updated("http://example.com/widgets/1"),
+        )]);
         assert_eq!(key_values.len(), 1);
         assert!(
             body.trim_end()
@@ -467,6 +759,27 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_batch_request_body_uses_type_of_each_part() {
+        let (_, body) = build_batch_request_body(&[
+            publish_part(&UrlNotification::updated("http://example.com/jobs/new")),
+            publish_part(&UrlNotification::deleted("http://example.com/jobs/expired")),
+        ]);
+        assert!(body.contains(r#"{"type":"URL_UPDATED","url":"http://example.com/jobs/new"}"#));
+        assert!(body.contains(r#"{"type":"URL_DELETED","url":"http://example.com/jobs/expired"}"#));
+    }
+
+    #[test]
+    fn test_batch_request_body_for_metadata() {
+        let (key_values, body) =
+            build_batch_request_body(&[metadata_part("http://example.com/jobs/1?a=b")]);
+        assert_eq!(key_values[0].1, "http://example.com/jobs/1?a=b");
+        assert!(body.contains(
+            "GET /v3/urlNotifications/metadata?url=http%3A%2F%2Fexample.com%2Fjobs%2F1%3Fa%3Db HTTP/1.1\r\naccept: application/json\r\n\r\n--"
+        ));
+        assert!(!body.contains("content-length"));
+    }
+
     #[test]
     fn test_split_one() {
         let (head, rest) = split_one("Content-ID: <response-1>: x", ":");
@@ -520,10 +833,10 @@ mod tests {
             })
             .await;
 
-        let value: serde_json::Value =
-            HttpClient::get("test-token", server.url("/metadata").as_str())
-                .await
-                .unwrap();
+        let value: serde_json::Value = HttpClient::default()
+            .get(&test_token(), server.url("/metadata").as_str())
+            .await
+            .unwrap();
         mock.assert_async().await;
         assert_eq!(value["url"], "http://example.com/");
     }
@@ -538,10 +851,11 @@ mod tests {
             })
             .await;
 
-        let result: Result<serde_json::Value, GoogleApiError> =
-            HttpClient::get("test-token", server.url("/metadata").as_str()).await;
+        let result: Result<serde_json::Value, GoogleApiError> = HttpClient::default()
+            .get(&test_token(), server.url("/metadata").as_str())
+            .await;
         match result {
-            Err(GoogleApiError::HttpStatus(status, body)) => {
+            Err(GoogleApiError::HttpStatus { status, body, .. }) => {
                 assert_eq!(status, 403);
                 assert!(body.contains("403"));
             }
@@ -549,6 +863,231 @@ mod tests {
         }
     }
 
+    // 呼ばれるたびに token-1, token-2, ... を返すプロバイダ
+    struct RotatingTokenProvider {
+        calls: AtomicUsize,
+        invalidated: AtomicUsize,
+    }
+
+    #[async_trait::async_trait]
+    impl TokenProvider for RotatingTokenProvider {
+        async fn token(&self) -> Result<AccessToken, GoogleApiError> {
+            let n = self.calls.fetch_add(1, Ordering::SeqCst) + 1;
+            Ok(AccessToken::new(format!("token-{}", n).as_str()))
+        }
+        async fn invalidate(&self) {
+            self.invalidated.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    fn rotating_tokens() -> RotatingTokenProvider {
+        RotatingTokenProvider {
+            calls: Default::default(),
+            invalidated: Default::default(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_get_retries_once_with_fresh_token_on_401() {
+        let server = MockServer::start_async().await;
+        let rejected = server
+            .mock_async(|when, then| {
+                when.method(GET)
+                    .path("/metadata")
+                    .header("Authorization", "Bearer token-1");
+                then.status(401).body(r#"{"error":{"code":401}}"#);
+            })
+            .await;
+        let accepted = server
+            .mock_async(|when, then| {
+                when.method(GET)
+                    .path("/metadata")
+                    .header("Authorization", "Bearer token-2");
+                then.status(200).body(r#"{"url":"http://example.com/"}"#);
+            })
+            .await;
+
+        let tokens = rotating_tokens();
+        let value: serde_json::Value = HttpClient::default()
+            .get(&tokens, server.url("/metadata").as_str())
+            .await
+            .unwrap();
+        rejected.assert_async().await;
+        accepted.assert_async().await;
+        assert_eq!(value["url"], "http://example.com/");
+        assert_eq!(tokens.invalidated.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_post_gives_up_after_second_401() {
+        let server = MockServer::start_async().await;
+        let mock = server
+            .mock_async(|when, then| {
+                when.method(POST).path("/publish");
+                then.status(401).body("unauthorized");
+            })
+            .await;
+
+        let result: Result<serde_json::Value, GoogleApiError> = HttpClient::default()
+            .post(
+                &rotating_tokens(),
+                server.url("/publish").as_str(),
+                json!({"url": "http://example.com/"}),
+            )
+            .await;
+        // 再送は一度だけ
+        mock.assert_hits_async(2).await;
+        assert!(matches!(result, Err(GoogleApiError::Unauthorized { .. })));
+    }
+
+    fn retrying_client(max_attempts: u32) -> HttpClient {
+        HttpClient::new(
+            reqwest::Client::new(),
+            RetryPolicy::new()
+                .max_attempts(max_attempts)
+                .base_delay(std::time::Duration::from_millis(1)),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_get_retries_transient_error() {
+        let server = MockServer::start_async().await;
+        let unavailable = server
+            .mock_async(|when, then| {
+                when.method(GET)
+                    .path("/metadata")
+                    .header("Authorization", "Bearer token-1");
+                then.status(503).body("unavailable");
+            })
+            .await;
+        let accepted = server
+            .mock_async(|when, then| {
+                when.method(GET)
+                    .path("/metadata")
+                    .header("Authorization", "Bearer token-2");
+                then.status(200).body(r#"{"url":"http://example.com/"}"#);
+            })
+            .await;
+
+        let value: serde_json::Value = retrying_client(3)
+            .get(&rotating_tokens(), server.url("/metadata").as_str())
+            .await
+            .unwrap();
+        unavailable.assert_async().await;
+        accepted.assert_async().await;
+        assert_eq!(value["url"], "http://example.com/");
+    }
+
+    #[tokio::test]
+    async fn test_post_gives_up_after_max_attempts() {
+        let server = MockServer::start_async().await;
+        let mock = server
+            .mock_async(|when, then| {
+                when.method(POST).path("/publish");
+                then.status(403).body(
+                    r#"{"error":{"code":403,"message":"Quota exceeded","status":"RESOURCE_EXHAUSTED"}}"#,
+                );
+            })
+            .await;
+
+        let result: Result<serde_json::Value, GoogleApiError> = retrying_client(3)
+            .post(
+                &test_token(),
+                server.url("/publish").as_str(),
+                json!({"url": "http://example.com/"}),
+            )
+            .await;
+        mock.assert_hits_async(3).await;
+        assert!(matches!(
+            result,
+            Err(GoogleApiError::QuotaExceeded { status: 403, .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_post_does_not_retry_permanent_error() {
+        let server = MockServer::start_async().await;
+        let mock = server
+            .mock_async(|when, then| {
+                when.method(POST).path("/publish");
+                then.status(400)
+                    .body(r#"{"error":{"code":400,"status":"INVALID_ARGUMENT"}}"#);
+            })
+            .await;
+
+        let result: Result<serde_json::Value, GoogleApiError> = retrying_client(3)
+            .post(
+                &test_token(),
+                server.url("/publish").as_str(),
+                json!({"url": "http://example.com/"}),
+            )
+            .await;
+        mock.assert_hits_async(1).await;
+        assert!(matches!(
+            result,
+            Err(GoogleApiError::HttpStatus { status: 400, .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_retries_take_quota_budget() {
+        let server = MockServer::start_async().await;
+        let mock = server
+            .mock_async(|when, then| {
+                when.method(POST).path("/publish");
+                then.status(503).body("unavailable");
+            })
+            .await;
+
+        // 2 回目までは送れるが、3 回目の再送の分の予算は無い
+        let quota = QuotaGovernor::new().publish(QuotaLimit::unlimited().per_day(2));
+        let result: Result<serde_json::Value, GoogleApiError> = retrying_client(3)
+            .with_quota_governor(Some(Arc::new(quota)))
+            .post(
+                &test_token(),
+                server.url("/publish").as_str(),
+                json!({"url": "http://example.com/"}),
+            )
+            .await;
+        mock.assert_hits_async(2).await;
+        assert!(matches!(
+            result,
+            Err(GoogleApiError::QuotaBudgetExhausted {
+                bucket: QuotaBucket::Publish,
+                ..
+            })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_retry_after_longer_than_max_delay_is_not_waited() {
+        let server = MockServer::start_async().await;
+        let mock = server
+            .mock_async(|when, then| {
+                when.method(POST).path("/batch");
+                then.status(429)
+                    .header("Retry-After", "3600")
+                    .body("slow down");
+            })
+            .await;
+
+        let result = retrying_client(3)
+            .execute_url(
+                server.url("/batch").as_str(),
+                &test_token(),
+                notifications(
+                    &["http://example.com/widgets/1"],
+                    UrlNotificationsType::UPDATED,
+                ),
+            )
+            .await;
+        mock.assert_hits_async(1).await;
+        assert!(matches!(
+            result,
+            Err(GoogleApiError::QuotaExceeded { status: 429, .. })
+        ));
+    }
+
     #[tokio::test]
     async fn test_get_broken_json() {
         let server = MockServer::start_async().await;
@@ -559,17 +1098,70 @@ mod tests {
             })
             .await;
 
-        let result: Result<serde_json::Value, GoogleApiError> =
-            HttpClient::get("test-token", server.url("/metadata").as_str()).await;
-        assert!(matches!(result, Err(GoogleApiError::JsonParse(_))));
+        let result: Result<serde_json::Value, GoogleApiError> = HttpClient::default()
+            .get(&test_token(), server.url("/metadata").as_str())
+            .await;
+        match result {
+            Err(e @ GoogleApiError::JsonParse { .. }) => {
+                assert!(std::error::Error::source(&e)
+                    .unwrap()
+                    .is::<serde_json::Error>());
+            }
+            other => panic!("JsonParse であるべき: {:?}", other.err()),
+        }
     }
 
     #[tokio::test]
     async fn test_get_connection_error() {
         // 不正な URL はタイムアウトを待たず即時に Connection エラーになる
         let result: Result<serde_json::Value, GoogleApiError> =
-            HttpClient::get("test-token", "not-a-url").await;
-        assert!(matches!(result, Err(GoogleApiError::Connection(_))));
+            HttpClient::default().get(&test_token(), "not-a-url").await;
+        match result {
+            Err(e @ GoogleApiError::Connection(_)) => {
+                assert!(std::error::Error::source(&e)
+                    .unwrap()
+                    .is::<reqwest::Error>());
+            }
+            other => panic!("Connection であるべき: {:?}", other.err()),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_get_tls_error() {
+        // 平文の HTTP サーバーに https で接続すると TLS ハンドシェイクに失敗する
+        let server = MockServer::start_async().await;
+        let url = server.url("/metadata").replacen("http://", "https://", 1);
+        let result: Result<serde_json::Value, GoogleApiError> =
+            HttpClient::default().get(&test_token(), url.as_str()).await;
+        assert!(
+            matches!(result, Err(GoogleApiError::Tls(_))),
+            "{:?}",
+            result.err()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_get_timeout_error() {
+        let server = MockServer::start_async().await;
+        server
+            .mock_async(|when, then| {
+                when.method(GET).path("/metadata");
+                then.status(200).delay(Duration::from_secs(1)).body("{}");
+            })
+            .await;
+
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_millis(50))
+            .build()
+            .unwrap();
+        let result: Result<serde_json::Value, GoogleApiError> =
+            HttpClient::new(client, RetryPolicy::none())
+                .get(&test_token(), server.url("/metadata").as_str())
+                .await;
+        match result {
+            Err(e @ GoogleApiError::Timeout(_)) => assert!(e.is_retriable()),
+            other => panic!("Timeout であるべき: {:?}", other.err()),
+        }
     }
 
     #[tokio::test]
@@ -585,13 +1177,14 @@ mod tests {
             })
             .await;
 
-        let value: serde_json::Value = HttpClient::post(
-            "test-token",
-            server.url("/publish").as_str(),
-            json!({"url": "http://example.com/"}),
-        )
-        .await
-        .unwrap();
+        let value: serde_json::Value = HttpClient::default()
+            .post(
+                &test_token(),
+                server.url("/publish").as_str(),
+                json!({"url": "http://example.com/"}),
+            )
+            .await
+            .unwrap();
         mock.assert_async().await;
         assert_eq!(value["ok"], true);
     }
@@ -606,18 +1199,19 @@ mod tests {
             })
             .await;
 
-        let result: Result<serde_json::Value, GoogleApiError> = HttpClient::post(
-            "test-token",
-            server.url("/publish").as_str(),
-            json!({"url": "http://example.com/"}),
-        )
-        .await;
+        let result: Result<serde_json::Value, GoogleApiError> = HttpClient::default()
+            .post(
+                &test_token(),
+                server.url("/publish").as_str(),
+                json!({"url": "http://example.com/"}),
+            )
+            .await;
         match result {
-            Err(GoogleApiError::HttpStatus(status, body)) => {
+            Err(GoogleApiError::QuotaExceeded { status, body, .. }) => {
                 assert_eq!(status, 429);
                 assert_eq!(body, "rate limit");
             }
-            other => panic!("HttpStatus であるべき: {:?}", other.err()),
+            other => panic!("QuotaExceeded であるべき: {:?}", other.err()),
         }
     }
 
@@ -641,17 +1235,20 @@ mod tests {
             })
             .await;
 
-        let result = HttpClient::execute_url(
-            server.url("/batch").as_str(),
-            "test-token",
-            vec![
-                "http://example.com/widgets/1".to_string(),
-                "http://example.com/widgets/2".to_string(),
-            ],
-            UrlNotificationsType::UPDATED,
-        )
-        .await
-        .unwrap();
+        let result = HttpClient::default()
+            .execute_url(
+                server.url("/batch").as_str(),
+                &test_token(),
+                notifications(
+                    &[
+                        "http://example.com/widgets/1",
+                        "http://example.com/widgets/2",
+                    ],
+                    UrlNotificationsType::UPDATED,
+                ),
+            )
+            .await
+            .unwrap();
         mock.assert_async().await;
 
         assert_eq!(result.len(), 2);
@@ -665,6 +1262,64 @@ mod tests {
         assert_eq!(result[1].status_code(), 403);
     }
 
+    #[tokio::test]
+    async fn test_execute_batch_typed_results() {
+        let server = MockServer::start_async().await;
+        mock_batch_response(
+            &server,
+            format!(
+                "{}{}{}--{}--\r\n",
+                batch_part(
+                    1,
+                    "200 OK",
+                    r#"{"urlNotificationMetadata":{"url":"http://example.com/widgets/1","latestUpdate":{"url":"http://example.com/widgets/1","type":"URL_UPDATED","notifyTime":"2026-10-01T10:00:00Z"}}}"#
+                ),
+                batch_part(
+                    2,
+                    "403 Forbidden",
+                    r#"{"error":{"code":403,"message":"Permission denied. Failed to verify the URL ownership.","status":"PERMISSION_DENIED"}}"#
+                ),
+                batch_part(3, "502 Bad Gateway", "<html>bad gateway</html>"),
+                BOUNDARY
+            ),
+        )
+        .await;
+
+        let result = HttpClient::default()
+            .execute_url(
+                server.url("/batch").as_str(),
+                &test_token(),
+                notifications(
+                    &[
+                        "http://example.com/widgets/1",
+                        "http://example.com/widgets/2",
+                        "http://example.com/widgets/3",
+                    ],
+                    UrlNotificationsType::UPDATED,
+                ),
+            )
+            .await
+            .unwrap();
+
+        let published = result[0].result().unwrap();
+        assert_eq!(
+            published
+                .url_notification_metadata
+                .latest_update
+                .unwrap()
+                .notify_time,
+            "2026-10-01T10:00:00Z"
+        );
+        let error = result[1].result().unwrap_err();
+        assert_eq!(error.code, 403);
+        assert_eq!(error.status, "PERMISSION_DENIED");
+        // エンベロープでないボディはそのまま message に入る
+        let error = result[2].result().unwrap_err();
+        assert_eq!(error.code, 502);
+        assert_eq!(error.status, "");
+        assert_eq!(error.message, "<html>bad gateway</html>");
+    }
+
     #[tokio::test]
     async fn test_execute_batch_with_quoted_boundary() {
         let server = MockServer::start_async().await;
@@ -680,18 +1335,350 @@ mod tests {
             })
             .await;
 
-        let result = HttpClient::execute_url(
+        let result = HttpClient::default()
+            .execute_url(
+                server.url("/batch").as_str(),
+                &test_token(),
+                notifications(
+                    &[
+                        "http://example.com/widgets/1",
+                        "http://example.com/widgets/2",
+                    ],
+                    UrlNotificationsType::UPDATED,
+                ),
+            )
+            .await
+            .unwrap();
+        assert_eq!(result.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_execute_retries_only_failed_parts() {
+        let server = MockServer::start_async().await;
+        let first = server
+            .mock_async(|when, then| {
+                when.method(POST)
+                    .path("/batch")
+                    .header("Authorization", "Bearer token-1");
+                then.status(200)
+                    .header(
+                        "Content-Type",
+                        format!("multipart/mixed; boundary={}", BOUNDARY).as_str(),
+                    )
+                    .body(format!(
+                        "{}{}{}--{}--\r\n",
+                        batch_part(1, "200 OK", r#"{"ok":1}"#),
+                        batch_part(
+                            2,
+                            "429 Too Many Requests",
+                            r#"{"error":{"code":429,"status":"RESOURCE_EXHAUSTED"}}"#
+                        ),
+                        batch_part(3, "400 Bad Request", r#"{"error":{"code":400}}"#),
+                        BOUNDARY
+                    ));
+            })
+            .await;
+        // 再送されるのは 429 になった widgets/2 だけ
+        let second = server
+            .mock_async(|when, then| {
+                when.method(POST)
+                    .path("/batch")
+                    .header("Authorization", "Bearer token-2")
+                    .body_contains("http://example.com/widgets/2")
+                    .matches(|req| {
+                        let body =
+                            String::from_utf8_lossy(req.body.as_deref().unwrap_or(&[])).to_string();
+                        !body.contains("widgets/1") && !body.contains("widgets/3")
+                    });
+                then.status(200)
+                    .header(
+                        "Content-Type",
+                        format!("multipart/mixed; boundary={}", BOUNDARY).as_str(),
+                    )
+                    .body(format!(
+                        "{}--{}--\r\n",
+                        batch_part(1, "200 OK", r#"{"ok":2}"#),
+                        BOUNDARY
+                    ));
+            })
+            .await;
+
+        let result = HttpClient::new(
+            reqwest::Client::new(),
+            RetryPolicy::new()
+                .base_delay(std::time::Duration::from_millis(1))
+                .retry_batch_parts(true),
+        )
+        .execute_url(
+            server.url("/batch").as_str(),
+            &rotating_tokens(),
+            notifications(
+                &[
+                    "http://example.com/widgets/1",
+                    "http://example.com/widgets/2",
+                    "http://example.com/widgets/3",
+                ],
+                UrlNotificationsType::UPDATED,
+            ),
+        )
+        .await
+        .unwrap();
+        first.assert_async().await;
+        second.assert_async().await;
+
+        assert_eq!(result.len(), 3);
+        assert_eq!(result[0].status_code(), 200);
+        assert_eq!(result[0].attempts(), 1);
+        assert_eq!(result[1].url(), "http://example.com/widgets/2");
+        assert_eq!(result[1].status_code(), 200);
+        assert_eq!(result[1].json()["ok"], 2);
+        assert_eq!(result[1].attempts(), 2);
+        // 400 は恒久的なエラーなので再送しない
+        assert_eq!(result[2].status_code(), 400);
+        assert_eq!(result[2].attempts(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_execute_part_retry_stops_at_max_attempts() {
+        let server = MockServer::start_async().await;
+        let mock = server
+            .mock_async(|when, then| {
+                when.method(POST).path("/batch");
+                then.status(200)
+                    .header(
+                        "Content-Type",
+                        format!("multipart/mixed; boundary={}", BOUNDARY).as_str(),
+                    )
+                    .body(format!(
+                        "{}--{}--\r\n",
+                        batch_part(1, "503 Service Unavailable", "unavailable"),
+                        BOUNDARY
+                    ));
+            })
+            .await;
+
+        let result = HttpClient::new(
+            reqwest::Client::new(),
+            RetryPolicy::new()
+                .max_attempts(3)
+                .base_delay(std::time::Duration::from_millis(1))
+                .retry_batch_parts(true),
+        )
+        .execute_url(
+            server.url("/batch").as_str(),
+            &test_token(),
+            notifications(
+                &["http://example.com/widgets/1"],
+                UrlNotificationsType::UPDATED,
+            ),
+        )
+        .await
+        .unwrap();
+        mock.assert_hits_async(3).await;
+        assert_eq!(result[0].status_code(), 503);
+        assert_eq!(result[0].attempts(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_execute_part_retry_follows_longest_retry_delay() {
+        let server = MockServer::start_async().await;
+        let mock = server
+            .mock_async(|when, then| {
+                when.method(POST).path("/batch");
+                then.status(200)
+                    .header(
+                        "Content-Type",
+                        format!("multipart/mixed; boundary={}", BOUNDARY).as_str(),
+                    )
+                    .body(format!(
+                        "{}{}--{}--\r\n",
+                        batch_part(1, "503 Service Unavailable", "unavailable"),
+                        batch_part(
+                            2,
+                            "429 Too Many Requests",
+                            r#"{"error":{"code":429,"status":"RESOURCE_EXHAUSTED","details":[{"@type":"type.googleapis.com/google.rpc.RetryInfo","retryDelay":"3600s"}]}}"#
+                        ),
+                        BOUNDARY
+                    ));
+            })
+            .await;
+
+        // 429 の part が max_delay より長く待てと言うので、どちらも再送しない
+        let result = HttpClient::new(
+            reqwest::Client::new(),
+            RetryPolicy::new()
+                .base_delay(std::time::Duration::from_millis(1))
+                .retry_batch_parts(true),
+        )
+        .execute_url(
             server.url("/batch").as_str(),
-            "test-token",
-            vec![
-                "http://example.com/widgets/1".to_string(),
-                "http://example.com/widgets/2".to_string(),
-            ],
-            UrlNotificationsType::UPDATED,
+            &test_token(),
+            notifications(
+                &[
+                    "http://example.com/widgets/1",
+                    "http://example.com/widgets/2",
+                ],
+                UrlNotificationsType::UPDATED,
+            ),
+        )
+        .await
+        .unwrap();
+        mock.assert_hits_async(1).await;
+        assert_eq!(result[0].status_code(), 503);
+        assert_eq!(result[1].status_code(), 429);
+        assert_eq!(result[1].attempts(), 1);
+    }
+
+    // 1 回目は 503 の part を返し、再送のリクエストには retry で応える
+    async fn mock_failed_part_retry(server: &MockServer, retry: u16) -> Mock<'_> {
+        server
+            .mock_async(|when, then| {
+                when.method(POST)
+                    .path("/batch")
+                    .header("Authorization", "Bearer token-1");
+                then.status(200)
+                    .header(
+                        "Content-Type",
+                        format!("multipart/mixed; boundary={}", BOUNDARY).as_str(),
+                    )
+                    .body(format!(
+                        "{}--{}--\r\n",
+                        batch_part(1, "503 Service Unavailable", "unavailable"),
+                        BOUNDARY
+                    ));
+            })
+            .await;
+        server
+            .mock_async(|when, then| {
+                when.method(POST)
+                    .path("/batch")
+                    .header("Authorization", "Bearer token-2");
+                then.status(retry)
+                    .body(r#"{"error":{"code":400,"status":"INVALID_ARGUMENT"}}"#);
+            })
+            .await
+    }
+
+    #[tokio::test]
+    async fn test_execute_part_retry_reports_failed_retry_request() {
+        let server = MockServer::start_async().await;
+        mock_failed_part_retry(&server, 400).await;
+
+        let result = HttpClient::new(
+            reqwest::Client::new(),
+            RetryPolicy::new()
+                .base_delay(std::time::Duration::from_millis(1))
+                .retry_batch_parts(true),
+        )
+        .execute_url(
+            server.url("/batch").as_str(),
+            &rotating_tokens(),
+            notifications(
+                &["http://example.com/widgets/1"],
+                UrlNotificationsType::UPDATED,
+            ),
+        )
+        .await
+        .unwrap();
+        // 最後に受け取った part の状態と、再送が失敗した理由の両方が分かる
+        assert_eq!(result[0].status_code(), 503);
+        assert_eq!(result[0].attempts(), 1);
+        assert!(matches!(
+            result[0].retry_error(),
+            Some(GoogleApiError::HttpStatus { status: 400, .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_execute_part_retry_reports_exhausted_budget() {
+        let server = MockServer::start_async().await;
+        let retry = mock_failed_part_retry(&server, 200).await;
+
+        let quota = QuotaGovernor::new().publish(QuotaLimit::unlimited().per_day(1));
+        let result = HttpClient::new(
+            reqwest::Client::new(),
+            RetryPolicy::new()
+                .base_delay(std::time::Duration::from_millis(1))
+                .retry_batch_parts(true),
+        )
+        .with_quota_governor(Some(Arc::new(quota)))
+        .execute_url(
+            server.url("/batch").as_str(),
+            &rotating_tokens(),
+            notifications(
+                &["http://example.com/widgets/1"],
+                UrlNotificationsType::UPDATED,
+            ),
         )
         .await
         .unwrap();
+        assert_eq!(result[0].status_code(), 503);
+        assert!(matches!(
+            result[0].retry_error(),
+            Some(GoogleApiError::QuotaBudgetExhausted {
+                bucket: QuotaBucket::Publish,
+                ..
+            })
+        ));
+        // 予算が無いので再送のリクエストは送っていない
+        retry.assert_hits_async(0).await;
+    }
+
+    #[tokio::test]
+    async fn test_execute_metadata_batch() {
+        let server = MockServer::start_async().await;
+        let mock = server
+            .mock_async(|when, then| {
+                when.method(POST)
+                    .path("/batch")
+                    .body_contains("GET /v3/urlNotifications/metadata?url=http%3A%2F%2Fexample.com%2Fwidgets%2F1")
+                    .body_contains("GET /v3/urlNotifications/metadata?url=http%3A%2F%2Fexample.com%2Fwidgets%2F2");
+                then.status(200)
+                    .header(
+                        "Content-Type",
+                        format!("multipart/mixed; boundary={}", BOUNDARY).as_str(),
+                    )
+                    .body(format!(
+                        "{}{}--{}--\r\n",
+                        batch_part(
+                            1,
+                            "200 OK",
+                            r#"{"url":"http://example.com/widgets/1","latestUpdate":{"url":"http://example.com/widgets/1","type":"URL_UPDATED","notifyTime":"2026-10-01T10:00:00.123456789Z"}}"#
+                        ),
+                        batch_part(
+                            2,
+                            "404 Not Found",
+                            r#"{"error":{"code":404,"message":"Requested entity was not found.","status":"NOT_FOUND"}}"#
+                        ),
+                        BOUNDARY
+                    ));
+            })
+            .await;
+
+        let result = HttpClient::default()
+            .execute_metadata_url(
+                server.url("/batch").as_str(),
+                &test_token(),
+                vec![
+                    "http://example.com/widgets/1".to_string(),
+                    "http://example.com/widgets/2".to_string(),
+                ],
+            )
+            .await
+            .unwrap();
+        mock.assert_async().await;
+
         assert_eq!(result.len(), 2);
+        let metadata = result[0].as_ref().unwrap();
+        assert_eq!(metadata.url, "http://example.com/widgets/1");
+        assert_eq!(
+            metadata.latest_update.as_ref().unwrap().notify_time,
+            "2026-10-01T10:00:00.123456789Z"
+        );
+        assert!(matches!(
+            result[1],
+            Err(GoogleApiError::HttpStatus { status: 404, .. })
+        ));
     }
 
     #[tokio::test]
@@ -706,17 +1693,23 @@ mod tests {
             })
             .await;
 
-        let result = HttpClient::execute_url(
-            server.url("/batch").as_str(),
-            "test-token",
-            vec![
-                "http://example.com/widgets/1".to_string(),
-                "http://example.com/widgets/2".to_string(),
-            ],
-            UrlNotificationsType::UPDATED,
-        )
-        .await;
-        assert!(matches!(result, Err(GoogleApiError::JsonParse(_))));
+        let result = HttpClient::default()
+            .execute_url(
+                server.url("/batch").as_str(),
+                &test_token(),
+                notifications(
+                    &[
+                        "http://example.com/widgets/1",
+                        "http://example.com/widgets/2",
+                    ],
+                    UrlNotificationsType::UPDATED,
+                ),
+            )
+            .await;
+        assert!(matches!(
+            result,
+            Err(GoogleApiError::MalformedBatch { part: None, .. })
+        ));
     }
 
     #[tokio::test]
@@ -735,14 +1728,20 @@ mod tests {
             })
             .await;
 
-        let result = HttpClient::execute_url(
-            server.url("/batch").as_str(),
-            "test-token",
-            vec!["http://example.com/widgets/1".to_string()],
-            UrlNotificationsType::UPDATED,
-        )
-        .await;
-        assert!(matches!(result, Err(GoogleApiError::JsonParse(_))));
+        let result = HttpClient::default()
+            .execute_url(
+                server.url("/batch").as_str(),
+                &test_token(),
+                notifications(
+                    &["http://example.com/widgets/1"],
+                    UrlNotificationsType::UPDATED,
+                ),
+            )
+            .await;
+        assert!(matches!(
+            result,
+            Err(GoogleApiError::MalformedBatch { part: None, .. })
+        ));
     }
 
     #[tokio::test]
@@ -765,29 +1764,38 @@ mod tests {
             })
             .await;
 
-        let result = HttpClient::execute_url(
-            server.url("/batch").as_str(),
-            "test-token",
-            vec![
-                "http://example.com/widgets/1".to_string(),
-                "http://example.com/widgets/2".to_string(),
-            ],
-            UrlNotificationsType::UPDATED,
-        )
-        .await;
-        assert!(matches!(result, Err(GoogleApiError::JsonParse(_))));
+        let result = HttpClient::default()
+            .execute_url(
+                server.url("/batch").as_str(),
+                &test_token(),
+                notifications(
+                    &[
+                        "http://example.com/widgets/1",
+                        "http://example.com/widgets/2",
+                    ],
+                    UrlNotificationsType::UPDATED,
+                ),
+            )
+            .await;
+        assert!(matches!(
+            result,
+            Err(GoogleApiError::MalformedBatch { part: None, .. })
+        ));
     }
 
     async fn execute_one_url_against(
         server: &MockServer,
     ) -> Result<Vec<ResponseGoogleIndexingBatch>, GoogleApiError> {
-        HttpClient::execute_url(
-            server.url("/batch").as_str(),
-            "test-token",
-            vec!["http://example.com/widgets/1".to_string()],
-            UrlNotificationsType::UPDATED,
-        )
-        .await
+        HttpClient::default()
+            .execute_url(
+                server.url("/batch").as_str(),
+                &test_token(),
+                notifications(
+                    &["http://example.com/widgets/1"],
+                    UrlNotificationsType::UPDATED,
+                ),
+            )
+            .await
     }
 
     async fn mock_batch_response(server: &MockServer, body: String) {
@@ -818,8 +1826,11 @@ mod tests {
         .await;
 
         match execute_one_url_against(&server).await {
-            Err(GoogleApiError::JsonParse(msg)) => {
-                assert!(msg.contains("unknown Content-ID"), "{}", msg)
+            Err(GoogleApiError::MalformedBatch {
+                part: Some(0),
+                message,
+            }) => {
+                assert!(message.contains("unknown Content-ID"), "{}", message)
             }
             other => panic!("unknown Content-ID はエラーであるべき: {:?}", other.err()),
         }
@@ -839,19 +1850,26 @@ mod tests {
         )
         .await;
 
-        let result = HttpClient::execute_url(
-            server.url("/batch").as_str(),
-            "test-token",
-            vec![
-                "http://example.com/widgets/1".to_string(),
-                "http://example.com/widgets/2".to_string(),
-            ],
-            UrlNotificationsType::UPDATED,
-        )
-        .await;
+        let result = HttpClient::default()
+            .execute_url(
+                server.url("/batch").as_str(),
+                &test_token(),
+                notifications(
+                    &[
+                        "http://example.com/widgets/1",
+                        "http://example.com/widgets/2",
+                    ],
+                    UrlNotificationsType::UPDATED,
+                ),
+            )
+            .await;
         match result {
-            Err(GoogleApiError::JsonParse(msg)) => {
-                assert!(msg.contains("duplicated Content-ID"), "{}", msg)
+            // 2 番目の part が重複している
+            Err(GoogleApiError::MalformedBatch {
+                part: Some(1),
+                message,
+            }) => {
+                assert!(message.contains("duplicated Content-ID"), "{}", message)
             }
             other => panic!("重複した Content-ID はエラーであるべき: {:?}", other.err()),
         }
@@ -871,8 +1889,11 @@ mod tests {
         .await;
 
         match execute_one_url_against(&server).await {
-            Err(GoogleApiError::JsonParse(msg)) => {
-                assert!(msg.contains("HTTP status line"), "{}", msg)
+            Err(GoogleApiError::MalformedBatch {
+                part: Some(0),
+                message,
+            }) => {
+                assert!(message.contains("HTTP status line"), "{}", message)
             }
             other => panic!(
                 "status を解析できない part はエラーであるべき: {:?}",
@@ -885,25 +1906,22 @@ mod tests {
     async fn test_execute_rejects_invalid_url_count() {
         // バリデーションは送信前に行われるので、送信不能な endpoint でも検証できる
         // (もし送信されてしまったら Connection エラーになりテストは即時に落ちる)
-        let result = HttpClient::execute_url(
-            "not-a-url",
-            "test-token",
-            vec![],
-            UrlNotificationsType::UPDATED,
-        )
-        .await;
+        let result = HttpClient::default()
+            .execute_url("not-a-url", &test_token(), vec![])
+            .await;
         assert!(matches!(result, Err(GoogleApiError::InvalidArgument(_))));
 
         let too_many = (0..101)
-            .map(|i| format!("http://example.com/{}", i))
-            .collect::<Vec<String>>();
-        let result = HttpClient::execute_url(
-            "not-a-url",
-            "test-token",
-            too_many,
-            UrlNotificationsType::UPDATED,
-        )
-        .await;
+            .map(|i| {
+                UrlNotification::new(
+                    format!("http://example.com/{}", i).as_str(),
+                    UrlNotificationsType::UPDATED,
+                )
+            })
+            .collect::<Vec<UrlNotification>>();
+        let result = HttpClient::default()
+            .execute_url("not-a-url", &test_token(), too_many)
+            .await;
         assert!(matches!(result, Err(GoogleApiError::InvalidArgument(_))));
     }
 
@@ -917,15 +1935,18 @@ mod tests {
             })
             .await;
 
-        let result = HttpClient::execute_url(
-            server.url("/batch").as_str(),
-            "test-token",
-            vec!["http://example.com/widgets/1".to_string()],
-            UrlNotificationsType::UPDATED,
-        )
-        .await;
+        let result = HttpClient::default()
+            .execute_url(
+                server.url("/batch").as_str(),
+                &test_token(),
+                notifications(
+                    &["http://example.com/widgets/1"],
+                    UrlNotificationsType::UPDATED,
+                ),
+            )
+            .await;
         match result {
-            Err(GoogleApiError::HttpStatus(status, body)) => {
+            Err(GoogleApiError::HttpStatus { status, body, .. }) => {
                 assert_eq!(status, 500);
                 assert_eq!(body, "internal error");
             }
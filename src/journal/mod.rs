@@ -0,0 +1,563 @@
+use crate::client::{BatchFailure, BatchReport, IndexingClient};
+use crate::error::GoogleApiError;
+use crate::http::MAX_BATCH_URLS;
+use crate::{UrlNotification, UrlNotificationsType};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Append-only log of submissions, so a run that died midway can be resumed.
+///
+/// [`submit`](SubmissionJournal::submit) writes one JSON line per URL for each step: the
+/// intent to notify, each send attempt, and the final status of its batch part. Lines
+/// are flushed to disk before the batch is sent and after its results arrive:
+/// ```json
+/// {"at":1792300000,"event":"intent","url":"https://example.com/1","type":"URL_UPDATED"}
+/// {"at":1792300000,"event":"attempt","url":"https://example.com/1"}
+/// {"at":1792300001,"event":"result","url":"https://example.com/1","status":200,"attempts":1,"retriable":false}
+/// ```
+/// A batch that fails as a whole gets a `failure` line per URL with the error instead of
+/// results.
+/// After a restart, [`resume`](SubmissionJournal::resume) sends only the notifications
+/// that have no final status yet. A URL that was sent but whose result was not recorded
+/// is sent again, since Google cannot tell us whether that request landed.
+/// ```rust,no_run
+/// use google_indexing_api::{IndexingClient, SubmissionJournal, UrlNotification};
+/// async fn example_journal(client: &IndexingClient) {
+///     let journal = SubmissionJournal::open("/var/lib/indexing/journal.jsonl").unwrap();
+///     // Finish what the last run left behind first
+///     journal.resume(client).await.unwrap();
+///     journal
+///         .submit(client, vec![UrlNotification::updated("https://example.com/jobs/1")])
+///         .await
+///         .unwrap();
+/// }
+/// ```
+#[derive(Debug)]
+pub struct SubmissionJournal {
+    path: PathBuf,
+    // 書き込みはブロッキング用のスレッドで行うので、そこへ渡せるよう Arc に入れる
+    file: Arc<Mutex<File>>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Record {
+    at: u64,
+    #[serde(flatten)]
+    event: Event,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "lowercase")]
+enum Event {
+    Intent {
+        url: String,
+        #[serde(rename = "type")]
+        url_type: UrlNotificationsType,
+    },
+    Attempt {
+        url: String,
+    },
+    Result {
+        url: String,
+        status: u16,
+        attempts: u32,
+        // 一時的なエラー。resume で送り直す
+        retriable: bool,
+    },
+    // バッチ全体が失敗した。結果が無いので resume で送り直す
+    Failure {
+        url: String,
+        error: String,
+    },
+}
+
+impl SubmissionJournal {
+    /// Opens the journal, creating the file and its parent directory if needed.
+    ///
+    /// A last line cut off by a crash is removed.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, GoogleApiError> {
+        let path = path.as_ref().to_path_buf();
+        if let Some(parent) = path.parent() {
+            if !parent.as_os_str().is_empty() {
+                std::fs::create_dir_all(parent).map_err(GoogleApiError::Journal)?;
+            }
+        }
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .read(true)
+            .open(&path)
+            .map_err(GoogleApiError::Journal)?;
+
+        // 書き込み途中で落ちた行は捨てる。残すと次に追記した行とつながってしまう
+        let contents = std::fs::read(&path).map_err(GoogleApiError::Journal)?;
+        if !contents.is_empty() && !contents.ends_with(b"\n") {
+            let complete = contents
+                .iter()
+                .rposition(|b| *b == b'\n')
+                .map_or(0, |i| i + 1);
+            file.set_len(complete as u64)
+                .map_err(GoogleApiError::Journal)?;
+        }
+        Ok(SubmissionJournal {
+            path,
+            file: Arc::new(Mutex::new(file)),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        self.path.as_path()
+    }
+
+    /// Records the notifications, sends them in batches of 100, and records the
+    /// status of every part.
+    ///
+    /// With a [`UrlValidator`](crate::UrlValidator) on the client, the journal records the
+    /// normalized URLs, so the results match their intents.
+    ///
+    /// A batch that fails as a whole does not stop the others: its notifications are in
+    /// [`BatchReport::failed`] with the error, and stay unfinished in the journal. `Err`
+    /// is returned when the journal cannot be written, or when nothing was sent because
+    /// a URL was rejected.
+    pub async fn submit(
+        &self,
+        client: &IndexingClient,
+        notifications: Vec<UrlNotification>,
+    ) -> Result<BatchReport, GoogleApiError> {
+        // 結果は正規化した URL で返るので、intent から同じ URL で記録する
+        let notifications = client.prepare(notifications)?;
+        self.append(
+            notifications
+                .iter()
+                .map(|n| Event::Intent {
+                    url: n.url.clone(),
+                    url_type: n.url_type.clone(),
+                })
+                .collect(),
+        )
+        .await?;
+        self.send(client, notifications).await
+    }
+
+    /// Sends the notifications the journal has no final status for: never sent, sent
+    /// without a recorded result, or failed with a transient error (see
+    /// [`ResponseGoogleIndexingBatch::is_retriable`](crate::ResponseGoogleIndexingBatch::is_retriable)).
+    /// Failed batches are reported like in [`submit`](SubmissionJournal::submit).
+    pub async fn resume(&self, client: &IndexingClient) -> Result<BatchReport, GoogleApiError> {
+        let file = self.file.clone();
+        let path = self.path.clone();
+        let notifications = blocking(move || {
+            let _file = file.lock().unwrap();
+            replay(path.as_path())
+        })
+        .await?;
+        self.send(client, notifications).await
+    }
+
+    /// The notifications [`resume`](SubmissionJournal::resume) would send, in the order
+    /// they were first recorded. A URL recorded several times keeps its latest type.
+    pub fn unfinished(&self) -> Result<Vec<UrlNotification>, GoogleApiError> {
+        let _file = self.file.lock().unwrap();
+        replay(self.path.as_path())
+    }
+
+    /// Rewrites the journal with only the unfinished notifications, so it does not grow
+    /// forever.
+    pub fn compact(&self) -> Result<(), GoogleApiError> {
+        let mut file = self.file.lock().unwrap();
+        let unfinished = replay(self.path.as_path())?;
+        let tmp = {
+            let mut name = self.path.as_os_str().to_os_string();
+            name.push(".tmp");
+            PathBuf::from(name)
+        };
+        let lines = encode(unfinished.into_iter().map(|n| Event::Intent {
+            url: n.url,
+            url_type: n.url_type,
+        }))?;
+        std::fs::write(&tmp, lines).map_err(GoogleApiError::Journal)?;
+        std::fs::rename(&tmp, &self.path).map_err(GoogleApiError::Journal)?;
+        *file = OpenOptions::new()
+            .append(true)
+            .read(true)
+            .open(&self.path)
+            .map_err(GoogleApiError::Journal)?;
+        Ok(())
+    }
+
+    async fn send(
+        &self,
+        client: &IndexingClient,
+        notifications: Vec<UrlNotification>,
+    ) -> Result<BatchReport, GoogleApiError> {
+        let mut report = BatchReport::default();
+        for chunk in notifications.chunks(MAX_BATCH_URLS) {
+            self.append(
+                chunk
+                    .iter()
+                    .map(|n| Event::Attempt { url: n.url.clone() })
+                    .collect(),
+            )
+            .await?;
+            let batch = match client.batch_notifications(chunk.to_vec()).await {
+                Ok(batch) => batch,
+                Err(error) => {
+                    // 送った分の結果は捨てずに、このバッチの失敗を記録して次へ進む
+                    self.append(
+                        chunk
+                            .iter()
+                            .map(|n| Event::Failure {
+                                url: n.url.clone(),
+                                error: error.to_string(),
+                            })
+                            .collect(),
+                    )
+                    .await?;
+                    report.failed.push(BatchFailure {
+                        notifications: chunk.to_vec(),
+                        error,
+                    });
+                    continue;
+                }
+            };
+            self.append(
+                batch
+                    .iter()
+                    .map(|part| Event::Result {
+                        url: part.url().to_string(),
+                        status: part.status_code(),
+                        attempts: part.attempts(),
+                        retriable: part.is_retriable(),
+                    })
+                    .collect(),
+            )
+            .await?;
+            report.results.extend(batch);
+        }
+        Ok(report)
+    }
+
+    async fn append(&self, events: Vec<Event>) -> Result<(), GoogleApiError> {
+        if events.is_empty() {
+            return Ok(());
+        }
+        let file = self.file.clone();
+        blocking(move || {
+            let lines = encode(events)?;
+            let mut file = file.lock().unwrap();
+            file.write_all(lines.as_bytes())
+                .map_err(GoogleApiError::Journal)?;
+            // 送信前に intent と attempt がディスクに載っていることを保証する
+            file.sync_data().map_err(GoogleApiError::Journal)
+        })
+        .await
+    }
+}
+
+// ファイルのロックを取ってから呼ぶ
+fn replay(path: &Path) -> Result<Vec<UrlNotification>, GoogleApiError> {
+    let contents = std::fs::read_to_string(path).map_err(GoogleApiError::Journal)?;
+    let mut order = Vec::new();
+    let mut pending: HashMap<String, UrlNotificationsType> = HashMap::new();
+    let mut intended: HashMap<String, UrlNotificationsType> = HashMap::new();
+    for (i, line) in contents.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let record: Record = serde_json::from_str(line).map_err(|e| {
+            GoogleApiError::Journal(std::io::Error::new(
+                ErrorKind::InvalidData,
+                format!("line {}: {}", i + 1, e),
+            ))
+        })?;
+        match record.event {
+            Event::Intent { url, url_type } => {
+                if !intended.contains_key(&url) {
+                    order.push(url.clone());
+                }
+                intended.insert(url.clone(), url_type.clone());
+                pending.insert(url, url_type);
+            }
+            Event::Attempt { .. } | Event::Failure { .. } => {}
+            Event::Result { url, retriable, .. } => {
+                if !retriable {
+                    pending.remove(&url);
+                }
+            }
+        }
+    }
+    Ok(order
+        .into_iter()
+        .filter_map(|url| {
+            let url_type = pending.remove(&url)?;
+            Some(UrlNotification { url, url_type })
+        })
+        .collect())
+}
+
+// fsync やファイルの読み込みでランタイムのワーカーを止めないよう、ブロッキング用のスレッドで動かす
+async fn blocking<F, T>(f: F) -> Result<T, GoogleApiError>
+where
+    F: FnOnce() -> Result<T, GoogleApiError> + Send + 'static,
+    T: Send + 'static,
+{
+    tokio::task::spawn_blocking(f)
+        .await
+        .unwrap_or_else(|e| std::panic::resume_unwind(e.into_panic()))
+}
+
+fn encode<I>(events: I) -> Result<String, GoogleApiError>
+where
+    I: IntoIterator<Item = Event>,
+{
+    let at = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let mut lines = String::new();
+    for event in events {
+        let line = serde_json::to_string(&Record { at, event })
+            .map_err(|e| GoogleApiError::Journal(std::io::Error::new(ErrorKind::InvalidData, e)))?;
+        lines.push_str(line.as_str());
+        lines.push('\n');
+    }
+    Ok(lines)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{batch_ok, batch_response, client_builder, client_for, temp_path};
+    use crate::validate::UrlValidator;
+    use httpmock::prelude::*;
+
+    fn urls(notifications: &[UrlNotification]) -> Vec<&str> {
+        notifications.iter().map(|n| n.url.as_str()).collect()
+    }
+
+    #[tokio::test]
+    async fn test_submit_records_every_step() {
+        let server = MockServer::start_async().await;
+        server
+            .mock_async(|when, then| {
+                when.method(POST).path("/batch");
+                then.status(200)
+                    .header("Content-Type", "multipart/mixed; boundary=batch_x")
+                    .body(batch_response(&[
+                        ("200 OK", "{}"),
+                        ("503 Service Unavailable", "{}"),
+                        ("403 Forbidden", "{}"),
+                    ]));
+            })
+            .await;
+        let path = temp_path("journal.jsonl");
+        let journal = SubmissionJournal::open(&path).unwrap();
+        let report = journal
+            .submit(
+                &client_for(&server),
+                vec![
+                    UrlNotification::updated("https://example.com/1"),
+                    UrlNotification::deleted("https://example.com/2"),
+                    UrlNotification::updated("https://example.com/3"),
+                ],
+            )
+            .await
+            .unwrap();
+        assert!(report.is_complete());
+        assert_eq!(report.results.len(), 3);
+
+        let lines = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(lines.lines().count(), 9);
+        assert!(
+            lines
+                .contains(r#""event":"intent","url":"https://example.com/2","type":"URL_DELETED""#),
+            "{}",
+            lines
+        );
+        assert!(
+            lines.contains(r#""event":"result","url":"https://example.com/3","status":403"#),
+            "{}",
+            lines
+        );
+
+        // 503 は一時的なエラーなので未完了、403 は送り直しても無駄なので完了扱い
+        let unfinished = journal.unfinished().unwrap();
+        assert_eq!(urls(&unfinished), vec!["https://example.com/2"]);
+        assert_eq!(unfinished[0].url_type, UrlNotificationsType::DELETED);
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_submit_records_normalized_urls() {
+        let server = MockServer::start_async().await;
+        server
+            .mock_async(|when, then| {
+                when.method(POST)
+                    .path("/batch")
+                    .body_contains(r#""url":"https://example.com/jobs/1""#);
+                then.status(200)
+                    .header("Content-Type", "multipart/mixed; boundary=batch_x")
+                    .body(batch_response(&[("200 OK", "{}")]));
+            })
+            .await;
+        let client = client_builder(&server)
+            .url_validator(UrlValidator::new())
+            .build()
+            .unwrap();
+        let path = temp_path("journal.jsonl");
+        let journal = SubmissionJournal::open(&path).unwrap();
+        journal
+            .submit(
+                &client,
+                vec![UrlNotification::updated("HTTPS://Example.com/jobs/1#apply")],
+            )
+            .await
+            .unwrap();
+
+        // intent と result が同じ URL なので、resume で送り直すものは無い
+        assert!(journal.unfinished().unwrap().is_empty());
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_resume_sends_only_unfinished_work() {
+        let path = temp_path("journal.jsonl");
+        {
+            // 2 件目の送信中に落ちたジャーナル。最後の行は書きかけ
+            let journal = SubmissionJournal::open(&path).unwrap();
+            journal
+                .append(vec![
+                    Event::Intent {
+                        url: "https://example.com/1".to_string(),
+                        url_type: UrlNotificationsType::UPDATED,
+                    },
+                    Event::Intent {
+                        url: "https://example.com/2".to_string(),
+                        url_type: UrlNotificationsType::UPDATED,
+                    },
+                    Event::Attempt {
+                        url: "https://example.com/1".to_string(),
+                    },
+                    Event::Result {
+                        url: "https://example.com/1".to_string(),
+                        status: 200,
+                        attempts: 1,
+                        retriable: false,
+                    },
+                    Event::Attempt {
+                        url: "https://example.com/2".to_string(),
+                    },
+                ])
+                .await
+                .unwrap();
+            let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+            file.write_all(br#"{"at":1,"event":"res"#).unwrap();
+        }
+
+        let server = MockServer::start_async().await;
+        let mock = server
+            .mock_async(|when, then| {
+                when.method(POST)
+                    .path("/batch")
+                    .body_contains("https://example.com/2")
+                    .matches(|req| {
+                        !String::from_utf8_lossy(req.body.as_deref().unwrap_or_default())
+                            .contains("https://example.com/1")
+                    });
+                then.status(200)
+                    .header("Content-Type", "multipart/mixed; boundary=batch_x")
+                    .body(batch_response(&[("200 OK", "{}")]));
+            })
+            .await;
+
+        let journal = SubmissionJournal::open(&path).unwrap();
+        let report = journal.resume(&client_for(&server)).await.unwrap();
+        mock.assert_async().await;
+        assert_eq!(
+            report.results.iter().map(|r| r.url()).collect::<Vec<_>>(),
+            vec!["https://example.com/2"]
+        );
+        assert!(journal.unfinished().unwrap().is_empty());
+
+        // 全部終わったので何も送らない
+        assert!(journal
+            .resume(&client_for(&server))
+            .await
+            .unwrap()
+            .results
+            .is_empty());
+        mock.assert_hits_async(1).await;
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_failed_batch_stays_unfinished_and_compact() {
+        let server = MockServer::start_async().await;
+        server
+            .mock_async(|when, then| {
+                when.method(POST)
+                    .path("/batch")
+                    .body_contains("\"https://example.com/0\"");
+                then.status(200)
+                    .header("Content-Type", "multipart/mixed; boundary=batch_x")
+                    .body(batch_ok(100));
+            })
+            .await;
+        server
+            .mock_async(|when, then| {
+                when.method(POST)
+                    .path("/batch")
+                    .body_contains("\"https://example.com/100\"");
+                then.status(500).body("internal");
+            })
+            .await;
+        let path = temp_path("journal.jsonl");
+        let journal = SubmissionJournal::open(&path).unwrap();
+        let report = journal
+            .submit(
+                &client_for(&server),
+                (0..101)
+                    .map(|i| {
+                        UrlNotification::updated(format!("https://example.com/{}", i).as_str())
+                    })
+                    .collect(),
+            )
+            .await
+            .unwrap();
+        // 先に送ったバッチの結果は失われず、失敗したバッチはエラーとともに返る
+        assert_eq!(report.results.len(), 100);
+        assert_eq!(report.failed.len(), 1);
+        assert_eq!(
+            urls(&report.failed[0].notifications),
+            vec!["https://example.com/100"]
+        );
+        assert!(matches!(
+            report.failed[0].error,
+            GoogleApiError::HttpStatus { status: 500, .. }
+        ));
+        let lines = std::fs::read_to_string(&path).unwrap();
+        assert!(
+            lines.contains(r#""event":"failure","url":"https://example.com/100","error":"#),
+            "{}",
+            lines
+        );
+        assert_eq!(
+            urls(&journal.unfinished().unwrap()),
+            vec!["https://example.com/100"]
+        );
+
+        journal.compact().unwrap();
+        let lines = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(lines.lines().count(), 1);
+        assert_eq!(
+            urls(&journal.unfinished().unwrap()),
+            vec!["https://example.com/100"]
+        );
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
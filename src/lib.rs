@@ -4,7 +4,9 @@
 //!
 //! Since it defines the structures needed for the API, it can simplify API access.
 //!
-//! ※ Token generation is not supported in this API.
+//! Access tokens can be passed to each call, supplied by a [`TokenProvider`], or, with the
+//! `service-account` feature, generated from a service account key by
+//! `ServiceAccountTokenProvider`.
 //!
 //! ## Batch API
 //! With the batch API, you can make multiple publish requests in bulk. When processing a large number of job listings consecutively, batch requests are processed faster.
@@ -37,15 +39,73 @@
 //!         .await;
 //! }
 //! ```
+//!
+//! ## Reusable client
+//! [`IndexingClient`] keeps one HTTP connection pool for all calls and can be configured
+//! (timeouts, user agent, proxy, base URL, or an existing `reqwest::Client`).
+//! ```rust
+//! use google_indexing_api::{
+//!     IndexingClient, StaticTokenProvider, UrlNotificationsApi, UrlNotificationsType,
+//! };
+//! use std::time::Duration;
+//! async fn example_client(token: &str) {
+//!     let client = IndexingClient::builder()
+//!         .connect_timeout(Duration::from_secs(5))
+//!         .user_agent("my-job-board/1.0")
+//!         .token_provider(StaticTokenProvider::new(token))
+//!         .build()
+//!         .unwrap();
+//!     client
+//!         .publish("https://example.com/widgets/1", UrlNotificationsType::UPDATED)
+//!         .await;
+//!     // The existing API can share the same client
+//!     let api = UrlNotificationsApi::from(client.clone());
+//!     api.get_metadata(token, "https://example.com/widgets/1").await;
+//! }
+//! ```
+mod auth;
+mod client;
+mod coalesce;
+#[cfg(feature = "structured-data")]
+mod eligibility;
 mod error;
 mod http;
+mod journal;
+mod pool;
+mod property;
+mod queue;
+mod quota;
+mod retry;
+mod router;
+#[cfg(feature = "sitemap")]
+mod sitemap;
+mod smart;
+#[cfg(test)]
+pub(crate) mod test_support;
+mod timestamp;
+mod validate;
 
-use crate::http::HttpClient;
-use crate::GoogleApiError;
+pub use auth::*;
+pub use client::*;
+pub use coalesce::*;
+#[cfg(feature = "structured-data")]
+pub use eligibility::*;
 pub use error::*;
+pub use journal::*;
+pub use pool::*;
+pub use property::*;
+pub use queue::*;
+pub use quota::*;
+pub use retry::*;
+pub use router::*;
 use serde::{Deserialize, Serialize};
-use serde_json::{json, Value};
-use urlencoding::encode;
+use serde_json::Value;
+#[cfg(feature = "sitemap")]
+pub use sitemap::*;
+pub use smart::*;
+use std::sync::Arc;
+pub use timestamp::*;
+pub use validate::*;
 
 /// API Access Endpoint
 pub struct GoogleIndexingApi {}
@@ -58,7 +118,7 @@ impl GoogleIndexingApi {
 
 /// URL Notification Type
 /// UPDATE or DELETE
-#[derive(Default, Debug, Serialize, Deserialize, Clone)]
+#[derive(Default, Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
 pub enum UrlNotificationsType {
     #[serde(rename = "URL_NOTIFICATION_TYPE_UNSPECIFIED")]
     #[default]
@@ -82,11 +142,56 @@ impl std::fmt::Display for UrlNotificationsType {
     }
 }
 
+/// A URL and the type of notification to send for it.
+///
+/// Lets a single batch mix `UPDATED` and `DELETED` notifications.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
+pub struct UrlNotification {
+    pub url: String,
+    #[serde(rename = "type")]
+    pub url_type: UrlNotificationsType,
+}
+
+impl UrlNotification {
+    pub fn new(url: &str, url_type: UrlNotificationsType) -> Self {
+        UrlNotification {
+            url: url.to_string(),
+            url_type,
+        }
+    }
+    pub fn updated(url: &str) -> Self {
+        UrlNotification::new(url, UrlNotificationsType::UPDATED)
+    }
+    pub fn deleted(url: &str) -> Self {
+        UrlNotification::new(url, UrlNotificationsType::DELETED)
+    }
+}
+
+impl<S: Into<String>> From<(S, UrlNotificationsType)> for UrlNotification {
+    fn from((url, url_type): (S, UrlNotificationsType)) -> Self {
+        UrlNotification {
+            url: url.into(),
+            url_type,
+        }
+    }
+}
+
 // https://developers.go
 // ogle.com/search/apis/indexing-api/v3/reference/indexing/rest/v3/urlNotifications?hl=ja
 /// urlNotifications API
-#[derive(Default)]
-pub struct UrlNotificationsApi {}
+///
+/// A thin wrapper around [`IndexingClient`]. Build it from a configured client with
+/// `UrlNotificationsApi::from(client)` to reuse its connection pool and settings.
+#[derive(Default, Clone, Debug)]
+pub struct UrlNotificationsApi {
+    client: IndexingClient,
+}
+
+impl From<IndexingClient> for UrlNotificationsApi {
+    fn from(client: IndexingClient) -> Self {
+        UrlNotificationsApi { client }
+    }
+}
 
 impl UrlNotificationsApi {
     pub async fn publish(
@@ -94,31 +199,19 @@ impl UrlNotificationsApi {
         token: &str,
         url: &str,
         url_type: UrlNotificationsType,
-    ) -> Result<Value, GoogleApiError> {
-        HttpClient::post(
-            token,
-            "https://indexing.googleapis.com/v3/urlNotifications:publish",
-            json!({
-                "url": url,
-                "type": url_type.to_string(),
-            }),
-        )
-        .await
+    ) -> Result<PublishUrlNotificationResponse, GoogleApiError> {
+        self.client
+            .publish_with(&StaticTokenProvider::new(token), url, url_type)
+            .await
     }
     pub async fn get_metadata(
         &self,
         token: &str,
         url: &str,
     ) -> Result<ResponseUrlNotificationMetadata, GoogleApiError> {
-        HttpClient::get(
-            token,
-            format!(
-                r#"https://indexing.googleapis.com/v3/urlNotifications/metadata?url={}"#,
-                encode(url),
-            )
-            .as_str(),
-        )
-        .await
+        self.client
+            .get_metadata_with(&StaticTokenProvider::new(token), url)
+            .await
     }
     pub async fn batch(
         &self,
@@ -126,7 +219,52 @@ impl UrlNotificationsApi {
         urls: Vec<String>,
         url_type: UrlNotificationsType,
     ) -> Result<Vec<ResponseGoogleIndexingBatch>, GoogleApiError> {
-        HttpClient::execute(token, urls, url_type).await
+        self.client
+            .batch_with(&StaticTokenProvider::new(token), urls, url_type)
+            .await
+    }
+    /// Fetch notification metadata for 1 to 100 URLs in a single batch request.
+    /// See [`IndexingClient::batch_get_metadata`].
+    pub async fn batch_get_metadata(
+        &self,
+        token: &str,
+        urls: Vec<String>,
+    ) -> Result<Vec<Result<ResponseUrlNotificationMetadata, GoogleApiError>>, GoogleApiError> {
+        self.client
+            .batch_get_metadata_with(&StaticTokenProvider::new(token), urls)
+            .await
+    }
+    /// Like `batch`, but each URL has its own notification type.
+    pub async fn batch_notifications(
+        &self,
+        token: &str,
+        notifications: Vec<UrlNotification>,
+    ) -> Result<Vec<ResponseGoogleIndexingBatch>, GoogleApiError> {
+        self.client
+            .batch_notifications_with(&StaticTokenProvider::new(token), notifications)
+            .await
+    }
+    /// Like `batch_all`, but each URL has its own notification type.
+    pub async fn batch_all_notifications(
+        &self,
+        token: &str,
+        notifications: Vec<UrlNotification>,
+    ) -> Result<BatchReport, GoogleApiError> {
+        self.client
+            .batch_all_notifications_with(&StaticTokenProvider::new(token), notifications)
+            .await
+    }
+    /// Notify Google about any number of URLs, split into batches of 100.
+    /// See [`IndexingClient::batch_all`].
+    pub async fn batch_all(
+        &self,
+        token: &str,
+        urls: Vec<String>,
+        url_type: UrlNotificationsType,
+    ) -> Result<BatchReport, GoogleApiError> {
+        self.client
+            .batch_all_with(&StaticTokenProvider::new(token), urls, url_type)
+            .await
     }
 }
 
@@ -140,6 +278,73 @@ pub struct ResponseUrlNotificationMetadata {
     pub latest_remove: Option<ResponseUrlNotification>,
 }
 
+impl ResponseUrlNotificationMetadata {
+    /// The most recent of `latest_update` and `latest_remove`.
+    ///
+    /// A notification whose `notify_time` cannot be parsed counts as older than one
+    /// that can. On a tie, `latest_update` is returned.
+    pub fn last_notification(&self) -> Option<&ResponseUrlNotification> {
+        match (&self.latest_update, &self.latest_remove) {
+            (Some(update), Some(remove)) => {
+                if remove.notify_timestamp() > update.notify_timestamp() {
+                    Some(remove)
+                } else {
+                    Some(update)
+                }
+            }
+            (update, remove) => update.as_ref().or(remove.as_ref()),
+        }
+    }
+    /// Whether the last notification Google received for the URL is a removal.
+    pub fn is_currently_deleted(&self) -> bool {
+        self.last_notification()
+            .is_some_and(|n| n.url_type == UrlNotificationsType::DELETED)
+    }
+    /// Whether Google was notified of an update at or after `time`, and the URL was
+    /// not removed since. Same as [`CachedMetadata::is_up_to_date`].
+    pub fn is_updated_since<T: Into<Timestamp>>(&self, time: T) -> bool {
+        CachedMetadata::from_metadata(self).is_up_to_date(time)
+    }
+}
+
+/// Response of `urlNotifications.publish`: `{"urlNotificationMetadata": {...}}`.
+///
+/// The raw JSON is kept and available through [`PublishUrlNotificationResponse::raw`].
+#[derive(Debug, Clone)]
+pub struct PublishUrlNotificationResponse {
+    pub url_notification_metadata: ResponseUrlNotificationMetadata,
+    raw: Value,
+}
+
+impl PublishUrlNotificationResponse {
+    /// The response body as received.
+    pub fn raw(&self) -> &Value {
+        &self.raw
+    }
+    pub fn into_raw(self) -> Value {
+        self.raw
+    }
+}
+
+impl<'de> Deserialize<'de> for PublishUrlNotificationResponse {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        struct Typed {
+            #[serde(rename = "urlNotificationMetadata")]
+            url_notification_metadata: ResponseUrlNotificationMetadata,
+        }
+        let raw = Value::deserialize(deserializer)?;
+        let typed = Typed::deserialize(&raw).map_err(serde::de::Error::custom)?;
+        Ok(PublishUrlNotificationResponse {
+            url_notification_metadata: typed.url_notification_metadata,
+            raw,
+        })
+    }
+}
+
 /// Response Url Notification
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct ResponseUrlNotification {
@@ -150,12 +355,21 @@ pub struct ResponseUrlNotification {
     pub notify_time: String,
 }
 
+impl ResponseUrlNotification {
+    /// `notify_time` parsed, or `None` if it is not an RFC 3339 timestamp.
+    pub fn notify_timestamp(&self) -> Option<Timestamp> {
+        Timestamp::parse(self.notify_time.as_str())
+    }
+}
+
 /// Google Index Batch Response
 #[derive(Debug, Default)]
 pub struct ResponseGoogleIndexingBatch {
     url: String,
     status_code: u16,
     value: String,
+    attempts: u32,
+    retry_error: Option<Arc<GoogleApiError>>,
 }
 
 impl ResponseGoogleIndexingBatch {
@@ -168,7 +382,54 @@ impl ResponseGoogleIndexingBatch {
     pub fn value(&self) -> &str {
         self.value.as_str()
     }
+    /// The body as JSON, or `Null` if it is not valid JSON. See [`result`](Self::result)
+    /// for a typed response.
     pub fn json(&self) -> Value {
         serde_json::from_str(self.value.as_str()).unwrap_or_default()
     }
+    /// The typed publish response of a successful part, or the Google error of a failed one.
+    ///
+    /// A failed part whose body is not a Google error envelope gives a [`GoogleErrorBody`]
+    /// with the part's status code and the raw body as `message`. A successful part whose
+    /// body cannot be parsed is reported the same way.
+    pub fn result(&self) -> Result<PublishUrlNotificationResponse, GoogleErrorBody> {
+        if !(200..300).contains(&self.status_code) {
+            return Err(GoogleErrorBody::from_response(
+                self.status_code,
+                self.value.as_str(),
+            ));
+        }
+        serde_json::from_str(self.value.as_str()).map_err(|e| GoogleErrorBody {
+            code: self.status_code,
+            message: format!("invalid publish response: {}: {}", e, self.value),
+            ..GoogleErrorBody::default()
+        })
+    }
+    /// Number of times this URL was sent. Greater than 1 when the part was retried
+    /// (see [`RetryPolicy::retry_batch_parts`]).
+    pub fn attempts(&self) -> u32 {
+        self.attempts
+    }
+    /// Why this part was not retried further, when it was not for running out of
+    /// attempts: the re-sent batch failed as a whole, or the [`QuotaGovernor`] had no
+    /// budget left for it. The status code and body are those of the last attempt.
+    pub fn retry_error(&self) -> Option<&GoogleApiError> {
+        self.retry_error.as_deref()
+    }
+    /// Whether the part failed with a transient error that is worth retrying.
+    pub fn is_retriable(&self) -> bool {
+        !(200..300).contains(&self.status_code)
+            && RetryPolicy::is_retriable_response(self.status_code, self.value.as_str())
+    }
+    /// Whether the part failed because a quota or rate limit was exceeded
+    /// (`RESOURCE_EXHAUSTED` or HTTP 429).
+    pub fn is_quota_exceeded(&self) -> bool {
+        if (200..300).contains(&self.status_code) {
+            return false;
+        }
+        match GoogleErrorBody::parse(self.value.as_str()) {
+            Some(error) => error.is_quota_exceeded(),
+            None => self.status_code == 429,
+        }
+    }
 }
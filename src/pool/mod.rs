@@ -0,0 +1,662 @@
+use crate::auth::TokenProvider;
+use crate::client::{BatchFailure, BatchReport, IndexingClient};
+use crate::error::{GoogleApiError, GoogleErrorBody};
+use crate::quota::{QuotaBucket, QuotaGovernor};
+use crate::{
+    PublishUrlNotificationResponse, ResponseGoogleIndexingBatch, UrlNotification,
+    UrlNotificationsType,
+};
+use futures_util::future::join_all;
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+const MINUTE: Duration = Duration::from_secs(60);
+
+/// Spreads publish requests over several Google Cloud projects, each with its own
+/// credentials and quota.
+///
+/// The Indexing API quota is per project. Every project in the pool has a
+/// [`TokenProvider`] for one of its service accounts and a [`QuotaGovernor`] with its
+/// limits. [`batch_all`](CredentialPool::batch_all) splits the URLs between the
+/// projects in proportion to their remaining budget, and
+/// [`publish`](CredentialPool::publish) uses the project with the most budget left.
+///
+/// When the API answers `RESOURCE_EXHAUSTED` for a project, the affected URLs are sent
+/// with the next project, and the project is skipped until its quota resets: midnight
+/// Pacific Time for a daily quota, one minute otherwise.
+/// ```rust
+/// use google_indexing_api::{
+///     CredentialPool, IndexingClient, QuotaGovernor, QuotaLimit, StaticTokenProvider,
+///     UrlNotificationsType,
+/// };
+/// async fn example_pool(token_a: &str, token_b: &str, urls: Vec<String>) {
+///     let pool = CredentialPool::new(IndexingClient::new())
+///         .project("indexing-a", StaticTokenProvider::new(token_a), QuotaGovernor::new())
+///         .project(
+///             "indexing-b",
+///             StaticTokenProvider::new(token_b),
+///             QuotaGovernor::new().publish(QuotaLimit::unlimited().per_day(1000)),
+///         );
+///     pool.batch_all(urls, UrlNotificationsType::UPDATED).await;
+/// }
+/// ```
+#[derive(Debug)]
+pub struct CredentialPool {
+    client: IndexingClient,
+    projects: Vec<PoolProject>,
+}
+
+#[derive(Debug)]
+struct PoolProject {
+    name: String,
+    client: IndexingClient,
+    quota: Arc<QuotaGovernor>,
+    // クォータ超過が返ってきたら、この時刻まで使わない
+    exhausted_until: Mutex<Option<Instant>>,
+}
+
+impl PoolProject {
+    // いま送れる publish の件数
+    fn budget(&self, now: Instant) -> Result<u64, GoogleApiError> {
+        if self.exhausted_until(now).is_some() {
+            return Ok(0);
+        }
+        let remaining = self.quota.remaining(QuotaBucket::Publish)?;
+        Ok([remaining.today, remaining.this_minute]
+            .into_iter()
+            .flatten()
+            .map(u64::from)
+            .min()
+            .unwrap_or(u64::from(u32::MAX)))
+    }
+
+    // 予算が空くまでの時間
+    fn retry_in(&self, now: Instant) -> Result<Duration, GoogleApiError> {
+        if let Some(until) = self.exhausted_until(now) {
+            return Ok(until - now);
+        }
+        let remaining = self.quota.remaining(QuotaBucket::Publish)?;
+        Ok(match remaining.today {
+            Some(0) => remaining.resets_in,
+            _ => MINUTE,
+        })
+    }
+
+    fn exhausted_until(&self, now: Instant) -> Option<Instant> {
+        self.exhausted_until
+            .lock()
+            .unwrap()
+            .filter(|until| now < *until)
+    }
+
+    fn mark_exhausted(&self, error: &GoogleApiError) {
+        let cooldown = match error {
+            GoogleApiError::QuotaBudgetExhausted { retry_in, .. } => *retry_in,
+            _ => self.cooldown(error.google_error()),
+        };
+        self.exhausted_for(cooldown);
+    }
+
+    fn mark_part_exhausted(&self, part: &ResponseGoogleIndexingBatch) {
+        let error = GoogleErrorBody::parse(part.value());
+        self.exhausted_for(self.cooldown(error.as_ref()));
+    }
+
+    // 日次のクォータなら太平洋時間の 0 時まで、それ以外は 1 分休ませる
+    fn cooldown(&self, error: Option<&GoogleErrorBody>) -> Duration {
+        if error.is_some_and(is_daily_quota) {
+            if let Ok(remaining) = self.quota.remaining(QuotaBucket::Publish) {
+                return remaining.resets_in;
+            }
+        }
+        MINUTE
+    }
+
+    fn exhausted_for(&self, cooldown: Duration) {
+        let until = Instant::now() + cooldown;
+        let mut exhausted_until = self.exhausted_until.lock().unwrap();
+        if exhausted_until.is_none_or(|current| current < until) {
+            *exhausted_until = Some(until);
+        }
+    }
+}
+
+// Google のメッセージは "... limit 'Publish requests per day' ..." の形
+fn is_daily_quota(error: &GoogleErrorBody) -> bool {
+    let per_day = |text: &str| text.to_ascii_lowercase().contains("per day");
+    per_day(error.message.as_str())
+        || error
+            .quota_violations()
+            .iter()
+            .any(|violation| per_day(violation.description.as_str()))
+}
+
+impl CredentialPool {
+    /// Sends through clones of `client`. They share its connection pool and settings,
+    /// but use the token provider and quota governor of each project.
+    pub fn new(client: IndexingClient) -> Self {
+        CredentialPool {
+            client,
+            projects: Vec::new(),
+        }
+    }
+
+    /// Adds a project. Adding a project with the same name again replaces it.
+    /// Pass the same `Arc<QuotaGovernor>` as other clients of the project to share
+    /// its budget with them.
+    pub fn project<P, Q>(mut self, name: &str, provider: P, governor: Q) -> Self
+    where
+        P: TokenProvider + 'static,
+        Q: Into<Arc<QuotaGovernor>>,
+    {
+        let quota = governor.into();
+        let project = PoolProject {
+            name: name.to_string(),
+            client: self.client.with_account(Arc::new(provider), quota.clone()),
+            quota,
+            exhausted_until: Mutex::new(None),
+        };
+        match self.projects.iter_mut().find(|p| p.name == name) {
+            Some(current) => *current = project,
+            None => self.projects.push(project),
+        }
+        self
+    }
+
+    /// The project names, in the order they were added.
+    pub fn projects(&self) -> Vec<&str> {
+        self.projects.iter().map(|p| p.name.as_str()).collect()
+    }
+
+    /// The quota governor of `project`, to check its remaining budget.
+    pub fn quota_governor(&self, project: &str) -> Option<&QuotaGovernor> {
+        self.projects
+            .iter()
+            .find(|p| p.name == project)
+            .map(|p| p.quota.as_ref())
+    }
+
+    /// Whether `project` is skipped because the API answered `RESOURCE_EXHAUSTED`.
+    pub fn is_exhausted(&self, project: &str) -> bool {
+        self.projects
+            .iter()
+            .find(|p| p.name == project)
+            .is_some_and(|p| p.exhausted_until(Instant::now()).is_some())
+    }
+
+    /// Notify Google about one URL with the project that has the most budget left,
+    /// falling back to the next one on a quota error.
+    pub async fn publish(
+        &self,
+        url: &str,
+        url_type: UrlNotificationsType,
+    ) -> Result<PublishUrlNotificationResponse, GoogleApiError> {
+        let now = Instant::now();
+        let mut order = self
+            .budgets(now)?
+            .into_iter()
+            .enumerate()
+            .filter(|(_, budget)| *budget > 0)
+            .collect::<Vec<_>>();
+        order.sort_by_key(|(_, budget)| Reverse(*budget));
+        let mut last_error = None;
+        for (index, _) in order {
+            let project = &self.projects[index];
+            match project.client.publish(url, url_type.clone()).await {
+                Err(e) if e.is_quota_exceeded() => {
+                    project.mark_exhausted(&e);
+                    last_error = Some(e);
+                }
+                result => return result,
+            }
+        }
+        match last_error {
+            Some(e) => Err(e),
+            None => Err(self.exhausted(now)?),
+        }
+    }
+
+    /// Notify Google about any number of URLs, spread over the projects.
+    pub async fn batch_all(
+        &self,
+        urls: Vec<String>,
+        url_type: UrlNotificationsType,
+    ) -> Result<BatchReport, GoogleApiError> {
+        let notifications = urls
+            .into_iter()
+            .map(|url| UrlNotification::new(url.as_str(), url_type.clone()))
+            .collect();
+        self.batch_all_notifications(notifications).await
+    }
+
+    /// Like [`IndexingClient::batch_all_notifications`], but the notifications are split
+    /// between the projects in proportion to their remaining budget.
+    ///
+    /// Parts that fail with `RESOURCE_EXHAUSTED` are sent again with another project
+    /// while any has budget left; otherwise their failed result is returned. The
+    /// results are in the order of `notifications`. Notifications that could not be
+    /// sent, because a batch failed as a whole or the budget ran out after a failover,
+    /// are in [`BatchReport::failed`] with their error. If the projects together do not
+    /// have the budget for all of them, nothing is sent and
+    /// [`GoogleApiError::QuotaBudgetExhausted`] is returned.
+    pub async fn batch_all_notifications(
+        &self,
+        notifications: Vec<UrlNotification>,
+    ) -> Result<BatchReport, GoogleApiError> {
+        // 結果を入力の位置に戻せるよう、先に正規化と重複の除去を済ませる
+        let notifications = self.client.prepare(notifications)?;
+        let mut results = notifications.iter().map(|_| None).collect::<Vec<_>>();
+        let mut pending = (0..notifications.len()).collect::<Vec<_>>();
+        // 送り直さない通知の位置とエラー
+        let mut failures: Vec<(Vec<usize>, GoogleApiError)> = Vec::new();
+        let mut quota_error = None;
+        let mut first_round = true;
+        while !pending.is_empty() {
+            let now = Instant::now();
+            let budgets = self.budgets(now)?;
+            let total = budgets.iter().sum::<u64>();
+            if first_round && total < pending.len() as u64 {
+                return Err(self.exhausted(now)?);
+            }
+            if total == 0 {
+                break;
+            }
+            first_round = false;
+
+            pending.sort_unstable();
+            let mut rest = pending.into_iter();
+            let groups = allocate(budgets.as_slice(), rest.len())
+                .into_iter()
+                .enumerate()
+                .filter(|(_, share)| *share > 0)
+                .map(|(index, share)| (index, rest.by_ref().take(share).collect::<Vec<_>>()))
+                .collect::<Vec<_>>();
+            // 予算に入らなかった分は次の回に回す
+            pending = rest.collect();
+
+            let sent = join_all(groups.into_iter().map(|(index, indices)| {
+                let project = &self.projects[index];
+                let batch = indices
+                    .iter()
+                    .map(|&i| notifications[i].clone())
+                    .collect::<Vec<_>>();
+                async move {
+                    let result = project.client.batch_all_notifications(batch).await;
+                    (project, indices, result)
+                }
+            }))
+            .await;
+            for (project, indices, result) in sent {
+                let mut failed = Vec::new();
+                match result {
+                    Ok(report) => {
+                        // 正規化済みで重複も無いので、URL で入力の位置に戻せる
+                        let position = indices
+                            .iter()
+                            .map(|&i| (notifications[i].url.as_str(), i))
+                            .collect::<HashMap<_, _>>();
+                        for response in report.results {
+                            let Some(&i) = position.get(response.url()) else {
+                                continue;
+                            };
+                            if response.is_quota_exceeded() {
+                                project.mark_part_exhausted(&response);
+                                pending.push(i);
+                            }
+                            results[i] = Some(response);
+                        }
+                        for failure in report.failed {
+                            let indices = failure
+                                .notifications
+                                .iter()
+                                .filter_map(|n| position.get(n.url.as_str()).copied())
+                                .collect();
+                            failed.push((indices, failure.error));
+                        }
+                    }
+                    Err(e) => failed.push((indices, e)),
+                }
+                for (indices, e) in failed {
+                    if e.is_quota_exceeded() {
+                        project.mark_exhausted(&e);
+                        pending.extend(indices);
+                        quota_error = Some(e);
+                    } else {
+                        failures.push((indices, e));
+                    }
+                }
+            }
+        }
+
+        // 予算が尽きて送れなかった分
+        let unsent = pending
+            .into_iter()
+            .filter(|&i| results[i].is_none())
+            .collect::<Vec<_>>();
+        if !unsent.is_empty() {
+            let error = match quota_error {
+                Some(e) => e,
+                None => self.exhausted(Instant::now()).unwrap_or_else(|e| e),
+            };
+            failures.push((unsent, error));
+        }
+        for (indices, _) in failures.iter_mut() {
+            indices.sort_unstable();
+        }
+        failures.sort_by_key(|(indices, _)| indices.first().copied());
+        Ok(BatchReport {
+            results: results.into_iter().flatten().collect(),
+            failed: failures
+                .into_iter()
+                .map(|(indices, error)| BatchFailure {
+                    notifications: indices
+                        .into_iter()
+                        .map(|i| notifications[i].clone())
+                        .collect(),
+                    error,
+                })
+                .collect(),
+        })
+    }
+
+    fn budgets(&self, now: Instant) -> Result<Vec<u64>, GoogleApiError> {
+        if self.projects.is_empty() {
+            return Err(GoogleApiError::InvalidArgument(
+                "no project is configured in this CredentialPool".to_string(),
+            ));
+        }
+        self.projects.iter().map(|p| p.budget(now)).collect()
+    }
+
+    // どのプロジェクトにも予算が無いときのエラー。最も早く空く時間を返す
+    fn exhausted(&self, now: Instant) -> Result<GoogleApiError, GoogleApiError> {
+        let mut retry_in = Duration::MAX;
+        for project in &self.projects {
+            retry_in = retry_in.min(project.retry_in(now)?);
+        }
+        Ok(GoogleApiError::QuotaBudgetExhausted {
+            bucket: QuotaBucket::Publish,
+            retry_in,
+        })
+    }
+}
+
+// count 件を予算に比例して割り振る。予算の合計を超える分は割り振らない
+fn allocate(budgets: &[u64], count: usize) -> Vec<usize> {
+    let total = budgets.iter().sum::<u64>();
+    let count = (count as u64).min(total);
+    if count == 0 {
+        return vec![0; budgets.len()];
+    }
+    let mut shares = budgets
+        .iter()
+        .map(|&budget| (u128::from(count) * u128::from(budget) / u128::from(total)) as u64)
+        .collect::<Vec<_>>();
+    // 端数は予算の多い順に 1 件ずつ足す
+    let mut order = (0..budgets.len()).collect::<Vec<_>>();
+    order.sort_by_key(|&i| Reverse(budgets[i]));
+    let mut left = count - shares.iter().sum::<u64>();
+    while left > 0 {
+        for &i in &order {
+            if left > 0 && shares[i] < budgets[i] {
+                shares[i] += 1;
+                left -= 1;
+            }
+        }
+    }
+    shares.into_iter().map(|share| share as usize).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::StaticTokenProvider;
+    use crate::quota::QuotaLimit;
+    use crate::test_support::{batch_ok, batch_response};
+    use httpmock::prelude::*;
+
+    const DAILY_QUOTA_ERROR: &str = r#"{"error":{"code":429,"message":"Quota exceeded for quota metric 'Publish requests' and limit 'Publish requests per day' of service 'indexing.googleapis.com'.","status":"RESOURCE_EXHAUSTED"}}"#;
+
+    fn daily(per_day: u32) -> QuotaGovernor {
+        QuotaGovernor::new().publish(QuotaLimit::unlimited().per_day(per_day))
+    }
+
+    fn pool_for(server: &MockServer, budget_a: u32, budget_b: u32) -> CredentialPool {
+        let client = IndexingClient::builder()
+            .base_url(server.base_url().as_str())
+            .build()
+            .unwrap();
+        CredentialPool::new(client)
+            .project("a", StaticTokenProvider::new("token-a"), daily(budget_a))
+            .project("b", StaticTokenProvider::new("token-b"), daily(budget_b))
+    }
+
+    #[test]
+    fn test_allocate_follows_budget() {
+        assert_eq!(allocate(&[300, 100], 4), vec![3, 1]);
+        assert_eq!(allocate(&[1, 1, 1], 2), vec![1, 1, 0]);
+        assert_eq!(allocate(&[5, 0], 3), vec![3, 0]);
+        assert_eq!(allocate(&[2, 1], 10), vec![2, 1]);
+        assert_eq!(allocate(&[0, 0], 1), vec![0, 0]);
+    }
+
+    #[tokio::test]
+    async fn test_batch_all_spreads_by_remaining_budget() {
+        let server = MockServer::start_async().await;
+        let project_a = server
+            .mock_async(|when, then| {
+                when.method(POST)
+                    .path("/batch")
+                    .header("Authorization", "Bearer token-a")
+                    .body_contains("https://example.com/1")
+                    .body_contains("https://example.com/3");
+                then.status(200)
+                    .header("Content-Type", "multipart/mixed; boundary=batch_x")
+                    .body(batch_ok(3));
+            })
+            .await;
+        let project_b = server
+            .mock_async(|when, then| {
+                when.method(POST)
+                    .path("/batch")
+                    .header("Authorization", "Bearer token-b")
+                    .body_contains("https://example.com/4");
+                then.status(200)
+                    .header("Content-Type", "multipart/mixed; boundary=batch_x")
+                    .body(batch_response(&[("200 OK", "{}")]));
+            })
+            .await;
+
+        let pool = pool_for(&server, 6, 2);
+        let urls = (1..=4)
+            .map(|i| format!("https://example.com/{}", i))
+            .collect::<Vec<_>>();
+        let report = pool
+            .batch_all(urls.clone(), UrlNotificationsType::UPDATED)
+            .await
+            .unwrap();
+        project_a.assert_async().await;
+        project_b.assert_async().await;
+        assert!(report.is_complete());
+        assert_eq!(
+            report.results.iter().map(|r| r.url()).collect::<Vec<_>>(),
+            urls
+        );
+        let remaining = |name| {
+            pool.quota_governor(name)
+                .unwrap()
+                .remaining(QuotaBucket::Publish)
+                .unwrap()
+                .today
+        };
+        assert_eq!(remaining("a"), Some(3));
+        assert_eq!(remaining("b"), Some(1));
+
+        // 合計の予算を超える分は送らない
+        let result = pool
+            .batch_all(
+                (1..=5)
+                    .map(|i| format!("https://example.com/x{}", i))
+                    .collect(),
+                UrlNotificationsType::UPDATED,
+            )
+            .await;
+        assert!(matches!(
+            result,
+            Err(GoogleApiError::QuotaBudgetExhausted { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_batch_all_fails_over_on_resource_exhausted() {
+        let server = MockServer::start_async().await;
+        let project_a = server
+            .mock_async(|when, then| {
+                when.method(POST)
+                    .path("/batch")
+                    .header("Authorization", "Bearer token-a");
+                then.status(200)
+                    .header("Content-Type", "multipart/mixed; boundary=batch_x")
+                    .body(batch_response(&[
+                        ("200 OK", "{}"),
+                        ("429 Too Many Requests", DAILY_QUOTA_ERROR),
+                    ]));
+            })
+            .await;
+        let project_b = server
+            .mock_async(|when, then| {
+                when.method(POST)
+                    .path("/batch")
+                    .header("Authorization", "Bearer token-b")
+                    .body_contains("https://example.com/2");
+                then.status(200)
+                    .header("Content-Type", "multipart/mixed; boundary=batch_x")
+                    .body(batch_response(&[("200 OK", "{}")]));
+            })
+            .await;
+
+        // 予算の多い a に 2 件とも割り振られる
+        let pool = pool_for(&server, 100, 1);
+        let report = pool
+            .batch_all_notifications(vec![
+                UrlNotification::updated("https://example.com/1"),
+                UrlNotification::updated("https://example.com/2"),
+            ])
+            .await
+            .unwrap();
+        project_a.assert_async().await;
+        project_b.assert_async().await;
+        assert!(report.is_complete());
+        assert_eq!(
+            report
+                .results
+                .iter()
+                .map(|r| (r.url(), r.status_code()))
+                .collect::<Vec<_>>(),
+            vec![
+                ("https://example.com/1", 200),
+                ("https://example.com/2", 200)
+            ]
+        );
+        assert!(pool.is_exhausted("a"));
+        assert!(!pool.is_exhausted("b"));
+
+        // どちらにも予算が無い
+        let result = pool
+            .publish("https://example.com/3", UrlNotificationsType::UPDATED)
+            .await;
+        assert!(matches!(
+            result,
+            Err(GoogleApiError::QuotaBudgetExhausted { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_batch_all_keeps_results_of_other_projects_on_error() {
+        let server = MockServer::start_async().await;
+        server
+            .mock_async(|when, then| {
+                when.method(POST)
+                    .path("/batch")
+                    .header("Authorization", "Bearer token-a");
+                then.status(200)
+                    .header("Content-Type", "multipart/mixed; boundary=batch_x")
+                    .body(batch_ok(2));
+            })
+            .await;
+        let project_b = server
+            .mock_async(|when, then| {
+                when.method(POST)
+                    .path("/batch")
+                    .header("Authorization", "Bearer token-b");
+                then.status(403).body(
+                    r#"{"error":{"code":403,"message":"Permission denied","status":"PERMISSION_DENIED"}}"#,
+                );
+            })
+            .await;
+
+        let pool = pool_for(&server, 2, 2);
+        let report = pool
+            .batch_all(
+                (1..=4)
+                    .map(|i| format!("https://example.com/{}", i))
+                    .collect(),
+                UrlNotificationsType::UPDATED,
+            )
+            .await
+            .unwrap();
+        project_b.assert_async().await;
+        // b の失敗で a の結果は失われず、b に送った通知はエラーとともに返る
+        assert_eq!(
+            report.results.iter().map(|r| r.url()).collect::<Vec<_>>(),
+            vec!["https://example.com/1", "https://example.com/2"]
+        );
+        assert_eq!(report.failed.len(), 1);
+        assert_eq!(
+            report.failed[0].notifications,
+            vec![
+                UrlNotification::updated("https://example.com/3"),
+                UrlNotification::updated("https://example.com/4"),
+            ]
+        );
+        assert!(matches!(
+            report.failed[0].error,
+            GoogleApiError::HttpStatus { status: 403, .. }
+        ));
+        assert!(!pool.is_exhausted("b"));
+    }
+
+    #[tokio::test]
+    async fn test_publish_fails_over_to_next_project() {
+        let server = MockServer::start_async().await;
+        let project_a = server
+            .mock_async(|when, then| {
+                when.method(POST)
+                    .path("/v3/urlNotifications:publish")
+                    .header("Authorization", "Bearer token-a");
+                then.status(429).body(
+                    r#"{"error":{"code":429,"message":"Rate limit exceeded","status":"RESOURCE_EXHAUSTED"}}"#,
+                );
+            })
+            .await;
+        let project_b = server
+            .mock_async(|when, then| {
+                when.method(POST)
+                    .path("/v3/urlNotifications:publish")
+                    .header("Authorization", "Bearer token-b");
+                then.status(200)
+                    .body(r#"{"urlNotificationMetadata":{"url":"https://example.com/1"}}"#);
+            })
+            .await;
+
+        let pool = pool_for(&server, 10, 5);
+        pool.publish("https://example.com/1", UrlNotificationsType::UPDATED)
+            .await
+            .unwrap();
+        project_a.assert_async().await;
+        project_b.assert_async().await;
+        assert!(pool.is_exhausted("a"));
+    }
+}
@@ -0,0 +1,418 @@
+use crate::client::IndexingClient;
+use crate::error::GoogleApiError;
+use crate::UrlNotification;
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
+
+/// Permission level the Indexing API requires on the property of a URL.
+pub const SITE_OWNER: &str = "siteOwner";
+
+/// A verified Search Console property.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum SearchConsoleProperty {
+    /// A URL-prefix property such as `https://www.example.com/`. It covers the URLs
+    /// that start with the prefix, with the same scheme and host. A default port
+    /// (`:443` for https, `:80` for http) is ignored on both sides.
+    UrlPrefix(String),
+    /// A domain property such as `sc-domain:example.com`. It covers the domain and its
+    /// subdomains, over http and https.
+    Domain(String),
+}
+
+impl SearchConsoleProperty {
+    /// Parses a property as Search Console writes it: `https://www.example.com/` or
+    /// `sc-domain:example.com`. The scheme and host are lowercased.
+    pub fn parse(site_url: &str) -> Result<Self, GoogleApiError> {
+        let site_url = site_url.trim();
+        if let Some(domain) = site_url.strip_prefix("sc-domain:") {
+            let domain = domain.trim_end_matches('.').to_ascii_lowercase();
+            if domain.is_empty() || domain.contains(['/', ':', '@']) {
+                return Err(GoogleApiError::InvalidArgument(format!(
+                    "invalid Search Console domain property: {}",
+                    site_url
+                )));
+            }
+            return Ok(SearchConsoleProperty::Domain(domain));
+        }
+        match split_url(site_url) {
+            Some((scheme, authority, path)) if !authority.is_empty() => {
+                let path = if path.is_empty() { "/" } else { path };
+                Ok(SearchConsoleProperty::UrlPrefix(format!(
+                    "{}://{}{}",
+                    scheme, authority, path
+                )))
+            }
+            _ => Err(GoogleApiError::InvalidArgument(format!(
+                "invalid Search Console property (expected https://host/ or sc-domain:host): {}",
+                site_url
+            ))),
+        }
+    }
+
+    /// Whether the property covers `url`.
+    pub fn covers(&self, url: &str) -> bool {
+        let Some((scheme, authority, path)) = split_url(url) else {
+            return false;
+        };
+        match self {
+            SearchConsoleProperty::UrlPrefix(prefix) => {
+                let path = if path.is_empty() { "/" } else { path };
+                format!("{}://{}{}", scheme, authority, path).starts_with(prefix.as_str())
+            }
+            SearchConsoleProperty::Domain(domain) => {
+                let host = host_of(authority.as_str());
+                host == domain.as_str()
+                    || host
+                        .strip_suffix(domain.as_str())
+                        .is_some_and(|sub| sub.ends_with('.'))
+            }
+        }
+    }
+
+    // 複数の property が URL を含むときは、より狭いものを選ぶ
+    pub(crate) fn specificity(&self) -> (u8, usize) {
+        match self {
+            SearchConsoleProperty::UrlPrefix(prefix) => (1, prefix.len()),
+            SearchConsoleProperty::Domain(domain) => (0, domain.len()),
+        }
+    }
+}
+
+impl Display for SearchConsoleProperty {
+    /// The `siteUrl` form used by Search Console.
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SearchConsoleProperty::UrlPrefix(prefix) => f.write_str(prefix),
+            SearchConsoleProperty::Domain(domain) => write!(f, "sc-domain:{}", domain),
+        }
+    }
+}
+
+/// An entry of the Search Console `sites.list` response.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct SiteEntry {
+    #[serde(rename = "siteUrl")]
+    pub site_url: String,
+    /// `siteOwner`, `siteFullUser`, `siteRestrictedUser` or `siteUnverifiedUser`.
+    #[serde(rename = "permissionLevel", default)]
+    pub permission_level: String,
+}
+
+/// URLs grouped by the property that covers them. See [`SearchConsoleProperties::route`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PropertyRoutes {
+    /// Each property with the notifications it covers, in the order the properties
+    /// were first needed.
+    pub routes: Vec<(SearchConsoleProperty, Vec<UrlNotification>)>,
+    /// Notifications no property covers. Sending them would fail with `403`.
+    pub uncovered: Vec<UrlNotification>,
+}
+
+/// The Search Console properties the account owns.
+///
+/// The Indexing API answers `403 Forbidden` for a URL unless the account is an owner
+/// of a property covering it. Checking URLs against the owned properties finds those
+/// URLs before any quota is used. Set the properties with
+/// [`IndexingClientBuilder::search_console_properties`](crate::IndexingClientBuilder::search_console_properties)
+/// to reject uncovered URLs on every call, or use [`route`](SearchConsoleProperties::route)
+/// to split URLs by property.
+/// ```rust
+/// use google_indexing_api::{SearchConsoleProperties, SearchConsoleProperty};
+/// let properties = SearchConsoleProperties::new()
+///     .with(SearchConsoleProperty::parse("sc-domain:example.com").unwrap())
+///     .with(SearchConsoleProperty::parse("https://jobs.example.org/").unwrap());
+/// assert!(properties.covers("https://www.example.com/jobs/1"));
+/// assert!(properties.covers("https://jobs.example.org/1"));
+/// assert!(!properties.covers("http://jobs.example.org/1"));
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SearchConsoleProperties {
+    properties: Vec<SearchConsoleProperty>,
+}
+
+impl SearchConsoleProperties {
+    pub fn new() -> Self {
+        SearchConsoleProperties::default()
+    }
+
+    /// Parses properties in the `siteUrl` form (`https://host/` or `sc-domain:host`).
+    pub fn parse<I, S>(site_urls: I) -> Result<Self, GoogleApiError>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let properties = site_urls
+            .into_iter()
+            .map(|site_url| SearchConsoleProperty::parse(site_url.as_ref()))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(SearchConsoleProperties { properties })
+    }
+
+    /// Lists the account's properties with the Search Console Sites API and keeps the
+    /// ones it owns. See [`IndexingClient::list_sites`].
+    pub async fn discover(client: &IndexingClient) -> Result<Self, GoogleApiError> {
+        let sites = client.list_sites().await?;
+        let properties = sites
+            .iter()
+            .filter(|site| site.permission_level == SITE_OWNER)
+            // 解析できない siteUrl は無視する
+            .filter_map(|site| SearchConsoleProperty::parse(site.site_url.as_str()).ok())
+            .collect();
+        Ok(SearchConsoleProperties { properties })
+    }
+
+    pub fn with(mut self, property: SearchConsoleProperty) -> Self {
+        self.add(property);
+        self
+    }
+
+    pub fn add(&mut self, property: SearchConsoleProperty) {
+        if !self.properties.contains(&property) {
+            self.properties.push(property);
+        }
+    }
+
+    pub fn properties(&self) -> &[SearchConsoleProperty] {
+        self.properties.as_slice()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.properties.is_empty()
+    }
+
+    /// The narrowest property covering `url`: the longest URL prefix, else the longest
+    /// domain.
+    pub fn property_for(&self, url: &str) -> Option<&SearchConsoleProperty> {
+        self.properties
+            .iter()
+            .filter(|property| property.covers(url))
+            .max_by_key(|property| property.specificity())
+    }
+
+    pub fn covers(&self, url: &str) -> bool {
+        self.property_for(url).is_some()
+    }
+
+    /// Returns `InvalidArgument` naming the index of the first URL no property covers.
+    pub fn check<S: AsRef<str>>(&self, urls: &[S]) -> Result<(), GoogleApiError> {
+        match urls.iter().position(|url| !self.covers(url.as_ref())) {
+            Some(index) => Err(GoogleApiError::InvalidArgument(format!(
+                "url {}: not covered by an owned Search Console property: {}",
+                index,
+                urls[index].as_ref()
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    pub(crate) fn check_url(&self, url: &str) -> Result<(), GoogleApiError> {
+        if self.covers(url) {
+            return Ok(());
+        }
+        Err(GoogleApiError::InvalidArgument(format!(
+            "not covered by an owned Search Console property: {}",
+            url
+        )))
+    }
+
+    /// Groups the notifications by the property that covers them.
+    pub fn route(&self, notifications: Vec<UrlNotification>) -> PropertyRoutes {
+        let mut routes = PropertyRoutes::default();
+        for notification in notifications {
+            let Some(property) = self.property_for(notification.url.as_str()) else {
+                routes.uncovered.push(notification);
+                continue;
+            };
+            match routes.routes.iter_mut().find(|(p, _)| p == property) {
+                Some((_, group)) => group.push(notification),
+                None => routes.routes.push((property.clone(), vec![notification])),
+            }
+        }
+        routes
+    }
+}
+
+// (小文字の scheme, 小文字の host[:port], パス以降)。http/https 以外は None。
+// 既定のポートは付けても付けなくても同じ URL なので落とす
+fn split_url(url: &str) -> Option<(String, String, &str)> {
+    let (scheme, rest) = url.trim().split_once("://")?;
+    let scheme = scheme.to_ascii_lowercase();
+    if scheme != "http" && scheme != "https" {
+        return None;
+    }
+    let end = rest.find(['/', '?', '#']).unwrap_or(rest.len());
+    let (authority, path) = rest.split_at(end);
+    // 認証情報は property の判定に関係しない
+    let authority = authority
+        .rsplit_once('@')
+        .map_or(authority, |(_, host)| host);
+    let default_port = if scheme == "https" { ":443" } else { ":80" };
+    let authority = authority.strip_suffix(default_port).unwrap_or(authority);
+    Some((scheme, authority.to_ascii_lowercase(), path))
+}
+
+fn host_of(authority: &str) -> &str {
+    let host = if authority.starts_with('[') {
+        authority
+            .split_once(']')
+            .map_or(authority, |(host, _)| host)
+    } else {
+        authority
+            .split_once(':')
+            .map_or(authority, |(host, _)| host)
+    };
+    host.trim_end_matches('.')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::StaticTokenProvider;
+    use httpmock::prelude::*;
+
+    fn property(site_url: &str) -> SearchConsoleProperty {
+        SearchConsoleProperty::parse(site_url).unwrap()
+    }
+
+    #[test]
+    fn test_parse_property() {
+        assert_eq!(
+            property("sc-domain:Example.com"),
+            SearchConsoleProperty::Domain("example.com".to_string())
+        );
+        assert_eq!(
+            property("HTTPS://WWW.Example.com"),
+            SearchConsoleProperty::UrlPrefix("https://www.example.com/".to_string())
+        );
+        assert_eq!(
+            property("https://example.com/jobs/").to_string(),
+            "https://example.com/jobs/"
+        );
+        assert_eq!(
+            property("sc-domain:example.com").to_string(),
+            "sc-domain:example.com"
+        );
+        for invalid in [
+            "example.com",
+            "ftp://example.com/",
+            "sc-domain:",
+            "https:///",
+        ] {
+            assert!(
+                SearchConsoleProperty::parse(invalid).is_err(),
+                "{}",
+                invalid
+            );
+        }
+    }
+
+    #[test]
+    fn test_covers() {
+        let domain = property("sc-domain:example.com");
+        assert!(domain.covers("https://example.com/"));
+        assert!(domain.covers("http://jobs.EXAMPLE.com:8080/1"));
+        assert!(!domain.covers("https://badexample.com/"));
+        assert!(!domain.covers("https://example.com.evil.org/"));
+
+        let prefix = property("https://www.example.com/jobs/");
+        assert!(prefix.covers("https://WWW.example.com/jobs/1"));
+        assert!(!prefix.covers("http://www.example.com/jobs/1"));
+        assert!(!prefix.covers("https://www.example.com/blog/1"));
+        assert!(!prefix.covers("https://example.com/jobs/1"));
+        assert!(property("https://www.example.com/").covers("https://www.example.com"));
+
+        // 既定のポートは付けても付けなくても同じ
+        assert!(property("https://example.com/").covers("https://example.com:443/jobs"));
+        assert!(property("https://example.com:443/").covers("https://example.com/jobs"));
+        assert!(property("http://example.com/").covers("http://example.com:80/jobs"));
+        assert!(!property("https://example.com/").covers("https://example.com:8443/jobs"));
+        assert!(!property("http://example.com/").covers("http://example.com:443/jobs"));
+        assert_eq!(
+            property("https://Example.com:443").to_string(),
+            "https://example.com/"
+        );
+    }
+
+    #[test]
+    fn test_route_and_check() {
+        let properties = SearchConsoleProperties::new()
+            .with(property("sc-domain:example.com"))
+            .with(property("https://www.example.com/jobs/"))
+            .with(property("https://example.org/"));
+        let routes = properties.route(vec![
+            UrlNotification::updated("https://www.example.com/jobs/1"),
+            UrlNotification::updated("https://blog.example.com/1"),
+            UrlNotification::deleted("https://example.net/1"),
+            UrlNotification::updated("https://example.org/1"),
+            UrlNotification::deleted("https://www.example.com/jobs/2"),
+        ]);
+        assert_eq!(
+            routes.routes,
+            vec![
+                (
+                    property("https://www.example.com/jobs/"),
+                    vec![
+                        UrlNotification::updated("https://www.example.com/jobs/1"),
+                        UrlNotification::deleted("https://www.example.com/jobs/2"),
+                    ]
+                ),
+                (
+                    property("sc-domain:example.com"),
+                    vec![UrlNotification::updated("https://blog.example.com/1")]
+                ),
+                (
+                    property("https://example.org/"),
+                    vec![UrlNotification::updated("https://example.org/1")]
+                ),
+            ]
+        );
+        assert_eq!(
+            routes.uncovered,
+            vec![UrlNotification::deleted("https://example.net/1")]
+        );
+
+        assert!(properties.check(&["https://example.org/1"]).is_ok());
+        let error = properties
+            .check(&["https://example.org/1", "https://example.net/1"])
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "invalid argument: url 1: not covered by an owned Search Console property: https://example.net/1"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_discover_keeps_owned_properties() {
+        let server = MockServer::start_async().await;
+        let mock = server
+            .mock_async(|when, then| {
+                when.method(GET)
+                    .path("/webmasters/v3/sites")
+                    .header("Authorization", "Bearer test-token");
+                then.status(200).body(
+                    r#"{"siteEntry":[
+                        {"siteUrl":"sc-domain:example.com","permissionLevel":"siteOwner"},
+                        {"siteUrl":"https://example.org/","permissionLevel":"siteFullUser"},
+                        {"siteUrl":"https://jobs.example.net/","permissionLevel":"siteOwner"}
+                    ]}"#,
+                );
+            })
+            .await;
+        let client = IndexingClient::builder()
+            .base_url(server.base_url().as_str())
+            .search_console_base_url(format!("{}/webmasters/v3", server.base_url()).as_str())
+            .token_provider(StaticTokenProvider::new("test-token"))
+            .build()
+            .unwrap();
+
+        let properties = SearchConsoleProperties::discover(&client).await.unwrap();
+        mock.assert_async().await;
+        assert_eq!(
+            properties.properties(),
+            &[
+                property("sc-domain:example.com"),
+                property("https://jobs.example.net/"),
+            ]
+        );
+    }
+}
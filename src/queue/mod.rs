@@ -0,0 +1,491 @@
+use crate::client::IndexingClient;
+use crate::error::GoogleApiError;
+use crate::http::MAX_BATCH_URLS;
+use crate::quota::QuotaBucket;
+use crate::{ResponseGoogleIndexingBatch, UrlNotification};
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime};
+
+/// Priority class of a queued notification. Higher classes are sent first.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Priority {
+    /// Minor edits that can wait for spare quota.
+    Low,
+    #[default]
+    Normal,
+    /// New pages and removals that should reach Google first.
+    High,
+}
+
+/// A notification waiting in a [`NotificationQueue`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct QueuedNotification {
+    pub notification: UrlNotification,
+    pub priority: Priority,
+    /// Drop the notification if it has not been sent by then.
+    pub deadline: Option<SystemTime>,
+    // 同じ優先度・期限なら先に入れたものから送る
+    seq: u64,
+}
+
+/// Result of [`NotificationQueue::send`].
+#[derive(Debug, Default)]
+pub struct QueueReport {
+    /// Results of the notifications that were sent, in the order they were sent.
+    pub sent: Vec<ResponseGoogleIndexingBatch>,
+    /// Notifications left in the queue for a later run, because the budget ran out,
+    /// Google answered with a quota error, or a batch failed. They are still in the queue.
+    pub deferred: Vec<QueuedNotification>,
+    /// Notifications dropped because their deadline passed before they could be sent.
+    pub expired: Vec<QueuedNotification>,
+    /// Time until the daily quota resets, when a [`QuotaGovernor`](crate::QuotaGovernor)
+    /// is configured and something was deferred.
+    pub retry_in: Option<Duration>,
+    /// The error of the batch that failed with an error other than a quota error.
+    /// Sending stopped there; that batch and the rest are in `deferred`.
+    pub error: Option<GoogleApiError>,
+}
+
+/// Prioritized queue in front of `batch`, for when there are more URLs than quota.
+///
+/// [`send`](NotificationQueue::send) sends the most important notifications the budget
+/// allows: higher [`Priority`] first, then the earliest deadline, then the oldest. What
+/// does not fit stays in the queue and spills over to the next run, e.g. after the daily
+/// quota resets. Notifications whose deadline has passed are dropped.
+/// ```rust
+/// use google_indexing_api::{IndexingClient, NotificationQueue, Priority, UrlNotification};
+/// use std::time::{Duration, SystemTime};
+/// async fn example_queue(client: &IndexingClient) {
+///     let mut queue = NotificationQueue::new();
+///     queue.push(UrlNotification::updated("https://example.com/jobs/new"), Priority::High);
+///     queue.push(UrlNotification::deleted("https://example.com/jobs/expired"), Priority::High);
+///     queue.push_with_deadline(
+///         UrlNotification::updated("https://example.com/jobs/edited"),
+///         Priority::Low,
+///         SystemTime::now() + Duration::from_secs(3 * 24 * 3600),
+///     );
+///     let report = queue.send(client).await.unwrap();
+///     println!("{} sent, {} deferred", report.sent.len(), report.deferred.len());
+/// }
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct NotificationQueue {
+    items: Vec<QueuedNotification>,
+    next_seq: u64,
+}
+
+impl NotificationQueue {
+    pub fn new() -> Self {
+        NotificationQueue::default()
+    }
+
+    pub fn push(&mut self, notification: UrlNotification, priority: Priority) {
+        self.push_item(notification, priority, None);
+    }
+
+    pub fn push_with_deadline(
+        &mut self,
+        notification: UrlNotification,
+        priority: Priority,
+        deadline: SystemTime,
+    ) {
+        self.push_item(notification, priority, Some(deadline));
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// The queued notifications, in the order they would be sent.
+    pub fn pending(&self) -> Vec<&QueuedNotification> {
+        let mut items = self.items.iter().collect::<Vec<_>>();
+        items.sort_by(|a, b| send_order(a, b));
+        items
+    }
+
+    /// Sends as many notifications as the client's
+    /// [`QuotaGovernor`](crate::QuotaGovernor) allows today (all of them without one).
+    ///
+    /// With a [`UrlValidator`](crate::UrlValidator) on the client, queued URLs are
+    /// normalized first. Entries for the same URL are merged into the one sent first,
+    /// with the type of the one pushed last.
+    pub async fn send(&mut self, client: &IndexingClient) -> Result<QueueReport, GoogleApiError> {
+        self.send_up_to(client, usize::MAX).await
+    }
+
+    /// Like [`send`](NotificationQueue::send), but sends at most `limit` notifications.
+    ///
+    /// If a batch fails with an error other than a quota error, its notifications and
+    /// the rest stay in the queue and the error is in [`QueueReport::error`], next to
+    /// the results of the batches sent before it. `Err` means nothing was sent, e.g.
+    /// because the quota store could not be read.
+    pub async fn send_up_to(
+        &mut self,
+        client: &IndexingClient,
+        limit: usize,
+    ) -> Result<QueueReport, GoogleApiError> {
+        let mut budget = limit;
+        let mut retry_in = None;
+        if let Some(governor) = client.quota_governor() {
+            let remaining = governor.remaining(QuotaBucket::Publish)?;
+            if let Some(today) = remaining.today {
+                budget = budget.min(today as usize);
+            }
+            retry_in = Some(remaining.resets_in);
+        }
+
+        // 先に正規化する。不正な URL があれば何も変えずに返す
+        let urls = client.normalize_each(
+            self.items
+                .iter()
+                .map(|q| q.notification.url.clone())
+                .collect(),
+        )?;
+        for (item, url) in self.items.iter_mut().zip(urls) {
+            item.notification.url = url;
+        }
+
+        let now = SystemTime::now();
+        let mut report = QueueReport::default();
+        let (expired, items): (Vec<_>, Vec<_>) = std::mem::take(&mut self.items)
+            .into_iter()
+            .partition(|q| q.deadline.is_some_and(|deadline| deadline <= now));
+        report.expired = expired;
+        // バッチの中で重複が除かれると結果と通知が対応しなくなるので、先にまとめる
+        let mut items = merge_duplicates(items);
+
+        let mut rest = items.split_off(budget.min(items.len()));
+        let mut pending = items.into_iter().peekable();
+        while pending.peek().is_some() {
+            let chunk = pending.by_ref().take(MAX_BATCH_URLS).collect::<Vec<_>>();
+            let notifications = chunk.iter().map(|q| q.notification.clone()).collect();
+            match client.batch_notifications(notifications).await {
+                Ok(results) => {
+                    for (item, result) in chunk.into_iter().zip(results) {
+                        // Google 側でクォータ切れになった URL は次回に回す
+                        if result
+                            .result()
+                            .is_err_and(|error| error.is_quota_exceeded())
+                        {
+                            rest.push(item);
+                        }
+                        report.sent.push(result);
+                    }
+                }
+                Err(e) => {
+                    rest.extend(chunk);
+                    rest.extend(pending.by_ref());
+                    if !e.is_quota_exceeded() {
+                        report.error = Some(e);
+                    }
+                }
+            }
+        }
+
+        rest.sort_by(send_order);
+        report.deferred = rest.clone();
+        if !report.deferred.is_empty() {
+            report.retry_in = retry_in;
+        }
+        self.items = rest;
+        Ok(report)
+    }
+
+    fn push_item(
+        &mut self,
+        notification: UrlNotification,
+        priority: Priority,
+        deadline: Option<SystemTime>,
+    ) {
+        self.items.push(QueuedNotification {
+            notification,
+            priority,
+            deadline,
+            seq: self.next_seq,
+        });
+        self.next_seq += 1;
+    }
+}
+
+// 同じ URL は送る順で先のものにまとめ、種類は最後に入れたものにする。送る順に並べて返す
+fn merge_duplicates(mut items: Vec<QueuedNotification>) -> Vec<QueuedNotification> {
+    items.sort_by(send_order);
+    let mut merged: Vec<QueuedNotification> = Vec::with_capacity(items.len());
+    // URL ごとの merged の位置と、種類を取った通知の seq
+    let mut seen: HashMap<String, (usize, u64)> = HashMap::new();
+    for item in items {
+        match seen.get_mut(&item.notification.url) {
+            Some((index, latest)) => {
+                if item.seq > *latest {
+                    *latest = item.seq;
+                    merged[*index].notification.url_type = item.notification.url_type;
+                }
+            }
+            None => {
+                seen.insert(item.notification.url.clone(), (merged.len(), item.seq));
+                merged.push(item);
+            }
+        }
+    }
+    merged
+}
+
+// 優先度の高い順、期限の早い順 (期限なしは最後)、入れた順
+fn send_order(a: &QueuedNotification, b: &QueuedNotification) -> std::cmp::Ordering {
+    b.priority
+        .cmp(&a.priority)
+        .then_with(|| match (a.deadline, b.deadline) {
+            (Some(a), Some(b)) => a.cmp(&b),
+            (Some(_), None) => std::cmp::Ordering::Less,
+            (None, Some(_)) => std::cmp::Ordering::Greater,
+            (None, None) => std::cmp::Ordering::Equal,
+        })
+        .then_with(|| a.seq.cmp(&b.seq))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::quota::{QuotaGovernor, QuotaLimit};
+    use crate::test_support::{batch_ok, batch_response, client_builder};
+    use crate::validate::UrlValidator;
+    use httpmock::prelude::*;
+
+    fn client_for(server: &MockServer, per_day: u32) -> IndexingClient {
+        client_builder(server)
+            .quota_governor(QuotaGovernor::new().publish(QuotaLimit::unlimited().per_day(per_day)))
+            .build()
+            .unwrap()
+    }
+
+    fn urls(queue: &[QueuedNotification]) -> Vec<&str> {
+        queue.iter().map(|q| q.notification.url.as_str()).collect()
+    }
+
+    #[test]
+    fn test_pending_order() {
+        let now = SystemTime::now();
+        let mut queue = NotificationQueue::new();
+        queue.push(
+            UrlNotification::updated("https://example.com/low"),
+            Priority::Low,
+        );
+        queue.push(
+            UrlNotification::updated("https://example.com/normal"),
+            Priority::Normal,
+        );
+        queue.push_with_deadline(
+            UrlNotification::updated("https://example.com/normal-later"),
+            Priority::Normal,
+            now + Duration::from_secs(7200),
+        );
+        queue.push_with_deadline(
+            UrlNotification::updated("https://example.com/normal-soon"),
+            Priority::Normal,
+            now + Duration::from_secs(3600),
+        );
+        queue.push(
+            UrlNotification::deleted("https://example.com/high"),
+            Priority::High,
+        );
+
+        let pending = queue
+            .pending()
+            .into_iter()
+            .map(|q| q.notification.url.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            pending,
+            vec![
+                "https://example.com/high",
+                "https://example.com/normal-soon",
+                "https://example.com/normal-later",
+                "https://example.com/normal",
+                "https://example.com/low",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_send_spills_over_what_the_budget_does_not_cover() {
+        let server = MockServer::start_async().await;
+        let mock = server
+            .mock_async(|when, then| {
+                when.method(POST)
+                    .path("/batch")
+                    .body_contains("https://example.com/high")
+                    .body_contains("https://example.com/normal");
+                then.status(200)
+                    .header("Content-Type", "multipart/mixed; boundary=batch_x")
+                    .body(batch_ok(2));
+            })
+            .await;
+        let client = client_for(&server, 2);
+
+        let now = SystemTime::now();
+        let mut queue = NotificationQueue::new();
+        queue.push(
+            UrlNotification::updated("https://example.com/low"),
+            Priority::Low,
+        );
+        queue.push(
+            UrlNotification::updated("https://example.com/normal"),
+            Priority::Normal,
+        );
+        queue.push(
+            UrlNotification::deleted("https://example.com/high"),
+            Priority::High,
+        );
+        queue.push_with_deadline(
+            UrlNotification::updated("https://example.com/stale"),
+            Priority::High,
+            now - Duration::from_secs(1),
+        );
+
+        let report = queue.send(&client).await.unwrap();
+        mock.assert_async().await;
+        assert_eq!(
+            report.sent.iter().map(|q| q.url()).collect::<Vec<_>>(),
+            vec!["https://example.com/high", "https://example.com/normal"]
+        );
+        assert_eq!(urls(&report.deferred), vec!["https://example.com/low"]);
+        assert_eq!(urls(&report.expired), vec!["https://example.com/stale"]);
+        assert!(report.retry_in.is_some());
+        // 送れなかったものは次回に持ち越す
+        assert_eq!(queue.len(), 1);
+
+        // 予算が尽きているので何も送らない
+        let report = queue.send(&client).await.unwrap();
+        assert!(report.sent.is_empty());
+        assert_eq!(urls(&report.deferred), vec!["https://example.com/low"]);
+        mock.assert_hits_async(1).await;
+    }
+
+    #[tokio::test]
+    async fn test_send_defers_parts_rejected_for_quota() {
+        let server = MockServer::start_async().await;
+        server
+            .mock_async(|when, then| {
+                when.method(POST).path("/batch");
+                then.status(200)
+                    .header("Content-Type", "multipart/mixed; boundary=batch_x")
+                    .body(batch_response(&[
+                        ("200 OK", "{}"),
+                        ("429 Too Many Requests", "{}"),
+                    ]));
+            })
+            .await;
+        let client = client_for(&server, 200);
+
+        let mut queue = NotificationQueue::new();
+        queue.push(
+            UrlNotification::updated("https://example.com/1"),
+            Priority::High,
+        );
+        queue.push(
+            UrlNotification::updated("https://example.com/2"),
+            Priority::Normal,
+        );
+
+        let report = queue.send(&client).await.unwrap();
+        assert_eq!(report.sent.len(), 2);
+        assert_eq!(urls(&report.deferred), vec!["https://example.com/2"]);
+        assert_eq!(queue.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_send_merges_urls_that_normalize_to_the_same() {
+        let server = MockServer::start_async().await;
+        let mock = server
+            .mock_async(|when, then| {
+                when.method(POST)
+                    .path("/batch")
+                    .body_contains(r#"{"type":"URL_DELETED","url":"https://example.com/1"}"#)
+                    .body_contains(r#"{"type":"URL_UPDATED","url":"https://example.com/2"}"#);
+                then.status(200)
+                    .header("Content-Type", "multipart/mixed; boundary=batch_x")
+                    .body(batch_response(&[
+                        ("200 OK", "{}"),
+                        ("429 Too Many Requests", "{}"),
+                    ]));
+            })
+            .await;
+        let client = client_builder(&server)
+            .url_validator(UrlValidator::new())
+            .build()
+            .unwrap();
+
+        let mut queue = NotificationQueue::new();
+        queue.push(
+            UrlNotification::updated("HTTPS://Example.com/1#top"),
+            Priority::High,
+        );
+        queue.push(
+            UrlNotification::updated("https://example.com/2"),
+            Priority::Normal,
+        );
+        queue.push(
+            UrlNotification::updated("https://example.com/1"),
+            Priority::Low,
+        );
+        queue.push(
+            UrlNotification::deleted("https://EXAMPLE.com/1"),
+            Priority::Low,
+        );
+
+        let report = queue.send(&client).await.unwrap();
+        mock.assert_async().await;
+        assert_eq!(
+            report.sent.iter().map(|r| r.url()).collect::<Vec<_>>(),
+            vec!["https://example.com/1", "https://example.com/2"]
+        );
+        // 429 になった 2 件目だけが残る
+        assert_eq!(urls(&report.deferred), vec!["https://example.com/2"]);
+        assert_eq!(queue.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_send_keeps_queue_on_error() {
+        let server = MockServer::start_async().await;
+        server
+            .mock_async(|when, then| {
+                when.method(POST)
+                    .path("/batch")
+                    .body_contains("\"https://example.com/0\"");
+                then.status(200)
+                    .header("Content-Type", "multipart/mixed; boundary=batch_x")
+                    .body(batch_ok(100));
+            })
+            .await;
+        server
+            .mock_async(|when, then| {
+                when.method(POST)
+                    .path("/batch")
+                    .body_contains("\"https://example.com/100\"");
+                then.status(500).body("internal");
+            })
+            .await;
+        let client = client_for(&server, 200);
+
+        let mut queue = NotificationQueue::new();
+        for i in 0..101 {
+            queue.push(
+                UrlNotification::updated(format!("https://example.com/{}", i).as_str()),
+                Priority::Normal,
+            );
+        }
+        // 先に送れたバッチの結果はエラーと一緒に返る
+        let report = queue.send_up_to(&client, 200).await.unwrap();
+        assert_eq!(report.sent.len(), 100);
+        assert!(matches!(
+            report.error,
+            Some(GoogleApiError::HttpStatus { status: 500, .. })
+        ));
+        assert_eq!(urls(&report.deferred), vec!["https://example.com/100"]);
+        assert_eq!(queue.len(), 1);
+    }
+}
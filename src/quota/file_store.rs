@@ -0,0 +1,255 @@
+use crate::error::GoogleApiError;
+use crate::quota::{QuotaBucket, QuotaStore};
+use fs4::fs_std::FileExt;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+/// Keeps the daily counts in a JSON file, so several processes share one budget.
+///
+/// Every call takes an exclusive lock on `<path>.lock`, reads the file, and replaces it
+/// atomically (write to `<path>.tmp`, then rename). `QuotaGovernor::acquire` does this
+/// on tokio's blocking thread pool. The file only holds the latest day seen:
+/// ```json
+/// {"day":"2026-10-18","used":{"publish":120,"metadata":4}}
+/// ```
+/// ```rust,no_run
+/// use google_indexing_api::{FileQuotaStore, IndexingClient, QuotaGovernor};
+/// let client = IndexingClient::builder()
+///     .quota_governor(QuotaGovernor::new().store(FileQuotaStore::new("/var/lib/indexing/quota.json")))
+///     .build()
+///     .unwrap();
+/// ```
+#[derive(Clone, Debug)]
+pub struct FileQuotaStore {
+    path: PathBuf,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct Ledger {
+    day: String,
+    #[serde(default)]
+    used: HashMap<QuotaBucket, u32>,
+}
+
+impl FileQuotaStore {
+    /// The file and its parent directory are created on first use.
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        FileQuotaStore {
+            path: path.as_ref().to_path_buf(),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        self.path.as_path()
+    }
+
+    // ロックを取ったまま f を実行する。ロックはファイルを閉じると解放される
+    fn with_lock<T, F>(&self, f: F) -> Result<T, GoogleApiError>
+    where
+        F: FnOnce(&mut Ledger) -> (T, bool),
+    {
+        if let Some(parent) = self.path.parent() {
+            if !parent.as_os_str().is_empty() {
+                std::fs::create_dir_all(parent).map_err(GoogleApiError::QuotaStore)?;
+            }
+        }
+        let lock = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(sibling(&self.path, "lock"))
+            .map_err(GoogleApiError::QuotaStore)?;
+        lock.lock_exclusive().map_err(GoogleApiError::QuotaStore)?;
+
+        let mut ledger = self.read()?;
+        let (value, changed) = f(&mut ledger);
+        if changed {
+            self.write(&ledger)?;
+        }
+        drop(lock);
+        Ok(value)
+    }
+
+    fn read(&self) -> Result<Ledger, GoogleApiError> {
+        match File::open(&self.path) {
+            Ok(file) => serde_json::from_reader(file).map_err(|e| {
+                GoogleApiError::QuotaStore(std::io::Error::new(ErrorKind::InvalidData, e))
+            }),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(Ledger::default()),
+            Err(e) => Err(GoogleApiError::QuotaStore(e)),
+        }
+    }
+
+    fn write(&self, ledger: &Ledger) -> Result<(), GoogleApiError> {
+        let tmp = sibling(&self.path, "tmp");
+        let json = serde_json::to_vec(ledger).map_err(|e| {
+            GoogleApiError::QuotaStore(std::io::Error::new(ErrorKind::InvalidData, e))
+        })?;
+        std::fs::write(&tmp, json).map_err(GoogleApiError::QuotaStore)?;
+        std::fs::rename(&tmp, &self.path).map_err(GoogleApiError::QuotaStore)
+    }
+}
+
+fn sibling(path: &Path, extension: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_os_string();
+    name.push(".");
+    name.push(extension);
+    PathBuf::from(name)
+}
+
+impl QuotaStore for FileQuotaStore {
+    fn take(
+        &self,
+        day: &str,
+        bucket: QuotaBucket,
+        cost: u32,
+        limit: u32,
+    ) -> Result<bool, GoogleApiError> {
+        self.with_lock(|ledger| {
+            // 日付は進めるだけ。時計の遅れたプロセスが前の日付で来ても、当日の数を消さずにそこへ数える
+            if day > ledger.day.as_str() {
+                *ledger = Ledger {
+                    day: day.to_string(),
+                    used: HashMap::new(),
+                };
+            }
+            let used = ledger.used.entry(bucket).or_insert(0);
+            if *used + cost > limit {
+                return (false, false);
+            }
+            *used += cost;
+            (true, true)
+        })
+    }
+
+    fn used(&self, day: &str, bucket: QuotaBucket) -> Result<u32, GoogleApiError> {
+        self.with_lock(|ledger| {
+            if day > ledger.day.as_str() {
+                return (0, false);
+            }
+            (ledger.used.get(&bucket).copied().unwrap_or(0), false)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::quota::{QuotaGovernor, QuotaLimit};
+    use crate::test_support::temp_path;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_file_store_persists_counts() {
+        let path = temp_path("quota.json");
+        let store = FileQuotaStore::new(&path);
+        assert!(store
+            .take("2026-10-18", QuotaBucket::Publish, 150, 200)
+            .unwrap());
+        assert!(!store
+            .take("2026-10-18", QuotaBucket::Publish, 51, 200)
+            .unwrap());
+
+        // 別のインスタンス (別プロセス相当) からも同じ値が見える
+        let other = FileQuotaStore::new(&path);
+        assert_eq!(other.used("2026-10-18", QuotaBucket::Publish).unwrap(), 150);
+        assert_eq!(other.used("2026-10-18", QuotaBucket::Metadata).unwrap(), 0);
+        let json = std::fs::read_to_string(&path).unwrap();
+        assert!(json.contains(r#""day":"2026-10-18""#), "{}", json);
+
+        // 日付が変わればリセットされる
+        assert_eq!(other.used("2026-10-19", QuotaBucket::Publish).unwrap(), 0);
+        assert!(other
+            .take("2026-10-19", QuotaBucket::Publish, 200, 200)
+            .unwrap());
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_file_store_never_goes_back_a_day() {
+        let path = temp_path("quota.json");
+        let store = FileQuotaStore::new(&path);
+        assert!(store
+            .take("2026-10-19", QuotaBucket::Publish, 150, 200)
+            .unwrap());
+
+        // 時計の遅れたプロセスが前の日付で来ても、当日の数はリセットされない
+        let lagging = FileQuotaStore::new(&path);
+        assert!(!lagging
+            .take("2026-10-18", QuotaBucket::Publish, 100, 200)
+            .unwrap());
+        assert!(lagging
+            .take("2026-10-18", QuotaBucket::Publish, 50, 200)
+            .unwrap());
+        assert_eq!(
+            lagging.used("2026-10-18", QuotaBucket::Publish).unwrap(),
+            200
+        );
+        assert_eq!(store.used("2026-10-19", QuotaBucket::Publish).unwrap(), 200);
+        let json = std::fs::read_to_string(&path).unwrap();
+        assert!(json.contains(r#""day":"2026-10-19""#), "{}", json);
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_file_store_is_atomic_across_instances() {
+        let path = Arc::new(temp_path("quota.json"));
+        let threads = (0..8)
+            .map(|_| {
+                let path = path.clone();
+                std::thread::spawn(move || {
+                    let store = FileQuotaStore::new(path.as_path());
+                    (0..25)
+                        .filter(|_| {
+                            store
+                                .take("2026-10-18", QuotaBucket::Publish, 1, 150)
+                                .unwrap()
+                        })
+                        .count()
+                })
+            })
+            .collect::<Vec<_>>();
+        let taken: usize = threads.into_iter().map(|t| t.join().unwrap()).sum();
+        assert_eq!(taken, 150);
+        assert_eq!(
+            FileQuotaStore::new(path.as_path())
+                .used("2026-10-18", QuotaBucket::Publish)
+                .unwrap(),
+            150
+        );
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_governor_acquires_from_file_store() {
+        let path = temp_path("quota.json");
+        let governor = QuotaGovernor::new()
+            .publish(QuotaLimit::unlimited().per_day(3))
+            .store(FileQuotaStore::new(&path));
+        governor.acquire(QuotaBucket::Publish, 2).await.unwrap();
+        assert!(matches!(
+            governor.acquire(QuotaBucket::Publish, 2).await,
+            Err(GoogleApiError::QuotaBudgetExhausted { .. })
+        ));
+        assert_eq!(
+            governor.remaining(QuotaBucket::Publish).unwrap().today,
+            Some(1)
+        );
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_file_store_reports_broken_file() {
+        let path = temp_path("quota.json");
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, "not json").unwrap();
+        assert!(matches!(
+            FileQuotaStore::new(&path).used("2026-10-18", QuotaBucket::Publish),
+            Err(GoogleApiError::QuotaStore(_))
+        ));
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
@@ -0,0 +1,573 @@
+#[cfg(feature = "file-quota-store")]
+mod file_store;
+mod pacific;
+mod store;
+
+use crate::error::GoogleApiError;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fmt::{Debug, Display, Formatter};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+#[cfg(feature = "file-quota-store")]
+pub use file_store::*;
+pub use store::*;
+
+const MINUTE: Duration = Duration::from_secs(60);
+
+/// The quota a call counts toward.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum QuotaBucket {
+    /// `publish` and `batch` (every URL of a batch counts).
+    Publish,
+    /// `get_metadata` and `batch_get_metadata`.
+    Metadata,
+}
+
+impl Display for QuotaBucket {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            QuotaBucket::Publish => f.write_str("publish"),
+            QuotaBucket::Metadata => f.write_str("metadata"),
+        }
+    }
+}
+
+/// Daily and per-minute limits of a [`QuotaBucket`]. Unset limits are not enforced.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct QuotaLimit {
+    per_day: Option<u32>,
+    per_minute: Option<u32>,
+}
+
+impl QuotaLimit {
+    pub fn unlimited() -> Self {
+        QuotaLimit::default()
+    }
+    /// Requests per day. The day starts at midnight Pacific Time, like Google's quota.
+    pub fn per_day(mut self, per_day: u32) -> Self {
+        self.per_day = Some(per_day);
+        self
+    }
+    /// Requests in any 60 second window.
+    pub fn per_minute(mut self, per_minute: u32) -> Self {
+        self.per_minute = Some(per_minute);
+        self
+    }
+}
+
+/// Budget left in a [`QuotaBucket`], from [`QuotaGovernor::remaining`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct QuotaRemaining {
+    /// Requests left today, or `None` without a daily limit.
+    pub today: Option<u32>,
+    /// Requests that can be sent right now under the per-minute limit, or `None` without one.
+    pub this_minute: Option<u32>,
+    /// Time until the daily budget resets (midnight Pacific Time).
+    pub resets_in: Duration,
+}
+
+/// Client-side quota governor.
+///
+/// Counts requests before they are sent and stops them once a daily or per-minute
+/// limit is reached, instead of letting the API answer `429`. Publish and metadata
+/// requests have separate budgets. The defaults follow the Indexing API's default quota:
+/// 200 publish requests per day, 380 publish and 180 metadata requests per minute.
+///
+/// A request over budget fails with [`GoogleApiError::QuotaBudgetExhausted`], unless
+/// [`max_wait`](QuotaGovernor::max_wait) allows waiting for the budget to free up.
+/// The budget is taken when a request is about to be sent, and is not given back
+/// if the request fails. Every request counts, so each retry (see
+/// [`RetryPolicy`](crate::RetryPolicy)) takes budget again; a retry over budget is not
+/// sent and fails with `QuotaBudgetExhausted`.
+///
+/// Daily counts are kept by a [`QuotaStore`], in memory by default. With the
+/// `file-quota-store` feature, `FileQuotaStore` shares them between processes.
+/// Per-minute counts are always kept per process.
+/// ```rust
+/// use google_indexing_api::{IndexingClient, QuotaBucket, QuotaGovernor, QuotaLimit};
+/// use std::time::Duration;
+/// let client = IndexingClient::builder()
+///     .quota_governor(
+///         QuotaGovernor::new()
+///             .publish(QuotaLimit::unlimited().per_day(1000).per_minute(100))
+///             .max_wait(Duration::from_secs(60)),
+///     )
+///     .build()
+///     .unwrap();
+/// let remaining = client
+///     .quota_governor()
+///     .unwrap()
+///     .remaining(QuotaBucket::Publish)
+///     .unwrap();
+/// assert_eq!(remaining.today, Some(1000));
+/// ```
+pub struct QuotaGovernor {
+    publish: QuotaLimit,
+    metadata: QuotaLimit,
+    max_wait: Duration,
+    store: Arc<dyn QuotaStore>,
+    minutes: Mutex<[MinuteWindow; 2]>,
+}
+
+impl Debug for QuotaGovernor {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("QuotaGovernor")
+            .field("publish", &self.publish)
+            .field("metadata", &self.metadata)
+            .field("max_wait", &self.max_wait)
+            .finish_non_exhaustive()
+    }
+}
+
+// バケットごとの直近 1 分間の (時刻, 件数)
+#[derive(Debug, Default)]
+struct MinuteWindow {
+    recent: VecDeque<(Instant, u32)>,
+}
+
+impl MinuteWindow {
+    fn roll(&mut self, now: Instant) {
+        while let Some((at, _)) = self.recent.front() {
+            if now.duration_since(*at) < MINUTE {
+                break;
+            }
+            self.recent.pop_front();
+        }
+    }
+
+    fn used_this_minute(&self) -> u32 {
+        self.recent.iter().map(|(_, cost)| cost).sum()
+    }
+
+    // 直近 1 分の使用量から cost 件分の空きができるまでの時間
+    fn minute_wait(&self, now: Instant, limit: u32, cost: u32) -> Duration {
+        let mut excess = (self.used_this_minute() + cost).saturating_sub(limit);
+        for (at, used) in self.recent.iter() {
+            excess = excess.saturating_sub(*used);
+            if excess == 0 {
+                return (*at + MINUTE).saturating_duration_since(now);
+            }
+        }
+        MINUTE
+    }
+}
+
+impl QuotaGovernor {
+    /// 200 publish requests per day, 380 publish and 180 metadata requests per minute,
+    /// and no waiting.
+    pub fn new() -> Self {
+        QuotaGovernor {
+            publish: QuotaLimit::unlimited().per_day(200).per_minute(380),
+            metadata: QuotaLimit::unlimited().per_minute(180),
+            max_wait: Duration::ZERO,
+            store: Arc::new(MemoryQuotaStore::new()),
+            minutes: Mutex::new(Default::default()),
+        }
+    }
+
+    /// Limits of publish requests (`publish` and every URL of `batch`).
+    pub fn publish(mut self, limit: QuotaLimit) -> Self {
+        self.publish = limit;
+        self
+    }
+    /// Limits of metadata requests (`get_metadata` and every URL of `batch_get_metadata`).
+    pub fn metadata(mut self, limit: QuotaLimit) -> Self {
+        self.metadata = limit;
+        self
+    }
+    /// How long a request over budget may wait for the budget to free up (default: zero,
+    /// fail at once). Longer waits fail with [`GoogleApiError::QuotaBudgetExhausted`].
+    pub fn max_wait(mut self, max_wait: Duration) -> Self {
+        self.max_wait = max_wait;
+        self
+    }
+
+    /// Where the daily counts are kept (default: [`MemoryQuotaStore`]).
+    pub fn store<S>(mut self, store: S) -> Self
+    where
+        S: QuotaStore + 'static,
+    {
+        self.store = Arc::new(store);
+        self
+    }
+
+    /// Budget left in `bucket`.
+    pub fn remaining(&self, bucket: QuotaBucket) -> Result<QuotaRemaining, GoogleApiError> {
+        self.remaining_at(bucket, Instant::now(), SystemTime::now())
+    }
+
+    /// Takes `cost` requests from `bucket` if the budget allows it now.
+    pub fn try_acquire(&self, bucket: QuotaBucket, cost: u32) -> Result<(), GoogleApiError> {
+        match self.try_acquire_at(bucket, cost, Instant::now(), SystemTime::now())? {
+            None => Ok(()),
+            Some(wait) => Err(GoogleApiError::QuotaBudgetExhausted {
+                bucket,
+                retry_in: wait,
+            }),
+        }
+    }
+
+    /// Takes `cost` requests from `bucket`, waiting up to `max_wait` for the budget.
+    pub async fn acquire(&self, bucket: QuotaBucket, cost: u32) -> Result<(), GoogleApiError> {
+        let deadline = Instant::now() + self.max_wait;
+        loop {
+            let now = Instant::now();
+            let Some(wait) = self
+                .acquire_at(bucket, cost, now, SystemTime::now())
+                .await?
+            else {
+                return Ok(());
+            };
+            if now + wait > deadline {
+                return Err(GoogleApiError::QuotaBudgetExhausted {
+                    bucket,
+                    retry_in: wait,
+                });
+            }
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    fn limit(&self, bucket: QuotaBucket) -> QuotaLimit {
+        match bucket {
+            QuotaBucket::Publish => self.publish,
+            QuotaBucket::Metadata => self.metadata,
+        }
+    }
+
+    // 予算があれば使って None、無ければ空くまでの時間を返す
+    fn try_acquire_at(
+        &self,
+        bucket: QuotaBucket,
+        cost: u32,
+        now: Instant,
+        wall: SystemTime,
+    ) -> Result<Option<Duration>, GoogleApiError> {
+        self.check_cost(bucket, cost)?;
+        // 分単位の枠を先に確保してから日次の予算を取る。日次の予算は取り消せないため
+        if let Some(wait) = self.reserve_minute(bucket, cost, now) {
+            return Ok(Some(wait));
+        }
+        // store はロックを外してから呼ぶ。取れなければ確保した枠を戻す
+        let taken = match self.limit(bucket).per_day {
+            Some(per_day) => take_day(self.store.as_ref(), bucket, cost, per_day, wall),
+            None => Ok(None),
+        };
+        if !matches!(taken, Ok(None)) {
+            self.release_minute(bucket, cost, now);
+        }
+        taken
+    }
+
+    // try_acquire_at と同じだが、store はファイルなどを読むことがあるので
+    // ブロックしてよいスレッドで呼ぶ
+    async fn acquire_at(
+        &self,
+        bucket: QuotaBucket,
+        cost: u32,
+        now: Instant,
+        wall: SystemTime,
+    ) -> Result<Option<Duration>, GoogleApiError> {
+        self.check_cost(bucket, cost)?;
+        if let Some(wait) = self.reserve_minute(bucket, cost, now) {
+            return Ok(Some(wait));
+        }
+        let taken = match self.limit(bucket).per_day {
+            Some(per_day) => {
+                let store = self.store.clone();
+                tokio::task::spawn_blocking(move || {
+                    take_day(store.as_ref(), bucket, cost, per_day, wall)
+                })
+                .await
+                .unwrap_or_else(|e| std::panic::resume_unwind(e.into_panic()))
+            }
+            None => Ok(None),
+        };
+        if !matches!(taken, Ok(None)) {
+            self.release_minute(bucket, cost, now);
+        }
+        taken
+    }
+
+    fn check_cost(&self, bucket: QuotaBucket, cost: u32) -> Result<(), GoogleApiError> {
+        let limit = self.limit(bucket);
+        // 待っても通らない量は入力の誤りとして扱う
+        for (name, max) in [("day", limit.per_day), ("minute", limit.per_minute)] {
+            if let Some(max) = max {
+                if cost > max {
+                    return Err(GoogleApiError::InvalidArgument(format!(
+                        "{} requests exceed the {} quota of {} per {}",
+                        cost, bucket, max, name
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // 分単位の枠に空きがあれば cost 件分を確保して None、無ければ空くまでの時間を返す
+    fn reserve_minute(&self, bucket: QuotaBucket, cost: u32, now: Instant) -> Option<Duration> {
+        let mut minutes = self.minutes.lock().unwrap();
+        let window = &mut minutes[bucket as usize];
+        window.roll(now);
+        if let Some(per_minute) = self.limit(bucket).per_minute {
+            if window.used_this_minute() + cost > per_minute {
+                return Some(window.minute_wait(now, per_minute, cost));
+            }
+        }
+        window.recent.push_back((now, cost));
+        None
+    }
+
+    fn release_minute(&self, bucket: QuotaBucket, cost: u32, now: Instant) {
+        let mut minutes = self.minutes.lock().unwrap();
+        let recent = &mut minutes[bucket as usize].recent;
+        if let Some(index) = recent.iter().rposition(|entry| *entry == (now, cost)) {
+            recent.remove(index);
+        }
+    }
+
+    fn remaining_at(
+        &self,
+        bucket: QuotaBucket,
+        now: Instant,
+        wall: SystemTime,
+    ) -> Result<QuotaRemaining, GoogleApiError> {
+        let limit = self.limit(bucket);
+        let today = match limit.per_day {
+            Some(per_day) => {
+                let used = self
+                    .store
+                    .used(pacific::pacific_date(wall).as_str(), bucket)?;
+                Some(per_day.saturating_sub(used))
+            }
+            None => None,
+        };
+        let mut minutes = self.minutes.lock().unwrap();
+        let window = &mut minutes[bucket as usize];
+        window.roll(now);
+        Ok(QuotaRemaining {
+            today,
+            this_minute: limit
+                .per_minute
+                .map(|per_minute| per_minute.saturating_sub(window.used_this_minute())),
+            resets_in: resets_in(wall),
+        })
+    }
+}
+
+// 日次の予算を取れれば None、無ければ太平洋時間の 0 時までの時間を返す
+fn take_day(
+    store: &dyn QuotaStore,
+    bucket: QuotaBucket,
+    cost: u32,
+    per_day: u32,
+    wall: SystemTime,
+) -> Result<Option<Duration>, GoogleApiError> {
+    let day = pacific::pacific_date(wall);
+    if !store.take(day.as_str(), bucket, cost, per_day)? {
+        return Ok(Some(resets_in(wall)));
+    }
+    Ok(None)
+}
+
+fn resets_in(wall: SystemTime) -> Duration {
+    pacific::next_pacific_midnight(wall)
+        .duration_since(wall)
+        .unwrap_or_default()
+}
+
+impl Default for QuotaGovernor {
+    fn default() -> Self {
+        QuotaGovernor::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::UNIX_EPOCH;
+
+    // 2026-07-01 12:00 PDT
+    fn noon() -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(1_782_932_400)
+    }
+
+    #[test]
+    fn test_daily_budget_resets_at_pacific_midnight() {
+        let governor = QuotaGovernor::new().publish(QuotaLimit::unlimited().per_day(3));
+        let now = Instant::now();
+        assert_eq!(
+            governor
+                .try_acquire_at(QuotaBucket::Publish, 2, now, noon())
+                .unwrap(),
+            None
+        );
+        // 残り 1 件に 2 件は入らないので、太平洋時間の 0 時 (12 時間後) まで待つ
+        assert_eq!(
+            governor
+                .try_acquire_at(QuotaBucket::Publish, 2, now, noon())
+                .unwrap(),
+            Some(Duration::from_secs(12 * 3600))
+        );
+        assert_eq!(
+            governor
+                .remaining_at(QuotaBucket::Publish, now, noon())
+                .unwrap()
+                .today,
+            Some(1)
+        );
+
+        let tomorrow = noon() + Duration::from_secs(12 * 3600);
+        assert_eq!(
+            governor
+                .try_acquire_at(QuotaBucket::Publish, 2, now, tomorrow)
+                .unwrap(),
+            None
+        );
+        let remaining = governor
+            .remaining_at(QuotaBucket::Publish, now, tomorrow)
+            .unwrap();
+        assert_eq!(remaining.today, Some(1));
+        assert_eq!(remaining.resets_in, Duration::from_secs(24 * 3600));
+    }
+
+    #[test]
+    fn test_per_minute_limit_uses_sliding_window() {
+        let governor = QuotaGovernor::new().metadata(QuotaLimit::unlimited().per_minute(10));
+        let start = Instant::now();
+        governor
+            .try_acquire_at(QuotaBucket::Metadata, 6, start, noon())
+            .unwrap();
+        let later = start + Duration::from_secs(20);
+        governor
+            .try_acquire_at(QuotaBucket::Metadata, 4, later, noon())
+            .unwrap();
+        assert_eq!(
+            governor
+                .remaining_at(QuotaBucket::Metadata, later, noon())
+                .unwrap()
+                .this_minute,
+            Some(0)
+        );
+        // 最初の 6 件が 1 分経って抜けるまで 40 秒待つ
+        assert_eq!(
+            governor
+                .try_acquire_at(QuotaBucket::Metadata, 5, later, noon())
+                .unwrap(),
+            Some(Duration::from_secs(40))
+        );
+        assert_eq!(
+            governor
+                .try_acquire_at(QuotaBucket::Metadata, 5, start + MINUTE, noon())
+                .unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_daily_refusal_gives_back_minute_budget() {
+        let governor =
+            QuotaGovernor::new().publish(QuotaLimit::unlimited().per_day(3).per_minute(5));
+        let now = Instant::now();
+        governor
+            .try_acquire_at(QuotaBucket::Publish, 2, now, noon())
+            .unwrap();
+        // 日次の予算が足りないときは、確保した分単位の枠を戻す
+        assert!(governor
+            .try_acquire_at(QuotaBucket::Publish, 2, now, noon())
+            .unwrap()
+            .is_some());
+        assert_eq!(
+            governor
+                .remaining_at(QuotaBucket::Publish, now, noon())
+                .unwrap()
+                .this_minute,
+            Some(3)
+        );
+    }
+
+    #[test]
+    fn test_buckets_are_separate() {
+        let governor = QuotaGovernor::new()
+            .publish(QuotaLimit::unlimited().per_day(1))
+            .metadata(QuotaLimit::unlimited().per_day(1));
+        governor.try_acquire(QuotaBucket::Publish, 1).unwrap();
+        governor.try_acquire(QuotaBucket::Metadata, 1).unwrap();
+        match governor.try_acquire(QuotaBucket::Publish, 1) {
+            Err(e @ GoogleApiError::QuotaBudgetExhausted { .. }) => {
+                assert!(e.is_quota_exceeded())
+            }
+            other => panic!("QuotaBudgetExhausted であるべき: {:?}", other.err()),
+        }
+        assert_eq!(
+            governor.remaining(QuotaBucket::Publish).unwrap().today,
+            Some(0)
+        );
+        assert_eq!(
+            governor.remaining(QuotaBucket::Metadata).unwrap().today,
+            Some(0)
+        );
+    }
+
+    #[test]
+    fn test_governors_share_a_store() {
+        let store = Arc::new(MemoryQuotaStore::new());
+        let first = QuotaGovernor::new().store(store.clone());
+        let second = QuotaGovernor::new().store(store);
+        first.try_acquire(QuotaBucket::Publish, 150).unwrap();
+        assert_eq!(
+            second.remaining(QuotaBucket::Publish).unwrap().today,
+            Some(50)
+        );
+        assert!(second.try_acquire(QuotaBucket::Publish, 51).is_err());
+    }
+
+    #[test]
+    fn test_memory_store_never_goes_back_a_day() {
+        let store = MemoryQuotaStore::new();
+        assert!(store
+            .take("2026-10-19", QuotaBucket::Publish, 150, 200)
+            .unwrap());
+        assert!(!store
+            .take("2026-10-18", QuotaBucket::Publish, 51, 200)
+            .unwrap());
+        assert_eq!(store.used("2026-10-18", QuotaBucket::Publish).unwrap(), 150);
+        assert!(store
+            .take("2026-10-20", QuotaBucket::Publish, 200, 200)
+            .unwrap());
+    }
+
+    #[test]
+    fn test_cost_over_limit_is_invalid() {
+        let governor = QuotaGovernor::new();
+        assert!(matches!(
+            governor.try_acquire(QuotaBucket::Publish, 201),
+            Err(GoogleApiError::InvalidArgument(_))
+        ));
+        // 拒否された分は使われない
+        assert_eq!(
+            governor.remaining(QuotaBucket::Publish).unwrap().today,
+            Some(200)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_acquire_waits_up_to_max_wait() {
+        let governor = QuotaGovernor::new()
+            .metadata(QuotaLimit::unlimited().per_minute(1))
+            .max_wait(Duration::from_millis(10));
+        governor.acquire(QuotaBucket::Metadata, 1).await.unwrap();
+        // 次の枠は約 1 分後なので max_wait を超え、待たずに失敗する
+        match governor.acquire(QuotaBucket::Metadata, 1).await {
+            Err(GoogleApiError::QuotaBudgetExhausted { bucket, retry_in }) => {
+                assert_eq!(bucket, QuotaBucket::Metadata);
+                assert!(retry_in > Duration::from_secs(59));
+            }
+            other => panic!("QuotaBudgetExhausted であるべき: {:?}", other.err()),
+        }
+    }
+}
@@ -0,0 +1,119 @@
+use crate::timestamp::{civil_from_days, days_from_civil};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const DAY: i64 = 86_400;
+const HOUR: i64 = 3_600;
+
+// Indexing API の日次クォータは太平洋時間の 0 時にリセットされる。
+// タイムゾーンのデータベースに依存しないよう、米国の夏時間の規則をここで計算する
+
+// 太平洋時間での日付 (1970-01-01 からの日数)
+pub(crate) fn pacific_day(time: SystemTime) -> i64 {
+    let unix = unix_seconds(time);
+    (unix + utc_offset(unix)).div_euclid(DAY)
+}
+
+// 太平洋時間での日付 "YYYY-MM-DD"
+pub(crate) fn pacific_date(time: SystemTime) -> String {
+    let (year, month, day) = civil_from_days(pacific_day(time));
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+// 次の太平洋時間 0 時。0 時は夏時間の切り替え (2 時) と重ならないので、
+// 標準時で求めた時刻が夏時間中なら 1 時間戻すだけでよい
+pub(crate) fn next_pacific_midnight(time: SystemTime) -> SystemTime {
+    let mut unix = (pacific_day(time) + 1) * DAY + 8 * HOUR;
+    if is_dst(unix) {
+        unix -= HOUR;
+    }
+    UNIX_EPOCH + Duration::from_secs(unix.max(0) as u64)
+}
+
+fn unix_seconds(time: SystemTime) -> i64 {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(duration) => duration.as_secs() as i64,
+        Err(e) => -(e.duration().as_secs() as i64) - 1,
+    }
+}
+
+fn utc_offset(unix: i64) -> i64 {
+    if is_dst(unix) {
+        -7 * HOUR
+    } else {
+        -8 * HOUR
+    }
+}
+
+// 夏時間は 3 月第 2 日曜 2:00 PST (10:00 UTC) から 11 月第 1 日曜 2:00 PDT (09:00 UTC) まで
+fn is_dst(unix: i64) -> bool {
+    let (year, _, _) = civil_from_days(unix.div_euclid(DAY));
+    let start = nth_sunday(year, 3, 2) * DAY + 10 * HOUR;
+    let end = nth_sunday(year, 11, 1) * DAY + 9 * HOUR;
+    (start..end).contains(&unix)
+}
+
+// year 年 month 月の n 番目の日曜日
+fn nth_sunday(year: i64, month: i64, n: i64) -> i64 {
+    let first = days_from_civil(year, month, 1);
+    // 1970-01-01 は木曜日なので、日曜日を 0 とした曜日は (days + 4) % 7
+    let weekday = (first + 4).rem_euclid(7);
+    first + (7 - weekday) % 7 + 7 * (n - 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utc(year: i64, month: i64, day: i64, hour: i64, minute: i64) -> SystemTime {
+        let unix = days_from_civil(year, month, day) * DAY + hour * HOUR + minute * 60;
+        UNIX_EPOCH + Duration::from_secs(unix as u64)
+    }
+
+    #[test]
+    fn test_dst_boundaries() {
+        // 2026 年の夏時間は 3 月 8 日から 11 月 1 日まで
+        assert_eq!(civil_from_days(nth_sunday(2026, 3, 2)), (2026, 3, 8));
+        assert_eq!(civil_from_days(nth_sunday(2026, 11, 1)), (2026, 11, 1));
+        assert!(!is_dst(unix_seconds(utc(2026, 3, 8, 9, 59))));
+        assert!(is_dst(unix_seconds(utc(2026, 3, 8, 10, 0))));
+        assert!(is_dst(unix_seconds(utc(2026, 11, 1, 8, 59))));
+        assert!(!is_dst(unix_seconds(utc(2026, 11, 1, 9, 0))));
+    }
+
+    #[test]
+    fn test_pacific_day() {
+        let date = |time| civil_from_days(pacific_day(time));
+        // 07:00 UTC は夏時間の 0 時、08:00 UTC は標準時の 0 時
+        assert_eq!(date(utc(2026, 7, 2, 6, 59)), (2026, 7, 1));
+        assert_eq!(date(utc(2026, 7, 2, 7, 0)), (2026, 7, 2));
+        assert_eq!(date(utc(2026, 12, 2, 7, 59)), (2026, 12, 1));
+        assert_eq!(date(utc(2026, 12, 2, 8, 0)), (2026, 12, 2));
+        assert_eq!(pacific_date(utc(2026, 3, 1, 7, 0)), "2026-02-28");
+    }
+
+    #[test]
+    fn test_next_pacific_midnight() {
+        assert_eq!(
+            next_pacific_midnight(utc(2026, 7, 1, 12, 0)),
+            utc(2026, 7, 2, 7, 0)
+        );
+        assert_eq!(
+            next_pacific_midnight(utc(2026, 12, 1, 12, 0)),
+            utc(2026, 12, 2, 8, 0)
+        );
+        // 夏時間が始まる日の 0 時は標準時、翌日の 0 時は夏時間
+        assert_eq!(
+            next_pacific_midnight(utc(2026, 3, 8, 12, 0)),
+            utc(2026, 3, 9, 7, 0)
+        );
+        assert_eq!(
+            next_pacific_midnight(utc(2026, 11, 1, 12, 0)),
+            utc(2026, 11, 2, 8, 0)
+        );
+        // 0 時ちょうどなら翌日の 0 時
+        assert_eq!(
+            next_pacific_midnight(utc(2026, 7, 2, 7, 0)),
+            utc(2026, 7, 3, 7, 0)
+        );
+    }
+}
@@ -0,0 +1,99 @@
+use crate::error::GoogleApiError;
+use crate::quota::QuotaBucket;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// Daily counter behind a [`QuotaGovernor`](crate::QuotaGovernor).
+///
+/// Days are identified by their date in Pacific Time (`YYYY-MM-DD`). Implementations
+/// shared by several processes must make [`take`](QuotaStore::take) atomic, so that two
+/// processes never both use the last unit of the budget. A store only moves forward to
+/// a later day: a `take` for an earlier day, e.g. from a process whose clock lags, counts
+/// against the latest day instead of resetting it.
+///
+/// [`QuotaGovernor::acquire`](crate::QuotaGovernor::acquire) calls
+/// [`take`](QuotaStore::take) on tokio's blocking thread pool, so it may do file I/O.
+/// `try_acquire` and `remaining` call the store on the calling thread.
+pub trait QuotaStore: Send + Sync {
+    /// Adds `cost` to the count of `bucket` on `day` if the new count does not exceed
+    /// `limit`, and returns whether it did. The count is unchanged otherwise.
+    fn take(
+        &self,
+        day: &str,
+        bucket: QuotaBucket,
+        cost: u32,
+        limit: u32,
+    ) -> Result<bool, GoogleApiError>;
+
+    /// Count of `bucket` on `day`.
+    fn used(&self, day: &str, bucket: QuotaBucket) -> Result<u32, GoogleApiError>;
+}
+
+impl<S> QuotaStore for Arc<S>
+where
+    S: QuotaStore + ?Sized,
+{
+    fn take(
+        &self,
+        day: &str,
+        bucket: QuotaBucket,
+        cost: u32,
+        limit: u32,
+    ) -> Result<bool, GoogleApiError> {
+        (**self).take(day, bucket, cost, limit)
+    }
+    fn used(&self, day: &str, bucket: QuotaBucket) -> Result<u32, GoogleApiError> {
+        (**self).used(day, bucket)
+    }
+}
+
+/// Keeps the counts in memory. This is the default store; the counts are lost when the
+/// process exits.
+#[derive(Debug, Default)]
+pub struct MemoryQuotaStore {
+    // 当日分だけを持つ
+    counts: Mutex<HashMap<(String, QuotaBucket), u32>>,
+}
+
+impl MemoryQuotaStore {
+    pub fn new() -> Self {
+        MemoryQuotaStore::default()
+    }
+}
+
+impl QuotaStore for MemoryQuotaStore {
+    fn take(
+        &self,
+        day: &str,
+        bucket: QuotaBucket,
+        cost: u32,
+        limit: u32,
+    ) -> Result<bool, GoogleApiError> {
+        let mut counts = self.counts.lock().unwrap();
+        // 前の日付で来ても当日の数は消さない
+        let day = counts
+            .keys()
+            .map(|(d, _)| d.as_str())
+            .chain([day])
+            .max()
+            .unwrap_or(day)
+            .to_string();
+        counts.retain(|(d, _), _| *d == day);
+        let used = counts.entry((day, bucket)).or_insert(0);
+        if *used + cost > limit {
+            return Ok(false);
+        }
+        *used += cost;
+        Ok(true)
+    }
+
+    fn used(&self, day: &str, bucket: QuotaBucket) -> Result<u32, GoogleApiError> {
+        let counts = self.counts.lock().unwrap();
+        let latest = counts.keys().map(|(d, _)| d.as_str()).max();
+        let day = match latest {
+            Some(latest) if latest > day => latest,
+            _ => day,
+        };
+        Ok(counts.get(&(day.to_string(), bucket)).copied().unwrap_or(0))
+    }
+}
@@ -0,0 +1,298 @@
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+use serde_json::Value;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::time::{Duration, SystemTime};
+
+// リトライしてよい HTTP ステータス
+const RETRIABLE_STATUS_CODES: [u16; 6] = [408, 429, 500, 502, 503, 504];
+// リトライしてよい Google エラーの status (google.rpc.Code)
+const RETRIABLE_ERROR_STATUSES: [&str; 5] = [
+    "RESOURCE_EXHAUSTED",
+    "UNAVAILABLE",
+    "INTERNAL",
+    "DEADLINE_EXCEEDED",
+    "ABORTED",
+];
+const RETRY_INFO_TYPE: &str = "type.googleapis.com/google.rpc.RetryInfo";
+
+/// Retry policy with exponential backoff.
+///
+/// A request is retried when it fails with a connection or timeout error, with
+/// HTTP 408, 429, 500, 502, 503 or 504, or with a Google error whose `status` is
+/// `RESOURCE_EXHAUSTED`, `UNAVAILABLE`, `INTERNAL`, `DEADLINE_EXCEEDED` or `ABORTED`.
+///
+/// The delay before retry `n` is `base_delay * 2^(n-1)`, capped at `max_delay`.
+/// With jitter, a random delay between half and all of that value is used.
+/// A `Retry-After` header, or the `retryDelay` of a `google.rpc.RetryInfo` error detail,
+/// replaces the computed delay; if it asks for more than `max_delay`, the error is
+/// returned instead of waiting. When several batch parts are retried together, the
+/// longest of their delays is used.
+///
+/// [`IndexingClient`](crate::IndexingClient) does not retry unless a policy is set.
+/// ```rust
+/// use google_indexing_api::{IndexingClient, RetryPolicy};
+/// use std::time::Duration;
+/// let client = IndexingClient::builder()
+///     .retry_policy(
+///         RetryPolicy::new()
+///             .max_attempts(5)
+///             .base_delay(Duration::from_millis(500)),
+///     )
+///     .build()
+///     .unwrap();
+/// ```
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    base_delay: Duration,
+    max_delay: Duration,
+    jitter: bool,
+    respect_retry_after: bool,
+    retry_batch_parts: bool,
+}
+
+impl RetryPolicy {
+    /// 4 attempts, 1 second base delay, 32 seconds max delay, jitter and `Retry-After` enabled.
+    pub fn new() -> Self {
+        RetryPolicy {
+            max_attempts: 4,
+            base_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(32),
+            jitter: true,
+            respect_retry_after: true,
+            retry_batch_parts: false,
+        }
+    }
+    /// Never retry.
+    pub fn none() -> Self {
+        RetryPolicy::new().max_attempts(1)
+    }
+
+    /// Total number of attempts, including the first one. `0` is treated as `1`.
+    pub fn max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = max_attempts.max(1);
+        self
+    }
+    pub fn base_delay(mut self, base_delay: Duration) -> Self {
+        self.base_delay = base_delay;
+        self
+    }
+    pub fn max_delay(mut self, max_delay: Duration) -> Self {
+        self.max_delay = max_delay;
+        self
+    }
+    pub fn jitter(mut self, jitter: bool) -> Self {
+        self.jitter = jitter;
+        self
+    }
+    /// Whether to wait as long as the `Retry-After` response header says.
+    pub fn respect_retry_after(mut self, respect_retry_after: bool) -> Self {
+        self.respect_retry_after = respect_retry_after;
+        self
+    }
+
+    /// Whether `batch` re-sends only the parts that failed with a transient error
+    /// (default: `false`). The parts are re-sent as a new, smaller batch, using the
+    /// same attempt limit and delays, and each result reports its number of attempts.
+    /// Every re-sent URL counts toward the quota again.
+    pub fn retry_batch_parts(mut self, retry_batch_parts: bool) -> Self {
+        self.retry_batch_parts = retry_batch_parts;
+        self
+    }
+
+    pub(crate) fn retries_batch_parts(&self) -> bool {
+        self.retry_batch_parts
+    }
+
+    /// Whether a failed response with this status code and body should be retried.
+    pub fn is_retriable_response(status_code: u16, body: &str) -> bool {
+        let status = google_error_status(body).unwrap_or_default();
+        RetryPolicy::is_retriable_status(status_code, status.as_str())
+    }
+
+    // 解析済みの Google エラーの code と status で判定する
+    pub(crate) fn is_retriable_status(status_code: u16, error_status: &str) -> bool {
+        RETRIABLE_STATUS_CODES.contains(&status_code)
+            || RETRIABLE_ERROR_STATUSES.contains(&error_status)
+    }
+
+    // attempt 回目の試行が失敗した後に待つ時間。None ならリトライしない
+    pub(crate) fn delay(&self, attempt: u32, retry_after: Option<Duration>) -> Option<Duration> {
+        if attempt >= self.max_attempts {
+            return None;
+        }
+        if self.respect_retry_after {
+            if let Some(retry_after) = retry_after {
+                if retry_after > self.max_delay {
+                    return None;
+                }
+                return Some(retry_after);
+            }
+        }
+        let exponent = attempt.saturating_sub(1).min(31);
+        let delay = self
+            .base_delay
+            .saturating_mul(1 << exponent)
+            .min(self.max_delay);
+        if !self.jitter {
+            return Some(delay);
+        }
+        let half = delay / 2;
+        let random = RandomState::new().build_hasher().finish();
+        Some(half + half.mul_f64(random as f64 / u64::MAX as f64))
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy::new()
+    }
+}
+
+// サーバーが指定した待ち時間。Retry-After ヘッダーが無ければボディの RetryInfo を見る
+pub(crate) fn retry_hint(headers: &HeaderMap, body: &str) -> Option<Duration> {
+    retry_after(headers).or_else(|| retry_delay(body))
+}
+
+pub(crate) fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    parse_retry_after(headers.get(RETRY_AFTER)?.to_str().ok()?)
+}
+
+// Retry-After は秒数か HTTP-date のどちらか
+pub(crate) fn parse_retry_after(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = httpdate::parse_http_date(value).ok()?;
+    Some(
+        date.duration_since(SystemTime::now())
+            .unwrap_or(Duration::ZERO),
+    )
+}
+
+// {"error": {"details": [{"@type": "...RetryInfo", "retryDelay": "1.5s"}]}} の retryDelay
+pub(crate) fn retry_delay(body: &str) -> Option<Duration> {
+    let value: Value = serde_json::from_str(body).ok()?;
+    let delay = value["error"]["details"]
+        .as_array()?
+        .iter()
+        .find(|detail| detail["@type"] == RETRY_INFO_TYPE)?["retryDelay"]
+        .as_str()?;
+    let seconds = delay.trim().strip_suffix('s')?.parse::<f64>().ok()?;
+    Duration::try_from_secs_f64(seconds).ok()
+}
+
+// {"error": {"status": "..."}} の status を取り出す
+fn google_error_status(body: &str) -> Option<String> {
+    let value: Value = serde_json::from_str(body).ok()?;
+    Some(value["error"]["status"].as_str()?.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    #[test]
+    fn test_delay_grows_exponentially_and_is_capped() {
+        let policy = RetryPolicy::new()
+            .max_attempts(10)
+            .base_delay(Duration::from_secs(1))
+            .max_delay(Duration::from_secs(5))
+            .jitter(false);
+        assert_eq!(policy.delay(1, None), Some(Duration::from_secs(1)));
+        assert_eq!(policy.delay(2, None), Some(Duration::from_secs(2)));
+        assert_eq!(policy.delay(3, None), Some(Duration::from_secs(4)));
+        assert_eq!(policy.delay(4, None), Some(Duration::from_secs(5)));
+        assert_eq!(policy.delay(9, None), Some(Duration::from_secs(5)));
+        // 最後の試行の後はリトライしない
+        assert_eq!(policy.delay(10, None), None);
+    }
+
+    #[test]
+    fn test_delay_with_jitter_stays_in_range() {
+        let policy = RetryPolicy::new().base_delay(Duration::from_secs(4));
+        for _ in 0..100 {
+            let delay = policy.delay(1, None).unwrap();
+            assert!(delay >= Duration::from_secs(2) && delay <= Duration::from_secs(4));
+        }
+    }
+
+    #[test]
+    fn test_delay_respects_retry_after() {
+        let policy = RetryPolicy::new().max_delay(Duration::from_secs(10));
+        assert_eq!(
+            policy.delay(1, Some(Duration::from_secs(7))),
+            Some(Duration::from_secs(7))
+        );
+        // max_delay を超える待ち時間は待たずに諦める
+        assert_eq!(policy.delay(1, Some(Duration::from_secs(60))), None);
+
+        let policy = policy.respect_retry_after(false).jitter(false);
+        assert_eq!(
+            policy.delay(1, Some(Duration::from_secs(60))),
+            Some(Duration::from_secs(1))
+        );
+    }
+
+    #[test]
+    fn test_none_never_retries() {
+        assert_eq!(RetryPolicy::none().delay(1, None), None);
+    }
+
+    #[test]
+    fn test_is_retriable_response() {
+        assert!(RetryPolicy::is_retriable_response(429, ""));
+        assert!(RetryPolicy::is_retriable_response(503, "unavailable"));
+        assert!(!RetryPolicy::is_retriable_response(400, "bad request"));
+        assert!(!RetryPolicy::is_retriable_response(
+            403,
+            r#"{"error":{"code":403,"status":"PERMISSION_DENIED"}}"#
+        ));
+        assert!(RetryPolicy::is_retriable_response(
+            403,
+            r#"{"error":{"code":403,"status":"RESOURCE_EXHAUSTED"}}"#
+        ));
+    }
+
+    #[test]
+    fn test_retry_after() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(retry_after(&headers), None);
+
+        headers.insert(RETRY_AFTER, HeaderValue::from_static("120"));
+        assert_eq!(retry_after(&headers), Some(Duration::from_secs(120)));
+
+        // 過去の日時なら待たない
+        headers.insert(
+            RETRY_AFTER,
+            HeaderValue::from_static("Wed, 21 Oct 2015 07:28:00 GMT"),
+        );
+        assert_eq!(retry_after(&headers), Some(Duration::ZERO));
+
+        let future = httpdate::fmt_http_date(SystemTime::now() + Duration::from_secs(300));
+        headers.insert(RETRY_AFTER, HeaderValue::from_str(future.as_str()).unwrap());
+        let delay = retry_after(&headers).unwrap();
+        assert!(delay > Duration::from_secs(290) && delay <= Duration::from_secs(300));
+    }
+
+    #[test]
+    fn test_retry_hint_falls_back_to_retry_info() {
+        let body = r#"{"error":{"code":429,"status":"RESOURCE_EXHAUSTED","details":[{"@type":"type.googleapis.com/google.rpc.RetryInfo","retryDelay":"1.500s"}]}}"#;
+        assert_eq!(
+            retry_hint(&HeaderMap::new(), body),
+            Some(Duration::from_millis(1500))
+        );
+        // ヘッダーがあればそちらを使う
+        let mut headers = HeaderMap::new();
+        headers.insert(RETRY_AFTER, HeaderValue::from_static("3"));
+        assert_eq!(retry_hint(&headers, body), Some(Duration::from_secs(3)));
+        assert_eq!(retry_hint(&HeaderMap::new(), "unavailable"), None);
+        assert_eq!(
+            retry_hint(&HeaderMap::new(), r#"{"error":{"code":429}}"#),
+            None
+        );
+    }
+}
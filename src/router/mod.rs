@@ -0,0 +1,527 @@
+use crate::auth::TokenProvider;
+use crate::client::IndexingClient;
+use crate::error::GoogleApiError;
+use crate::property::SearchConsoleProperty;
+use crate::quota::QuotaGovernor;
+use crate::{
+    PublishUrlNotificationResponse, ResponseGoogleIndexingBatch, ResponseUrlNotificationMetadata,
+    UrlNotification, UrlNotificationsType,
+};
+use futures_util::stream::{self, StreamExt};
+use std::fmt::{Debug, Formatter};
+use std::sync::Arc;
+
+const DEFAULT_ACCOUNT_CONCURRENCY: usize = 2;
+
+/// Notifications of one account that could not be sent, e.g. one failed batch.
+/// See [`RoutedBatchReport`].
+#[derive(Debug)]
+pub struct RoutedFailure {
+    pub property: SearchConsoleProperty,
+    pub notifications: Vec<UrlNotification>,
+    pub error: GoogleApiError,
+}
+
+/// Result of [`AccountRouter::batch_all`].
+#[derive(Debug, Default)]
+pub struct RoutedBatchReport {
+    /// Results of all accounts, grouped by account. Each group is in input order.
+    pub results: Vec<ResponseGoogleIndexingBatch>,
+    /// Notifications no account owns. They were not sent.
+    pub unrouted: Vec<UrlNotification>,
+    /// Batches that failed as a whole, e.g. because no token could be had.
+    pub failed: Vec<RoutedFailure>,
+}
+
+/// Sends each URL with the account that owns it, for sites owned by different
+/// service accounts.
+///
+/// Every account is a [`TokenProvider`] registered for a Search Console property or a
+/// host. [`batch_all`](AccountRouter::batch_all) splits the URLs by the narrowest
+/// property covering them (see [`property_for`](AccountRouter::property_for)), sends a
+/// separate series of batches per account over one shared [`IndexingClient`], and
+/// merges the results.
+///
+/// Accounts share the client's [`QuotaGovernor`] unless registered with their own,
+/// see [`property_with_quota`](AccountRouter::property_with_quota).
+/// ```rust
+/// use google_indexing_api::{
+///     AccountRouter, IndexingClient, SearchConsoleProperty, StaticTokenProvider, UrlNotification,
+/// };
+/// async fn example_router(token_a: &str, token_b: &str) {
+///     let router = AccountRouter::new(IndexingClient::new())
+///         .host("jobs.example.com", StaticTokenProvider::new(token_a))
+///         .property(
+///             SearchConsoleProperty::parse("sc-domain:example.org").unwrap(),
+///             StaticTokenProvider::new(token_b),
+///         );
+///     let report = router
+///         .batch_all(vec![
+///             UrlNotification::updated("https://jobs.example.com/1"),
+///             UrlNotification::updated("https://www.example.org/jobs/2"),
+///         ])
+///         .await;
+///     println!("{} sent, {} unrouted", report.results.len(), report.unrouted.len());
+/// }
+/// ```
+#[derive(Clone)]
+pub struct AccountRouter {
+    client: IndexingClient,
+    // 登録順。property ごとに 1 つ
+    accounts: Vec<Account>,
+    concurrency: usize,
+}
+
+#[derive(Clone)]
+struct Account {
+    property: SearchConsoleProperty,
+    tokens: Arc<dyn TokenProvider>,
+    // 専用の QuotaGovernor があれば、それを使うように差し替えた client
+    client: IndexingClient,
+}
+
+impl Debug for AccountRouter {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AccountRouter")
+            .field("client", &self.client)
+            .field("properties", &self.properties().collect::<Vec<_>>())
+            .field("concurrency", &self.concurrency)
+            .finish()
+    }
+}
+
+impl AccountRouter {
+    /// Sends through `client`. Its own token provider, if any, is not used.
+    pub fn new(client: IndexingClient) -> Self {
+        AccountRouter {
+            client,
+            accounts: Vec::new(),
+            concurrency: DEFAULT_ACCOUNT_CONCURRENCY,
+        }
+    }
+
+    /// Registers the account that owns `property`. Registering a property again
+    /// replaces its account. Pass an `Arc` to share one provider between properties.
+    pub fn property<P>(self, property: SearchConsoleProperty, provider: P) -> Self
+    where
+        P: TokenProvider + 'static,
+    {
+        self.register(property, Arc::new(provider), None)
+    }
+
+    /// Like [`property`](AccountRouter::property), but the account draws on its own
+    /// quota `governor` instead of the client's, since each Google Cloud project has
+    /// its own daily quota.
+    pub fn property_with_quota<P, Q>(
+        self,
+        property: SearchConsoleProperty,
+        provider: P,
+        governor: Q,
+    ) -> Self
+    where
+        P: TokenProvider + 'static,
+        Q: Into<Arc<QuotaGovernor>>,
+    {
+        self.register(property, Arc::new(provider), Some(governor.into()))
+    }
+
+    /// Registers the account for `host` and its subdomains, like a
+    /// `sc-domain:` property.
+    pub fn host<P>(self, host: &str, provider: P) -> Self
+    where
+        P: TokenProvider + 'static,
+    {
+        self.property(host_property(host), provider)
+    }
+
+    /// Like [`host`](AccountRouter::host), with the account's own quota `governor`.
+    pub fn host_with_quota<P, Q>(self, host: &str, provider: P, governor: Q) -> Self
+    where
+        P: TokenProvider + 'static,
+        Q: Into<Arc<QuotaGovernor>>,
+    {
+        self.property_with_quota(host_property(host), provider, governor)
+    }
+
+    /// Maximum number of accounts sending at the same time (default: 2). Each account
+    /// also sends up to `IndexingClientBuilder::batch_concurrency` batches at a time.
+    /// `0` is treated as `1`.
+    pub fn concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    /// The registered properties, in the order they were registered.
+    pub fn properties(&self) -> impl Iterator<Item = &SearchConsoleProperty> {
+        self.accounts.iter().map(|account| &account.property)
+    }
+
+    /// The quota governor the account of `property` draws on: its own, or else the
+    /// client's.
+    pub fn quota_governor(&self, property: &SearchConsoleProperty) -> Option<&QuotaGovernor> {
+        self.accounts
+            .iter()
+            .find(|account| account.property == *property)
+            .and_then(|account| account.client.quota_governor())
+    }
+
+    /// The property whose account would send `url`: the narrowest registered property
+    /// covering it.
+    pub fn property_for(&self, url: &str) -> Option<&SearchConsoleProperty> {
+        self.account_for(url).map(|account| &account.property)
+    }
+
+    /// Notify Google about one URL with the account that owns it.
+    pub async fn publish(
+        &self,
+        url: &str,
+        url_type: UrlNotificationsType,
+    ) -> Result<PublishUrlNotificationResponse, GoogleApiError> {
+        let account = self.owner_of(url)?;
+        account
+            .client
+            .publish_with(account.tokens.as_ref(), url, url_type)
+            .await
+    }
+
+    /// Fetch notification metadata for one URL with the account that owns it.
+    pub async fn get_metadata(
+        &self,
+        url: &str,
+    ) -> Result<ResponseUrlNotificationMetadata, GoogleApiError> {
+        let account = self.owner_of(url)?;
+        account
+            .client
+            .get_metadata_with(account.tokens.as_ref(), url)
+            .await
+    }
+
+    /// Splits the notifications by account and sends each account's share with
+    /// [`IndexingClient::batch_all_notifications`].
+    ///
+    /// An account that fails does not stop the others; its notifications and error
+    /// are in [`RoutedBatchReport::failed`].
+    pub async fn batch_all(&self, notifications: Vec<UrlNotification>) -> RoutedBatchReport {
+        let mut report = RoutedBatchReport::default();
+        // 最初に必要になった順に、アカウントごとにまとめる
+        let mut routes: Vec<(&Account, Vec<UrlNotification>)> = Vec::new();
+        for notification in notifications {
+            let Some(account) = self.account_for(notification.url.as_str()) else {
+                report.unrouted.push(notification);
+                continue;
+            };
+            match routes
+                .iter_mut()
+                .find(|(a, _)| a.property == account.property)
+            {
+                Some((_, group)) => group.push(notification),
+                None => routes.push((account, vec![notification])),
+            }
+        }
+        // buffered は routes の順に結果を返す
+        let sent = stream::iter(routes)
+            .map(|(account, notifications)| async move {
+                let result = account
+                    .client
+                    .batch_all_notifications_with(account.tokens.as_ref(), notifications.clone())
+                    .await;
+                (account.property.clone(), notifications, result)
+            })
+            .buffered(self.concurrency)
+            .collect::<Vec<_>>()
+            .await;
+        for (property, notifications, result) in sent {
+            match result {
+                Ok(sent) => {
+                    report.results.extend(sent.results);
+                    report
+                        .failed
+                        .extend(sent.failed.into_iter().map(|failure| RoutedFailure {
+                            property: property.clone(),
+                            notifications: failure.notifications,
+                            error: failure.error,
+                        }));
+                }
+                Err(error) => report.failed.push(RoutedFailure {
+                    property,
+                    notifications,
+                    error,
+                }),
+            }
+        }
+        report
+    }
+
+    // url を含む property のうち最も狭いものと、そのアカウント
+    fn account_for(&self, url: &str) -> Option<&Account> {
+        self.accounts
+            .iter()
+            .filter(|account| account.property.covers(url))
+            .max_by_key(|account| account.property.specificity())
+    }
+
+    fn owner_of(&self, url: &str) -> Result<&Account, GoogleApiError> {
+        self.account_for(url).ok_or_else(|| {
+            GoogleApiError::InvalidArgument(format!(
+                "no account is registered for a property covering {}",
+                url
+            ))
+        })
+    }
+
+    fn register(
+        mut self,
+        property: SearchConsoleProperty,
+        tokens: Arc<dyn TokenProvider>,
+        quota: Option<Arc<QuotaGovernor>>,
+    ) -> Self {
+        let client = match quota {
+            Some(quota) => self.client.with_account(tokens.clone(), quota),
+            None => self.client.clone(),
+        };
+        let account = Account {
+            property,
+            tokens,
+            client,
+        };
+        match self
+            .accounts
+            .iter_mut()
+            .find(|a| a.property == account.property)
+        {
+            Some(registered) => *registered = account,
+            None => self.accounts.push(account),
+        }
+        self
+    }
+}
+
+fn host_property(host: &str) -> SearchConsoleProperty {
+    SearchConsoleProperty::Domain(host.trim().trim_end_matches('.').to_ascii_lowercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::StaticTokenProvider;
+    use crate::quota::{QuotaBucket, QuotaLimit};
+    use crate::test_support::batch_ok;
+    use httpmock::prelude::*;
+
+    fn router_for(server: &MockServer) -> AccountRouter {
+        router_for_base_url(server.base_url().as_str())
+    }
+
+    fn router_for_base_url(base_url: &str) -> AccountRouter {
+        let client = IndexingClient::builder()
+            .base_url(base_url)
+            .build()
+            .unwrap();
+        AccountRouter::new(client)
+            .host("example.com", StaticTokenProvider::new("token-a"))
+            .property(
+                SearchConsoleProperty::parse("https://jobs.example.org/").unwrap(),
+                StaticTokenProvider::new("token-b"),
+            )
+    }
+
+    #[test]
+    fn test_property_for_picks_the_narrowest_property() {
+        let router = router_for_base_url("http://127.0.0.1:1")
+            .property(
+                SearchConsoleProperty::parse("https://www.example.com/jobs/").unwrap(),
+                StaticTokenProvider::new("token-c"),
+            )
+            // 登録済みの property はアカウントだけを差し替える
+            .host("example.com", StaticTokenProvider::new("token-d"));
+        assert_eq!(router.properties().count(), 3);
+        assert_eq!(
+            router
+                .property_for("https://www.example.com/jobs/1")
+                .map(|p| p.to_string()),
+            Some("https://www.example.com/jobs/".to_string())
+        );
+        assert_eq!(
+            router
+                .property_for("https://www.example.com/blog/1")
+                .map(|p| p.to_string()),
+            Some("sc-domain:example.com".to_string())
+        );
+        assert_eq!(router.property_for("https://example.net/"), None);
+    }
+
+    #[tokio::test]
+    async fn test_batch_all_sends_each_account_its_urls() {
+        let server = MockServer::start_async().await;
+        let account_a = server
+            .mock_async(|when, then| {
+                when.method(POST)
+                    .path("/batch")
+                    .header("Authorization", "Bearer token-a")
+                    .body_contains("https://example.com/1")
+                    .body_contains("https://www.example.com/2");
+                then.status(200)
+                    .header("Content-Type", "multipart/mixed; boundary=batch_x")
+                    .body(batch_ok(2));
+            })
+            .await;
+        let account_b = server
+            .mock_async(|when, then| {
+                when.method(POST)
+                    .path("/batch")
+                    .header("Authorization", "Bearer token-b")
+                    .body_contains("https://jobs.example.org/3");
+                then.status(200)
+                    .header("Content-Type", "multipart/mixed; boundary=batch_x")
+                    .body(batch_ok(1));
+            })
+            .await;
+
+        let router = router_for(&server);
+        let report = router
+            .batch_all(vec![
+                UrlNotification::updated("https://example.com/1"),
+                UrlNotification::updated("https://jobs.example.org/3"),
+                UrlNotification::deleted("https://www.example.com/2"),
+                UrlNotification::updated("https://example.net/4"),
+            ])
+            .await;
+        account_a.assert_async().await;
+        account_b.assert_async().await;
+        assert_eq!(
+            report.results.iter().map(|r| r.url()).collect::<Vec<_>>(),
+            vec![
+                "https://example.com/1",
+                "https://www.example.com/2",
+                "https://jobs.example.org/3",
+            ]
+        );
+        assert_eq!(
+            report.unrouted,
+            vec![UrlNotification::updated("https://example.net/4")]
+        );
+        assert!(report.failed.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_failed_account_does_not_stop_the_others() {
+        let server = MockServer::start_async().await;
+        server
+            .mock_async(|when, then| {
+                when.method(POST)
+                    .path("/batch")
+                    .header("Authorization", "Bearer token-a");
+                then.status(200)
+                    .header("Content-Type", "multipart/mixed; boundary=batch_x")
+                    .body(batch_ok(1));
+            })
+            .await;
+        server
+            .mock_async(|when, then| {
+                when.method(POST)
+                    .path("/batch")
+                    .header("Authorization", "Bearer token-b");
+                then.status(403).body(
+                    r#"{"error":{"code":403,"message":"Permission denied. Failed to verify the URL ownership.","status":"PERMISSION_DENIED"}}"#,
+                );
+            })
+            .await;
+
+        let router = router_for(&server);
+        let report = router
+            .batch_all(vec![
+                UrlNotification::updated("https://jobs.example.org/1"),
+                UrlNotification::updated("https://example.com/2"),
+            ])
+            .await;
+        assert_eq!(report.results.len(), 1);
+        assert_eq!(report.failed.len(), 1);
+        let failure = &report.failed[0];
+        assert_eq!(failure.property.to_string(), "https://jobs.example.org/");
+        assert_eq!(
+            failure.notifications,
+            vec![UrlNotification::updated("https://jobs.example.org/1")]
+        );
+        assert!(failure.error.is_permission_denied());
+    }
+
+    #[tokio::test]
+    async fn test_publish_uses_owner_account() {
+        let server = MockServer::start_async().await;
+        let mock = server
+            .mock_async(|when, then| {
+                when.method(POST)
+                    .path("/v3/urlNotifications:publish")
+                    .header("Authorization", "Bearer token-b");
+                then.status(200)
+                    .body(r#"{"urlNotificationMetadata":{"url":"https://jobs.example.org/1"}}"#);
+            })
+            .await;
+        let router = router_for(&server);
+        router
+            .publish("https://jobs.example.org/1", UrlNotificationsType::UPDATED)
+            .await
+            .unwrap();
+        mock.assert_async().await;
+
+        let result = router
+            .publish("https://example.net/1", UrlNotificationsType::UPDATED)
+            .await;
+        assert!(matches!(result, Err(GoogleApiError::InvalidArgument(_))));
+    }
+
+    #[tokio::test]
+    async fn test_account_with_own_quota_leaves_shared_budget_alone() {
+        let server = MockServer::start_async().await;
+        let mock = server
+            .mock_async(|when, then| {
+                when.method(POST).path("/v3/urlNotifications:publish");
+                then.status(200)
+                    .body(r#"{"urlNotificationMetadata":{"url":"https://example.com/1"}}"#);
+            })
+            .await;
+        let daily =
+            |per_day| QuotaGovernor::new().publish(QuotaLimit::unlimited().per_day(per_day));
+        let client = IndexingClient::builder()
+            .base_url(server.base_url().as_str())
+            .quota_governor(daily(1))
+            .build()
+            .unwrap();
+        let router = AccountRouter::new(client)
+            .host_with_quota("example.com", StaticTokenProvider::new("token-a"), daily(2))
+            .host("example.org", StaticTokenProvider::new("token-b"));
+
+        // example.com は自分の予算だけを使う
+        for _ in 0..2 {
+            router
+                .publish("https://example.com/1", UrlNotificationsType::UPDATED)
+                .await
+                .unwrap();
+        }
+        let own = SearchConsoleProperty::parse("sc-domain:example.com").unwrap();
+        let shared = SearchConsoleProperty::parse("sc-domain:example.org").unwrap();
+        let remaining = |property| {
+            router
+                .quota_governor(property)
+                .unwrap()
+                .remaining(QuotaBucket::Publish)
+                .unwrap()
+                .today
+        };
+        assert_eq!(remaining(&own), Some(0));
+        assert_eq!(remaining(&shared), Some(1));
+
+        router
+            .publish("https://example.org/1", UrlNotificationsType::UPDATED)
+            .await
+            .unwrap();
+        let result = router
+            .publish("https://example.org/2", UrlNotificationsType::UPDATED)
+            .await;
+        assert!(matches!(
+            result,
+            Err(GoogleApiError::QuotaBudgetExhausted { .. })
+        ));
+        mock.assert_hits_async(3).await;
+    }
+}
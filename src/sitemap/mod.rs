@@ -0,0 +1,520 @@
+use crate::error::GoogleApiError;
+use crate::http::MAX_BATCH_URLS;
+use crate::timestamp::Timestamp;
+use crate::{UrlNotification, UrlNotificationsType};
+use flate2::read::GzDecoder;
+use quick_xml::events::Event;
+use quick_xml::Reader;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::Read;
+
+// Google が受け付けるサイトマップの上限 (展開後 50MB)
+const MAX_SITEMAP_BYTES: u64 = 50 * 1024 * 1024;
+
+/// A `<url>` of a `urlset`, or a `<sitemap>` of a `sitemapindex`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SitemapEntry {
+    pub loc: String,
+    /// `None` if the entry has no `lastmod`, or it is not a W3C datetime.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lastmod: Option<Timestamp>,
+}
+
+/// A parsed sitemap file.
+/// ```rust
+/// use google_indexing_api::Sitemap;
+/// let sitemap = Sitemap::parse(
+///     br#"<urlset xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">
+///           <url><loc>https://example.com/jobs/1</loc><lastmod>2026-07-01</lastmod></url>
+///         </urlset>"#,
+/// )
+/// .unwrap();
+/// assert_eq!(sitemap.entries()[0].loc, "https://example.com/jobs/1");
+/// assert_eq!(
+///     sitemap.entries()[0].lastmod.unwrap().to_string(),
+///     "2026-07-01T00:00:00Z"
+/// );
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Sitemap {
+    /// A `urlset`: the pages of the site.
+    UrlSet(Vec<SitemapEntry>),
+    /// A `sitemapindex`: the locations of other sitemaps, to fetch and parse in turn.
+    Index(Vec<SitemapEntry>),
+}
+
+impl Sitemap {
+    /// Parses a sitemap, gzip-compressed or not.
+    ///
+    /// Only `loc` and `lastmod` of each entry are read. Elements of other namespaces,
+    /// such as image and news extensions, are ignored. Like Google, sitemaps over
+    /// 50 MB uncompressed are rejected.
+    pub fn parse(data: &[u8]) -> Result<Sitemap, GoogleApiError> {
+        if data.starts_with(&[0x1f, 0x8b]) {
+            let mut xml = Vec::new();
+            GzDecoder::new(data)
+                .take(MAX_SITEMAP_BYTES + 1)
+                .read_to_end(&mut xml)
+                .map_err(|e| {
+                    GoogleApiError::MalformedSitemap(format!("invalid gzip data: {}", e))
+                })?;
+            check_size(xml.as_slice())?;
+            return parse_xml(xml.as_slice());
+        }
+        check_size(data)?;
+        parse_xml(data)
+    }
+
+    pub fn entries(&self) -> &[SitemapEntry] {
+        match self {
+            Sitemap::UrlSet(entries) | Sitemap::Index(entries) => entries.as_slice(),
+        }
+    }
+
+    pub fn into_entries(self) -> Vec<SitemapEntry> {
+        match self {
+            Sitemap::UrlSet(entries) | Sitemap::Index(entries) => entries,
+        }
+    }
+
+    pub fn is_index(&self) -> bool {
+        matches!(self, Sitemap::Index(_))
+    }
+}
+
+fn check_size(xml: &[u8]) -> Result<(), GoogleApiError> {
+    if xml.len() as u64 > MAX_SITEMAP_BYTES {
+        return Err(GoogleApiError::MalformedSitemap(format!(
+            "uncompressed size exceeds {} bytes",
+            MAX_SITEMAP_BYTES
+        )));
+    }
+    Ok(())
+}
+
+fn parse_xml(xml: &[u8]) -> Result<Sitemap, GoogleApiError> {
+    let malformed = |reader: &Reader<&[u8]>, e: &dyn std::fmt::Display| {
+        GoogleApiError::MalformedSitemap(format!("at byte {}: {}", reader.error_position(), e))
+    };
+    let mut reader = Reader::from_reader(xml);
+    reader.config_mut().trim_text(true);
+    let mut buf = Vec::new();
+    let mut index = None;
+    let mut entries = Vec::new();
+    // 開いている要素の名前 (名前空間の接頭辞付き)
+    let mut path: Vec<Vec<u8>> = Vec::new();
+    let mut entry: Option<SitemapEntry> = None;
+    let mut text = String::new();
+    loop {
+        let event = reader
+            .read_event_into(&mut buf)
+            .map_err(|e| malformed(&reader, &e))?;
+        match event {
+            Event::Start(start) => {
+                let name = start.name().as_ref().to_vec();
+                match (path.len(), name.as_slice()) {
+                    (0, b"urlset") => index = Some(false),
+                    (0, b"sitemapindex") => index = Some(true),
+                    (0, other) => {
+                        return Err(GoogleApiError::MalformedSitemap(format!(
+                            "root element must be urlset or sitemapindex, got {}",
+                            String::from_utf8_lossy(other)
+                        )))
+                    }
+                    (1, b"url") if index == Some(false) => entry = Some(new_entry()),
+                    (1, b"sitemap") if index == Some(true) => entry = Some(new_entry()),
+                    _ => {}
+                }
+                path.push(name);
+                text.clear();
+            }
+            Event::Empty(empty) if path.is_empty() => {
+                return match empty.name().as_ref() {
+                    b"urlset" => Ok(Sitemap::UrlSet(Vec::new())),
+                    b"sitemapindex" => Ok(Sitemap::Index(Vec::new())),
+                    other => Err(GoogleApiError::MalformedSitemap(format!(
+                        "root element must be urlset or sitemapindex, got {}",
+                        String::from_utf8_lossy(other)
+                    ))),
+                };
+            }
+            Event::Text(t) => {
+                let unescaped = t.unescape().map_err(|e| malformed(&reader, &e))?;
+                text.push_str(unescaped.as_ref());
+            }
+            Event::CData(c) => {
+                text.push_str(String::from_utf8_lossy(c.as_ref()).trim());
+            }
+            Event::End(_) => {
+                let name = path.pop().unwrap_or_default();
+                // url / sitemap の直下の loc と lastmod だけを読む
+                if path.len() == 2 {
+                    if let Some(entry) = entry.as_mut() {
+                        match name.as_slice() {
+                            b"loc" => entry.loc = text.trim().to_string(),
+                            b"lastmod" => entry.lastmod = Timestamp::parse_w3c(text.as_str()),
+                            _ => {}
+                        }
+                    }
+                }
+                if path.len() == 1 {
+                    if let Some(entry) = entry.take() {
+                        if !entry.loc.is_empty() {
+                            entries.push(entry);
+                        }
+                    }
+                }
+                text.clear();
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+        buf.clear();
+    }
+    match index {
+        Some(false) => Ok(Sitemap::UrlSet(entries)),
+        Some(true) => Ok(Sitemap::Index(entries)),
+        None => Err(GoogleApiError::MalformedSitemap(
+            "no urlset or sitemapindex element".to_string(),
+        )),
+    }
+}
+
+fn new_entry() -> SitemapEntry {
+    SitemapEntry {
+        loc: String::new(),
+        lastmod: None,
+    }
+}
+
+/// The notifications to send for sitemap entries. See [`NotificationPlan::diff`].
+///
+/// `updated` and `deleted` are in sitemap order and hold every URL once.
+/// [`batches`](NotificationPlan::batches) splits them for
+/// [`UrlNotificationsApi::batch`](crate::UrlNotificationsApi::batch).
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct NotificationPlan {
+    pub updated: Vec<String>,
+    pub deleted: Vec<String>,
+}
+
+impl NotificationPlan {
+    /// `UPDATED` for every entry.
+    pub fn from_entries(entries: &[SitemapEntry]) -> Self {
+        NotificationPlan {
+            updated: latest(entries).into_iter().map(|(loc, _)| loc).collect(),
+            deleted: Vec::new(),
+        }
+    }
+
+    /// `UPDATED` for the entries whose `lastmod` is later than `since`. Entries without
+    /// `lastmod` are included, since they may have changed.
+    pub fn modified_since(entries: &[SitemapEntry], since: Timestamp) -> Self {
+        NotificationPlan {
+            updated: latest(entries)
+                .into_iter()
+                .filter(|(_, lastmod)| lastmod.is_none_or(|lastmod| lastmod > since))
+                .map(|(loc, _)| loc)
+                .collect(),
+            deleted: Vec::new(),
+        }
+    }
+
+    /// Compares two snapshots of a sitemap: `UPDATED` for URLs that are new in `current`
+    /// or whose `lastmod` changed, `DELETED` for URLs that are no longer listed.
+    ///
+    /// A URL whose `lastmod` is missing in `current` is treated as unchanged.
+    /// ```rust
+    /// use google_indexing_api::{NotificationPlan, Sitemap};
+    /// let previous = Sitemap::parse(
+    ///     br#"<urlset>
+    ///           <url><loc>https://example.com/jobs/1</loc><lastmod>2026-07-01</lastmod></url>
+    ///           <url><loc>https://example.com/jobs/2</loc><lastmod>2026-07-01</lastmod></url>
+    ///         </urlset>"#,
+    /// )
+    /// .unwrap();
+    /// let current = Sitemap::parse(
+    ///     br#"<urlset>
+    ///           <url><loc>https://example.com/jobs/1</loc><lastmod>2026-07-02</lastmod></url>
+    ///           <url><loc>https://example.com/jobs/3</loc></url>
+    ///         </urlset>"#,
+    /// )
+    /// .unwrap();
+    /// let plan = NotificationPlan::diff(previous.entries(), current.entries());
+    /// assert_eq!(
+    ///     plan.updated,
+    ///     vec!["https://example.com/jobs/1", "https://example.com/jobs/3"]
+    /// );
+    /// assert_eq!(plan.deleted, vec!["https://example.com/jobs/2"]);
+    /// ```
+    pub fn diff(previous: &[SitemapEntry], current: &[SitemapEntry]) -> Self {
+        let before = latest(previous);
+        let after = latest(current);
+        let before_by_loc = before
+            .iter()
+            .map(|(loc, lastmod)| (loc.as_str(), *lastmod))
+            .collect::<HashMap<_, _>>();
+        let after_locs = after
+            .iter()
+            .map(|(loc, _)| loc.as_str())
+            .collect::<std::collections::HashSet<_>>();
+        let updated = after
+            .iter()
+            .filter(|(loc, lastmod)| match before_by_loc.get(loc.as_str()) {
+                None => true,
+                Some(previous) => lastmod.is_some() && lastmod != previous,
+            })
+            .map(|(loc, _)| loc.clone())
+            .collect();
+        let deleted = before
+            .iter()
+            .filter(|(loc, _)| !after_locs.contains(loc.as_str()))
+            .map(|(loc, _)| loc.clone())
+            .collect();
+        NotificationPlan { updated, deleted }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.updated.is_empty() && self.deleted.is_empty()
+    }
+
+    /// Number of notifications in the plan.
+    pub fn len(&self) -> usize {
+        self.updated.len() + self.deleted.len()
+    }
+
+    /// The plan as notifications, `UPDATED` before `DELETED`, e.g. for
+    /// [`IndexingClient::batch_all_notifications`](crate::IndexingClient::batch_all_notifications).
+    pub fn notifications(&self) -> Vec<UrlNotification> {
+        self.updated
+            .iter()
+            .map(|url| UrlNotification::updated(url))
+            .chain(self.deleted.iter().map(|url| UrlNotification::deleted(url)))
+            .collect()
+    }
+
+    /// The plan split into calls of at most 100 URLs of one type, for
+    /// [`UrlNotificationsApi::batch`](crate::UrlNotificationsApi::batch).
+    /// ```rust
+    /// use google_indexing_api::{GoogleIndexingApi, NotificationPlan};
+    /// async fn example_plan(token: &str, plan: &NotificationPlan) {
+    ///     for (url_type, urls) in plan.batches() {
+    ///         GoogleIndexingApi::url_notifications()
+    ///             .batch(token, urls, url_type)
+    ///             .await;
+    ///     }
+    /// }
+    /// ```
+    pub fn batches(&self) -> Vec<(UrlNotificationsType, Vec<String>)> {
+        let updated = self
+            .updated
+            .chunks(MAX_BATCH_URLS)
+            .map(|urls| (UrlNotificationsType::UPDATED, urls.to_vec()));
+        let deleted = self
+            .deleted
+            .chunks(MAX_BATCH_URLS)
+            .map(|urls| (UrlNotificationsType::DELETED, urls.to_vec()));
+        updated.chain(deleted).collect()
+    }
+}
+
+// loc ごとに 1 件にまとめる。位置は最初、lastmod は最新のものを使う
+fn latest(entries: &[SitemapEntry]) -> Vec<(String, Option<Timestamp>)> {
+    let mut merged: Vec<(String, Option<Timestamp>)> = Vec::with_capacity(entries.len());
+    let mut seen: HashMap<&str, usize> = HashMap::new();
+    for entry in entries {
+        match seen.get(entry.loc.as_str()) {
+            Some(&i) => merged[i].1 = merged[i].1.max(entry.lastmod),
+            None => {
+                seen.insert(entry.loc.as_str(), merged.len());
+                merged.push((entry.loc.clone(), entry.lastmod));
+            }
+        }
+    }
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use std::io::Write;
+
+    const URLSET: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<urlset xmlns="http://www.sitemaps.org/schemas/sitemap/0.9"
+        xmlns:image="http://www.google.com/schemas/sitemap-image/1.1">
+  <url>
+    <loc>https://example.com/jobs?id=1&amp;lang=ja</loc>
+    <lastmod>2026-07-01T09:30+09:00</lastmod>
+    <image:image><image:loc>https://example.com/logo.png</image:loc></image:image>
+  </url>
+  <url>
+    <loc><![CDATA[ https://example.com/jobs/2 ]]></loc>
+    <lastmod>2026-07</lastmod>
+  </url>
+  <url><loc>https://example.com/jobs/3</loc><lastmod>yesterday</lastmod></url>
+  <url><lastmod>2026-07-01</lastmod></url>
+</urlset>"#;
+
+    fn entry(loc: &str, lastmod: Option<&str>) -> SitemapEntry {
+        SitemapEntry {
+            loc: loc.to_string(),
+            lastmod: lastmod.map(|t| Timestamp::parse(t).unwrap()),
+        }
+    }
+
+    #[test]
+    fn test_parse_urlset() {
+        let sitemap = Sitemap::parse(URLSET.as_bytes()).unwrap();
+        assert!(!sitemap.is_index());
+        assert_eq!(
+            sitemap.entries(),
+            &[
+                entry(
+                    "https://example.com/jobs?id=1&lang=ja",
+                    Some("2026-07-01T00:30:00Z")
+                ),
+                entry("https://example.com/jobs/2", Some("2026-07-01T00:00:00Z")),
+                entry("https://example.com/jobs/3", None),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_gzipped_sitemapindex() {
+        let xml = r#"<sitemapindex xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">
+  <sitemap><loc>https://example.com/sitemap-jobs.xml.gz</loc><lastmod>2026-07-01T12:00:00Z</lastmod></sitemap>
+  <sitemap><loc>https://example.com/sitemap-pages.xml</loc></sitemap>
+</sitemapindex>"#;
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(xml.as_bytes()).unwrap();
+        let sitemap = Sitemap::parse(encoder.finish().unwrap().as_slice()).unwrap();
+        assert_eq!(
+            sitemap,
+            Sitemap::Index(vec![
+                entry(
+                    "https://example.com/sitemap-jobs.xml.gz",
+                    Some("2026-07-01T12:00:00Z")
+                ),
+                entry("https://example.com/sitemap-pages.xml", None),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_parse_rejects_malformed() {
+        for data in [
+            &b"<rss><channel/></rss>"[..],
+            b"<urlset><url><loc>https://example.com/</url></urlset>",
+            b"not xml",
+            &[0x1f, 0x8b, 0x08, 0x00, 0x01],
+        ] {
+            assert!(
+                matches!(
+                    Sitemap::parse(data),
+                    Err(GoogleApiError::MalformedSitemap(_))
+                ),
+                "{}",
+                String::from_utf8_lossy(data)
+            );
+        }
+        assert_eq!(
+            Sitemap::parse(b"<urlset/>").unwrap(),
+            Sitemap::UrlSet(Vec::new())
+        );
+    }
+
+    #[test]
+    fn test_parse_rejects_oversized() {
+        // 上限ちょうどの urlset の後ろに 1 バイト足す
+        let mut xml = b"<urlset>".to_vec();
+        xml.resize(MAX_SITEMAP_BYTES as usize - b"</urlset>".len(), b' ');
+        xml.extend_from_slice(b"</urlset>");
+        assert_eq!(
+            Sitemap::parse(xml.as_slice()).unwrap(),
+            Sitemap::UrlSet(Vec::new())
+        );
+        xml.push(b' ');
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::fast());
+        encoder.write_all(xml.as_slice()).unwrap();
+        let gzipped = encoder.finish().unwrap();
+        for data in [xml.as_slice(), gzipped.as_slice()] {
+            assert!(matches!(
+                Sitemap::parse(data),
+                Err(GoogleApiError::MalformedSitemap(message)) if message.contains("exceeds")
+            ));
+        }
+    }
+
+    #[test]
+    fn test_diff() {
+        let previous = vec![
+            entry("https://example.com/1", Some("2026-07-01T00:00:00Z")),
+            entry("https://example.com/2", Some("2026-07-01T00:00:00Z")),
+            entry("https://example.com/3", Some("2026-07-01T00:00:00Z")),
+            entry("https://example.com/4", None),
+        ];
+        let current = vec![
+            entry("https://example.com/5", None),
+            entry("https://example.com/1", Some("2026-07-01T00:00:00Z")),
+            entry("https://example.com/2", Some("2026-07-02T00:00:00Z")),
+            entry("https://example.com/4", None),
+            // 重複した URL は最新の lastmod で比べる
+            entry("https://example.com/1", Some("2026-07-03T00:00:00Z")),
+        ];
+        let plan = NotificationPlan::diff(&previous, &current);
+        assert_eq!(
+            plan.updated,
+            vec![
+                "https://example.com/5",
+                "https://example.com/1",
+                "https://example.com/2",
+            ]
+        );
+        assert_eq!(plan.deleted, vec!["https://example.com/3"]);
+        assert_eq!(plan.len(), 4);
+        assert!(NotificationPlan::diff(&current, &current).is_empty());
+
+        let since = NotificationPlan::modified_since(
+            &current,
+            Timestamp::parse("2026-07-01T00:00:00Z").unwrap(),
+        );
+        assert_eq!(
+            since.updated,
+            vec![
+                "https://example.com/5",
+                "https://example.com/1",
+                "https://example.com/2",
+                "https://example.com/4",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_batches_split_by_type_and_size() {
+        let plan = NotificationPlan {
+            updated: (0..150)
+                .map(|i| format!("https://example.com/{}", i))
+                .collect(),
+            deleted: vec!["https://example.com/old".to_string()],
+        };
+        let batches = plan.batches();
+        assert_eq!(
+            batches
+                .iter()
+                .map(|(url_type, urls)| (url_type.clone(), urls.len()))
+                .collect::<Vec<_>>(),
+            vec![
+                (UrlNotificationsType::UPDATED, 100),
+                (UrlNotificationsType::UPDATED, 50),
+                (UrlNotificationsType::DELETED, 1),
+            ]
+        );
+        assert_eq!(
+            plan.notifications().last(),
+            Some(&UrlNotification::deleted("https://example.com/old"))
+        );
+    }
+}
@@ -0,0 +1,452 @@
+use crate::client::{BatchFailure, IndexingClient};
+use crate::error::GoogleApiError;
+use crate::http::MAX_BATCH_URLS;
+use crate::timestamp::Timestamp;
+use crate::{
+    ResponseGoogleIndexingBatch, ResponseUrlNotification, ResponseUrlNotificationMetadata,
+    UrlNotification,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::SystemTime;
+
+/// A page and the time it last changed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PageUpdate {
+    pub url: String,
+    pub last_modified: SystemTime,
+}
+
+impl PageUpdate {
+    pub fn new(url: &str, last_modified: SystemTime) -> Self {
+        PageUpdate {
+            url: url.to_string(),
+            last_modified,
+        }
+    }
+}
+
+/// What Google last recorded for a URL, as kept in a [`MetadataCache`].
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CachedMetadata {
+    /// `latestUpdate.notifyTime`.
+    pub latest_update: Option<SystemTime>,
+    /// `latestRemove.notifyTime`.
+    pub latest_remove: Option<SystemTime>,
+}
+
+impl CachedMetadata {
+    /// Times that cannot be parsed are treated as missing.
+    pub fn from_metadata(metadata: &ResponseUrlNotificationMetadata) -> Self {
+        let time = |notification: &Option<ResponseUrlNotification>| {
+            notification
+                .as_ref()
+                .and_then(|n| n.notify_timestamp())
+                .map(SystemTime::from)
+        };
+        CachedMetadata {
+            latest_update: time(&metadata.latest_update),
+            latest_remove: time(&metadata.latest_remove),
+        }
+    }
+
+    /// Whether Google was notified of an update at or after `last_modified`, and the
+    /// URL was not removed after that. A removal at the same time as the update does
+    /// not count.
+    ///
+    /// [`ResponseUrlNotificationMetadata::is_updated_since`] gives the same answer.
+    pub fn is_up_to_date<T: Into<Timestamp>>(&self, last_modified: T) -> bool {
+        let last_modified = SystemTime::from(last_modified.into());
+        match self.latest_update {
+            Some(updated) => {
+                updated >= last_modified
+                    && self.latest_remove.is_none_or(|removed| removed <= updated)
+            }
+            None => false,
+        }
+    }
+}
+
+/// Local copy of the notification metadata of URLs, so [`SmartPublisher`] does not
+/// have to call `get_metadata` for URLs it already knows about.
+///
+/// It implements `Serialize` and `Deserialize`, so it can be saved between runs.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct MetadataCache {
+    entries: HashMap<String, CachedMetadata>,
+}
+
+impl MetadataCache {
+    pub fn new() -> Self {
+        MetadataCache::default()
+    }
+
+    pub fn get(&self, url: &str) -> Option<&CachedMetadata> {
+        self.entries.get(url)
+    }
+
+    pub fn insert(&mut self, url: &str, metadata: CachedMetadata) {
+        self.entries.insert(url.to_string(), metadata);
+    }
+
+    pub fn remove(&mut self, url: &str) -> Option<CachedMetadata> {
+        self.entries.remove(url)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+/// A URL [`SmartPublisher`] did not send because Google already has a newer update.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SkippedUrl {
+    pub url: String,
+    pub last_modified: SystemTime,
+    /// When Google was last notified of an update.
+    pub notified_at: SystemTime,
+}
+
+/// Result of [`SmartPublisher::publish`].
+#[derive(Debug, Default)]
+pub struct SmartPublishReport {
+    /// Results of the URLs that were sent.
+    pub sent: Vec<ResponseGoogleIndexingBatch>,
+    /// URLs that did not need a notification.
+    pub skipped: Vec<SkippedUrl>,
+    /// Batches that failed as a whole. Their URLs are not cached, so they are checked
+    /// and sent again next time.
+    pub failed: Vec<BatchFailure>,
+}
+
+/// Sends `UPDATED` only for pages that changed since Google was last notified.
+///
+/// A page is skipped when its `latestUpdate.notifyTime` is at or after the page's
+/// last modification (and the URL was not removed since). The metadata comes from the
+/// [`MetadataCache`], or from `batch_get_metadata` for URLs not in the cache. URLs
+/// that were never notified, or whose metadata cannot be fetched, are sent.
+/// ```rust
+/// use google_indexing_api::{IndexingClient, PageUpdate, SmartPublisher};
+/// use std::time::{Duration, SystemTime};
+/// async fn example_smart(client: &IndexingClient) {
+///     let mut publisher = SmartPublisher::new();
+///     let modified = SystemTime::now() - Duration::from_secs(3600);
+///     let report = publisher
+///         .publish(client, vec![PageUpdate::new("https://example.com/jobs/1", modified)])
+///         .await
+///         .unwrap();
+///     println!("{} sent, {} skipped", report.sent.len(), report.skipped.len());
+/// }
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct SmartPublisher {
+    cache: MetadataCache,
+}
+
+impl SmartPublisher {
+    pub fn new() -> Self {
+        SmartPublisher::default()
+    }
+
+    /// Starts from a cache saved by a previous run.
+    pub fn with_cache(cache: MetadataCache) -> Self {
+        SmartPublisher { cache }
+    }
+
+    pub fn cache(&self) -> &MetadataCache {
+        &self.cache
+    }
+
+    pub fn into_cache(self) -> MetadataCache {
+        self.cache
+    }
+
+    /// Looks up the pages missing from the cache, sends `UPDATED` for the ones that
+    /// need it, and records the new notification times in the cache.
+    ///
+    /// With a [`UrlValidator`](crate::UrlValidator) on the client, the URLs are normalized
+    /// first, and the cache and the report use the normalized URLs.
+    pub async fn publish(
+        &mut self,
+        client: &IndexingClient,
+        pages: Vec<PageUpdate>,
+    ) -> Result<SmartPublishReport, GoogleApiError> {
+        // キャッシュは結果と同じく正規化した URL で引く
+        let urls = client.normalize_each(pages.iter().map(|p| p.url.clone()).collect())?;
+        // 同じ URL は一番新しい更新日時だけを見る
+        let mut order = Vec::new();
+        let mut latest: HashMap<String, SystemTime> = HashMap::new();
+        for (url, page) in urls.into_iter().zip(pages) {
+            match latest.get_mut(&url) {
+                Some(last_modified) => *last_modified = (*last_modified).max(page.last_modified),
+                None => {
+                    order.push(url.clone());
+                    latest.insert(url, page.last_modified);
+                }
+            }
+        }
+
+        let missing = order
+            .iter()
+            .filter(|url| self.cache.get(url).is_none())
+            .cloned()
+            .collect::<Vec<_>>();
+        for chunk in missing.chunks(MAX_BATCH_URLS) {
+            let results = client.batch_get_metadata(chunk.to_vec()).await?;
+            for (url, result) in chunk.iter().zip(results) {
+                match result {
+                    Ok(metadata) => self
+                        .cache
+                        .insert(url, CachedMetadata::from_metadata(&metadata)),
+                    // 404 は一度も通知していない URL
+                    Err(GoogleApiError::HttpStatus { status: 404, .. }) => {
+                        self.cache.insert(url, CachedMetadata::default())
+                    }
+                    // 取得できなかったものはキャッシュせずに送る
+                    Err(_) => {}
+                }
+            }
+        }
+
+        let mut report = SmartPublishReport::default();
+        let mut notifications = Vec::new();
+        for url in order {
+            let last_modified = latest[&url];
+            match self.cache.get(&url) {
+                Some(cached) if cached.is_up_to_date(last_modified) => {
+                    report.skipped.push(SkippedUrl {
+                        notified_at: cached.latest_update.unwrap_or(last_modified),
+                        url,
+                        last_modified,
+                    })
+                }
+                _ => notifications.push(UrlNotification::updated(url.as_str())),
+            }
+        }
+        if notifications.is_empty() {
+            return Ok(report);
+        }
+
+        let sent = client.batch_all_notifications(notifications).await?;
+        for result in sent.results.iter() {
+            let Ok(response) = result.result() else {
+                continue;
+            };
+            let mut cached = self.cache.get(result.url()).cloned().unwrap_or_default();
+            let sent = CachedMetadata::from_metadata(&response.url_notification_metadata);
+            cached.latest_update = sent.latest_update.or(Some(SystemTime::now()));
+            if sent.latest_remove.is_some() {
+                cached.latest_remove = sent.latest_remove;
+            }
+            self.cache.insert(result.url(), cached);
+        }
+        report.sent = sent.results;
+        report.failed = sent.failed;
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::StaticTokenProvider;
+    use crate::test_support::{batch_response, client_for};
+    use crate::validate::UrlValidator;
+    use crate::UrlNotificationsType;
+    use httpmock::prelude::*;
+
+    fn at(value: &str) -> SystemTime {
+        Timestamp::parse(value).unwrap().into()
+    }
+
+    #[test]
+    fn test_is_up_to_date() {
+        let cached = CachedMetadata {
+            latest_update: Some(at("2026-10-18T10:00:00Z")),
+            latest_remove: None,
+        };
+        assert!(cached.is_up_to_date(at("2026-10-18T09:00:00Z")));
+        assert!(cached.is_up_to_date(at("2026-10-18T10:00:00Z")));
+        assert!(!cached.is_up_to_date(at("2026-10-18T10:00:01Z")));
+
+        // 更新の通知より後に削除されていれば送り直す
+        let removed = CachedMetadata {
+            latest_remove: Some(at("2026-10-18T11:00:00Z")),
+            ..cached
+        };
+        assert!(!removed.is_up_to_date(at("2026-10-18T09:00:00Z")));
+        assert!(!CachedMetadata::default().is_up_to_date(at("2026-10-18T09:00:00Z")));
+    }
+
+    #[test]
+    fn test_is_up_to_date_agrees_with_metadata() {
+        let notification = |url_type, notify_time: &str| {
+            Some(ResponseUrlNotification {
+                url: "https://example.com/1".to_string(),
+                url_type,
+                notify_time: notify_time.to_string(),
+            })
+        };
+        let mut metadata = ResponseUrlNotificationMetadata {
+            url: "https://example.com/1".to_string(),
+            latest_update: notification(UrlNotificationsType::UPDATED, "2026-10-18T10:00:00Z"),
+            // 同時刻の削除は更新より前とみなす
+            latest_remove: notification(UrlNotificationsType::DELETED, "2026-10-18T10:00:00Z"),
+        };
+        let check = |metadata: &ResponseUrlNotificationMetadata, time: &str| {
+            let cached = CachedMetadata::from_metadata(metadata).is_up_to_date(at(time));
+            assert_eq!(metadata.is_updated_since(at(time)), cached, "{}", time);
+            cached
+        };
+        assert!(check(&metadata, "2026-10-18T10:00:00Z"));
+        assert!(!check(&metadata, "2026-10-18T10:00:00.5Z"));
+        metadata.latest_update = notification(UrlNotificationsType::UPDATED, "not a time");
+        assert!(!check(&metadata, "2026-10-18T09:00:00Z"));
+        metadata.latest_update = None;
+        assert!(!check(&metadata, "2026-10-18T09:00:00Z"));
+    }
+
+    #[tokio::test]
+    async fn test_cache_is_keyed_by_normalized_url() {
+        // キャッシュだけで判断できるので、到達できない base_url でも検証できる
+        let client = IndexingClient::builder()
+            .base_url("http://127.0.0.1:1")
+            .token_provider(StaticTokenProvider::new("test-token"))
+            .url_validator(UrlValidator::new())
+            .build()
+            .unwrap();
+        let mut cache = MetadataCache::new();
+        cache.insert(
+            "https://example.com/1",
+            CachedMetadata {
+                latest_update: Some(at("2026-10-18T10:00:00Z")),
+                latest_remove: None,
+            },
+        );
+        let mut publisher = SmartPublisher::with_cache(cache);
+        let report = publisher
+            .publish(
+                &client,
+                vec![PageUpdate::new(
+                    "HTTPS://Example.com/1#apply",
+                    at("2026-10-18T09:00:00Z"),
+                )],
+            )
+            .await
+            .unwrap();
+        assert_eq!(report.skipped.len(), 1);
+        assert_eq!(report.skipped[0].url, "https://example.com/1");
+        assert!(report.sent.is_empty());
+        assert_eq!(publisher.cache().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_publish_skips_urls_already_notified() {
+        let server = MockServer::start_async().await;
+        let metadata = server
+            .mock_async(|when, then| {
+                when.method(POST)
+                    .path("/batch")
+                    .body_contains("GET /v3/urlNotifications/metadata");
+                then.status(200)
+                    .header("Content-Type", "multipart/mixed; boundary=batch_x")
+                    .body(batch_response(&[
+                        (
+                            "200 OK",
+                            r#"{"url":"https://example.com/1","latestUpdate":{"url":"https://example.com/1","type":"URL_UPDATED","notifyTime":"2026-10-18T10:00:00.5Z"}}"#,
+                        ),
+                        (
+                            "404 Not Found",
+                            r#"{"error":{"code":404,"message":"Requested entity was not found.","status":"NOT_FOUND"}}"#,
+                        ),
+                        (
+                            "200 OK",
+                            r#"{"url":"https://example.com/3","latestUpdate":{"url":"https://example.com/3","type":"URL_UPDATED","notifyTime":"2026-10-17T10:00:00Z"}}"#,
+                        ),
+                    ]));
+            })
+            .await;
+        let publish = server
+            .mock_async(|when, then| {
+                when.method(POST)
+                    .path("/batch")
+                    .body_contains("POST /v3/urlNotifications:publish")
+                    .body_contains("https://example.com/2")
+                    .body_contains("https://example.com/3")
+                    .matches(|req| {
+                        !String::from_utf8_lossy(req.body.as_deref().unwrap_or_default())
+                            .contains("https://example.com/1\"")
+                    });
+                then.status(200)
+                    .header("Content-Type", "multipart/mixed; boundary=batch_x")
+                    .body(batch_response(&[
+                        (
+                            "200 OK",
+                            r#"{"urlNotificationMetadata":{"url":"https://example.com/2","latestUpdate":{"url":"https://example.com/2","type":"URL_UPDATED","notifyTime":"2026-10-18T12:00:00Z"}}}"#,
+                        ),
+                        (
+                            "200 OK",
+                            r#"{"urlNotificationMetadata":{"url":"https://example.com/3","latestUpdate":{"url":"https://example.com/3","type":"URL_UPDATED","notifyTime":"2026-10-18T12:00:00Z"}}}"#,
+                        ),
+                    ]));
+            })
+            .await;
+        let client = client_for(&server);
+
+        let modified = at("2026-10-18T09:00:00Z");
+        let mut publisher = SmartPublisher::new();
+        let report = publisher
+            .publish(
+                &client,
+                vec![
+                    PageUpdate::new("https://example.com/1", modified),
+                    PageUpdate::new("https://example.com/2", modified),
+                    PageUpdate::new("https://example.com/3", modified),
+                ],
+            )
+            .await
+            .unwrap();
+        metadata.assert_async().await;
+        publish.assert_async().await;
+        assert_eq!(
+            report.skipped,
+            vec![SkippedUrl {
+                url: "https://example.com/1".to_string(),
+                last_modified: modified,
+                notified_at: at("2026-10-18T10:00:00.5Z"),
+            }]
+        );
+        assert_eq!(
+            report.sent.iter().map(|r| r.url()).collect::<Vec<_>>(),
+            vec!["https://example.com/2", "https://example.com/3"]
+        );
+
+        // 2 回目はキャッシュだけで判断するので何も送らない
+        let report = publisher
+            .publish(
+                &client,
+                vec![
+                    PageUpdate::new("https://example.com/2", modified),
+                    PageUpdate::new("https://example.com/3", modified),
+                ],
+            )
+            .await
+            .unwrap();
+        assert!(report.sent.is_empty());
+        assert_eq!(report.skipped.len(), 2);
+        assert_eq!(
+            publisher
+                .cache()
+                .get("https://example.com/3")
+                .unwrap()
+                .latest_update,
+            Some(at("2026-10-18T12:00:00Z"))
+        );
+        metadata.assert_hits_async(1).await;
+        publish.assert_hits_async(1).await;
+    }
+}
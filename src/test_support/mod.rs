@@ -0,0 +1,65 @@
+//! テスト用のモックレスポンスとクライアント。各モジュールのテストから使う
+use crate::auth::StaticTokenProvider;
+use crate::client::{IndexingClient, IndexingClientBuilder};
+use httpmock::MockServer;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// バッチレスポンスの index 番目 (1 始まり) の part
+pub(crate) fn part(index: usize, status: &str, body: &str) -> String {
+    format!(
+        "--batch_x\r\nContent-Type: application/http\r\nContent-ID: <response-b29c5de2-0db4-490b-b421-6a51b598bd23+{}>\r\n\r\nHTTP/1.1 {}\r\nContent-Type: application/json\r\n\r\n{}\r\n",
+        index, status, body
+    )
+}
+
+/// part を並べて閉じたバッチレスポンスの本文。boundary は `batch_x`
+pub(crate) fn batch_body<I>(parts: I) -> String
+where
+    I: IntoIterator<Item = String>,
+{
+    let mut body = parts.into_iter().collect::<String>();
+    body.push_str("--batch_x--\r\n");
+    body
+}
+
+/// (ステータス行, JSON) ごとに 1 つの part を持つバッチレスポンス
+pub(crate) fn batch_response(parts: &[(&str, &str)]) -> String {
+    batch_body(
+        parts
+            .iter()
+            .enumerate()
+            .map(|(i, (status, body))| part(i + 1, status, body)),
+    )
+}
+
+/// count 件とも `200 OK` で `{}` を返すバッチレスポンス
+pub(crate) fn batch_ok(count: usize) -> String {
+    batch_body((1..=count).map(|i| part(i, "200 OK", "{}")))
+}
+
+/// モックサーバーに向け、固定のトークンで送るビルダー
+pub(crate) fn client_builder(server: &MockServer) -> IndexingClientBuilder {
+    IndexingClient::builder()
+        .base_url(server.base_url().as_str())
+        .token_provider(StaticTokenProvider::new("test-token"))
+}
+
+pub(crate) fn client_for(server: &MockServer) -> IndexingClient {
+    client_builder(server).build().unwrap()
+}
+
+/// テストごとに別のディレクトリの中のパス。ディレクトリはまだ作らない
+pub(crate) fn temp_path(name: &str) -> PathBuf {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_nanos();
+    std::env::temp_dir()
+        .join(format!(
+            "google-indexing-api-{}-{}",
+            std::process::id(),
+            nanos
+        ))
+        .join(name)
+}
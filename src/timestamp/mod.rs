@@ -0,0 +1,449 @@
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt::{Display, Formatter};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// A point in time as returned by the API, e.g. `notifyTime`
+/// (`"2026-10-18T09:30:00.123456789Z"`), with nanosecond precision.
+///
+/// It needs no date library. With the `chrono` or `time` feature it converts to and
+/// from `chrono::DateTime<Utc>` or `time::OffsetDateTime`, and it always converts to
+/// and from [`SystemTime`]. It is serialized as an RFC 3339 string.
+/// ```rust
+/// use google_indexing_api::Timestamp;
+/// let updated = Timestamp::parse("2026-10-18T09:30:00.5Z").unwrap();
+/// let removed = Timestamp::parse("2026-10-18T18:30:00+09:00").unwrap();
+/// assert!(updated > removed);
+/// assert_eq!(updated.to_string(), "2026-10-18T09:30:00.5Z");
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Timestamp {
+    seconds: i64,
+    // 0..1_000_000_000。負の秒でも時刻は seconds + nanos
+    nanos: u32,
+}
+
+impl Timestamp {
+    /// `nanos` over one second carry into `seconds`.
+    pub fn from_unix(seconds: i64, nanos: u32) -> Self {
+        Timestamp {
+            seconds: seconds + (nanos / NANOS_PER_SECOND) as i64,
+            nanos: nanos % NANOS_PER_SECOND,
+        }
+    }
+
+    /// Parses an RFC 3339 timestamp such as `"2026-10-18T09:30:00.123456Z"` or
+    /// `"2026-10-18T18:30:00+09:00"`. Digits below nanoseconds are dropped.
+    pub fn parse(value: &str) -> Option<Timestamp> {
+        let bytes = value.as_bytes();
+        if bytes.len() < 20
+            || !value.is_char_boundary(19)
+            || bytes[4] != b'-'
+            || bytes[7] != b'-'
+            || !matches!(bytes[10], b'T' | b't')
+            || bytes[13] != b':'
+            || bytes[16] != b':'
+        {
+            return None;
+        }
+        let year = digits(&value[0..4])?;
+        let month = digits(&value[5..7])?;
+        let day = digits(&value[8..10])?;
+        let hour = digits(&value[11..13])?;
+        let minute = digits(&value[14..16])?;
+        let second = digits(&value[17..19])?;
+        if !(1..=12).contains(&month)
+            || day < 1
+            || day > days_in_month(year, month)
+            || hour > 23
+            || minute > 59
+            || second > 60
+        {
+            return None;
+        }
+
+        let mut rest = &value[19..];
+        let mut nanos = 0u32;
+        if let Some(fraction) = rest.strip_prefix('.') {
+            let len = fraction.bytes().take_while(|b| b.is_ascii_digit()).count();
+            if len == 0 {
+                return None;
+            }
+            for b in fraction.bytes().take(len.min(9)) {
+                nanos = nanos * 10 + (b - b'0') as u32;
+            }
+            nanos *= 10u32.pow(9usize.saturating_sub(len) as u32);
+            rest = &fraction[len..];
+        }
+        let offset = match rest.as_bytes() {
+            [b'Z' | b'z'] => 0,
+            [sign @ (b'+' | b'-'), _, _, b':', _, _] => {
+                let hours = digits(&rest[1..3])?;
+                let minutes = digits(&rest[4..6])?;
+                if hours > 23 || minutes > 59 {
+                    return None;
+                }
+                let offset = hours * 3_600 + minutes * 60;
+                if *sign == b'-' {
+                    -offset
+                } else {
+                    offset
+                }
+            }
+            _ => return None,
+        };
+
+        // うるう秒 (:60) は次の秒として扱う
+        let seconds =
+            days_from_civil(year, month, day) * DAY + hour * 3_600 + minute * 60 + second - offset;
+        Some(Timestamp { seconds, nanos })
+    }
+
+    /// Parses a W3C datetime, the ISO 8601 profile of sitemaps and schema.org:
+    /// `YYYY`, `YYYY-MM`, `YYYY-MM-DD`, or a date and time with or without seconds.
+    /// Missing parts are the start of the period, and a time without an offset is UTC.
+    /// ```rust
+    /// use google_indexing_api::Timestamp;
+    /// assert_eq!(
+    ///     Timestamp::parse_w3c("2026-07-01T09:30+09:00").unwrap().to_string(),
+    ///     "2026-07-01T00:30:00Z"
+    /// );
+    /// assert_eq!(
+    ///     Timestamp::parse_w3c("2026-07").unwrap().to_string(),
+    ///     "2026-07-01T00:00:00Z"
+    /// );
+    /// ```
+    pub fn parse_w3c(value: &str) -> Option<Timestamp> {
+        let value = value.trim();
+        if let Some(timestamp) = Timestamp::parse(value) {
+            return Some(timestamp);
+        }
+        let mut full = match value.len() {
+            4 => format!("{}-01-01T00:00:00Z", value),
+            7 => format!("{}-01T00:00:00Z", value),
+            10 => format!("{}T00:00:00Z", value),
+            _ => value.to_string(),
+        };
+        // 秒のない hh:mm
+        if full.len() >= 16 && full.is_char_boundary(16) && full.as_bytes().get(16) != Some(&b':') {
+            full.insert_str(16, ":00");
+        }
+        // オフセットのない時刻は UTC とみなす
+        if full
+            .get(19..)
+            .is_some_and(|rest| !rest.contains(['Z', 'z', '+', '-']))
+        {
+            full.push('Z');
+        }
+        Timestamp::parse(full.as_str())
+    }
+
+    /// Seconds since 1970-01-01T00:00:00Z, negative before it.
+    pub fn unix_seconds(&self) -> i64 {
+        self.seconds
+    }
+
+    /// Nanoseconds after [`unix_seconds`](Timestamp::unix_seconds), `0..1_000_000_000`.
+    pub fn nanos(&self) -> u32 {
+        self.nanos
+    }
+}
+
+const NANOS_PER_SECOND: u32 = 1_000_000_000;
+const DAY: i64 = 86_400;
+
+impl Display for Timestamp {
+    /// RFC 3339 in UTC, with as many fraction digits as needed.
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let (year, month, day) = civil_from_days(self.seconds.div_euclid(DAY));
+        let time = self.seconds.rem_euclid(DAY);
+        write!(
+            f,
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+            year,
+            month,
+            day,
+            time / 3_600,
+            time / 60 % 60,
+            time % 60
+        )?;
+        if self.nanos > 0 {
+            let fraction = format!("{:09}", self.nanos);
+            write!(f, ".{}", fraction.trim_end_matches('0'))?;
+        }
+        f.write_str("Z")
+    }
+}
+
+impl From<SystemTime> for Timestamp {
+    fn from(time: SystemTime) -> Self {
+        match time.duration_since(UNIX_EPOCH) {
+            Ok(d) => Timestamp::from_unix(d.as_secs() as i64, d.subsec_nanos()),
+            Err(e) => {
+                let d = e.duration();
+                match d.subsec_nanos() {
+                    0 => Timestamp::from_unix(-(d.as_secs() as i64), 0),
+                    nanos => {
+                        Timestamp::from_unix(-(d.as_secs() as i64) - 1, NANOS_PER_SECOND - nanos)
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl From<Timestamp> for SystemTime {
+    fn from(time: Timestamp) -> Self {
+        let nanos = Duration::from_nanos(time.nanos as u64);
+        if time.seconds >= 0 {
+            UNIX_EPOCH + Duration::from_secs(time.seconds as u64) + nanos
+        } else {
+            UNIX_EPOCH - Duration::from_secs(time.seconds.unsigned_abs()) + nanos
+        }
+    }
+}
+
+impl Serialize for Timestamp {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Timestamp {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = String::deserialize(deserializer)?;
+        Timestamp::parse(value.as_str()).ok_or_else(|| {
+            serde::de::Error::custom(format!("invalid RFC 3339 timestamp: {}", value))
+        })
+    }
+}
+
+#[cfg(feature = "chrono")]
+impl From<Timestamp> for chrono::DateTime<chrono::Utc> {
+    fn from(time: Timestamp) -> Self {
+        // chrono の範囲 (およそ ±26 万年) は RFC 3339 の 4 桁の年より広い
+        chrono::DateTime::from_timestamp(time.seconds, time.nanos).unwrap_or_default()
+    }
+}
+
+#[cfg(feature = "chrono")]
+impl<Tz: chrono::TimeZone> From<chrono::DateTime<Tz>> for Timestamp {
+    fn from(time: chrono::DateTime<Tz>) -> Self {
+        // うるう秒は nanos が 10 億以上になるので次の秒に繰り上がる
+        Timestamp::from_unix(time.timestamp(), time.timestamp_subsec_nanos())
+    }
+}
+
+#[cfg(feature = "time")]
+impl From<Timestamp> for time::OffsetDateTime {
+    fn from(time: Timestamp) -> Self {
+        let nanos = time.seconds as i128 * NANOS_PER_SECOND as i128 + time.nanos as i128;
+        time::OffsetDateTime::from_unix_timestamp_nanos(nanos)
+            .unwrap_or(time::OffsetDateTime::UNIX_EPOCH)
+    }
+}
+
+#[cfg(feature = "time")]
+impl From<time::OffsetDateTime> for Timestamp {
+    fn from(time: time::OffsetDateTime) -> Self {
+        Timestamp::from_unix(time.unix_timestamp(), time.nanosecond())
+    }
+}
+
+fn digits(value: &str) -> Option<i64> {
+    if !value.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    value.parse().ok()
+}
+
+fn days_in_month(year: i64, month: i64) -> i64 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+// 1970-01-01 からの日数
+pub(crate) fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year - era * 400;
+    let mp = (month + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+// days_from_civil の逆。(年, 月, 日)
+pub(crate) fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400;
+    (if month <= 2 { year + 1 } else { year }, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ResponseUrlNotification, ResponseUrlNotificationMetadata, UrlNotificationsType};
+
+    #[test]
+    fn test_civil_days_round_trip() {
+        assert_eq!(days_from_civil(1970, 1, 1), 0);
+        assert_eq!(days_from_civil(2000, 3, 1), 11_017);
+        for days in [-1, 0, 59, 11_016, 20_744, 40_000] {
+            let (year, month, day) = civil_from_days(days);
+            assert_eq!(days_from_civil(year, month, day), days);
+        }
+    }
+
+    #[test]
+    fn test_parse() {
+        let at = |seconds: i64, nanos: u32| Some(Timestamp::from_unix(seconds, nanos));
+        assert_eq!(Timestamp::parse("1970-01-01T00:00:00Z"), at(0, 0));
+        assert_eq!(
+            Timestamp::parse("2026-10-18T09:30:00.123456Z"),
+            at(1_792_315_800, 123_456_000)
+        );
+        assert_eq!(
+            Timestamp::parse("2026-10-18T18:30:00+09:00"),
+            at(1_792_315_800, 0)
+        );
+        assert_eq!(
+            Timestamp::parse("2026-10-18T02:30:00.1234567891-07:00"),
+            at(1_792_315_800, 123_456_789)
+        );
+        assert_eq!(
+            Timestamp::parse("1969-12-31T23:59:59.25Z"),
+            at(-1, 250_000_000)
+        );
+        for invalid in [
+            "",
+            "2026-10-18",
+            "2026-10-18 09:30:00Z",
+            "2026-02-29T00:00:00Z",
+            "2026-10-18T24:00:00Z",
+            "2026-10-18T09:30:00",
+            "2026-10-18T09:30:00.Z",
+            "2026-10-18T09:30:00+0900",
+            "2026-10-18T09:30:0\u{e9}Z",
+        ] {
+            assert_eq!(Timestamp::parse(invalid), None, "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_parse_w3c() {
+        let at = |seconds: i64| Some(Timestamp::from_unix(seconds, 0));
+        for (value, expected) in [
+            ("2026-10-18T09:30:00Z", at(1_792_315_800)),
+            ("2026-10-18T18:30+09:00", at(1_792_315_800)),
+            ("2026-10-18T09:30", at(1_792_315_800)),
+            (" 2026-10-18T09:30:00 ", at(1_792_315_800)),
+            ("2026-10-18", at(1_792_281_600)),
+            ("2026-10", at(1_790_812_800)),
+            ("2026", at(1_767_225_600)),
+            ("2026-10-18 09:30", None),
+            ("2026-13", None),
+            ("yesterday", None),
+        ] {
+            assert_eq!(Timestamp::parse_w3c(value), expected, "{}", value);
+        }
+    }
+
+    #[test]
+    fn test_display_and_serde() {
+        let time = Timestamp::parse("2026-10-18T18:30:00.120+09:00").unwrap();
+        assert_eq!(time.to_string(), "2026-10-18T09:30:00.12Z");
+        assert_eq!(
+            Timestamp::from_unix(-1, 0).to_string(),
+            "1969-12-31T23:59:59Z"
+        );
+        let json = serde_json::to_string(&time).unwrap();
+        assert_eq!(json, r#""2026-10-18T09:30:00.12Z""#);
+        assert_eq!(serde_json::from_str::<Timestamp>(&json).unwrap(), time);
+        assert!(serde_json::from_str::<Timestamp>(r#""yesterday""#).is_err());
+    }
+
+    #[test]
+    fn test_system_time_round_trip() {
+        for (seconds, nanos) in [(0, 0), (1_792_315_800, 5), (-1, 250_000_000), (-2, 0)] {
+            let time = Timestamp::from_unix(seconds, nanos);
+            assert_eq!(Timestamp::from(SystemTime::from(time)), time);
+        }
+        assert_eq!(
+            SystemTime::from(Timestamp::from_unix(-1, 250_000_000)),
+            UNIX_EPOCH - Duration::from_millis(750)
+        );
+    }
+
+    #[test]
+    fn test_metadata_helpers() {
+        let notification = |url_type, notify_time: &str| {
+            Some(ResponseUrlNotification {
+                url: "https://example.com/1".to_string(),
+                url_type,
+                notify_time: notify_time.to_string(),
+            })
+        };
+        let mut metadata = ResponseUrlNotificationMetadata {
+            url: "https://example.com/1".to_string(),
+            latest_update: notification(
+                UrlNotificationsType::UPDATED,
+                "2026-10-18T09:30:00.000000002Z",
+            ),
+            latest_remove: notification(
+                UrlNotificationsType::DELETED,
+                "2026-10-18T09:30:00.000000001Z",
+            ),
+        };
+        assert_eq!(
+            metadata.last_notification().unwrap().url_type,
+            UrlNotificationsType::UPDATED
+        );
+        assert!(!metadata.is_currently_deleted());
+        let updated = Timestamp::parse("2026-10-18T09:30:00Z").unwrap();
+        assert!(metadata.is_updated_since(updated));
+        assert!(!metadata.is_updated_since(Timestamp::from_unix(updated.unix_seconds() + 1, 0)));
+        assert!(metadata.is_updated_since(UNIX_EPOCH));
+
+        // 削除の方が新しい
+        metadata.latest_remove =
+            notification(UrlNotificationsType::DELETED, "2026-10-19T00:00:00Z");
+        assert!(metadata.is_currently_deleted());
+        assert!(!metadata.is_updated_since(updated));
+
+        metadata.latest_update = None;
+        assert!(metadata.is_currently_deleted());
+        metadata.latest_remove = None;
+        assert!(metadata.last_notification().is_none());
+        assert!(!metadata.is_currently_deleted());
+    }
+
+    #[cfg(feature = "chrono")]
+    #[test]
+    fn test_chrono() {
+        let time = Timestamp::parse("2026-10-18T09:30:00.123456789Z").unwrap();
+        let chrono: chrono::DateTime<chrono::Utc> = time.into();
+        assert_eq!(chrono.to_rfc3339(), "2026-10-18T09:30:00.123456789+00:00");
+        assert_eq!(Timestamp::from(chrono), time);
+    }
+
+    #[cfg(feature = "time")]
+    #[test]
+    fn test_time() {
+        let time = Timestamp::parse("2026-10-18T09:30:00.123456789Z").unwrap();
+        let offset: time::OffsetDateTime = time.into();
+        assert_eq!(offset.unix_timestamp(), 1_792_315_800);
+        assert_eq!(offset.nanosecond(), 123_456_789);
+        assert_eq!(Timestamp::from(offset), time);
+    }
+}
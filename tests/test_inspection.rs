@@ -28,8 +28,8 @@ async fn test_sitemaps() {
         .batch(
             token.token().unwrap(),
             vec![
-                "http://example.com/widgets/1".to_string(),
-                "http://example.com/widgets/2".to_string(),
+                "https://example.com/widgets/1".to_string(),
+                "https://example.com/widgets/2".to_string(),
             ],
             UrlNotificationsType::UPDATED,
         )